@@ -1,2 +1,6 @@
+pub mod distribution;
 pub mod gamma_exposure;
+pub mod implied_volatility;
 pub mod option_stats;
+pub mod vanna_exposure;
+pub mod vol_surface;
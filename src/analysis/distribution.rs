@@ -0,0 +1,226 @@
+use crate::{
+    math::{bs, pricing::PricingConfig},
+    types::{DensityPoint, ImpliedDistribution, OptionSnapshot, Percentile, VolSlice, VolSurface},
+};
+
+/// Prices at which the density is evaluated, spread evenly in log moneyness
+const DENSITY_POINTS: usize = 401;
+/// How many at the money standard deviations the prices reach either side
+/// of the forward
+const RANGE_DEVIATIONS: f64 = 8.0;
+const PERCENTILES: [f64; 9] = [0.01, 0.05, 0.1, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99];
+
+/// The implied distribution of every expiration of `snapshot` the
+/// [`VolSurface`] fits a smile to.
+pub fn implied_distributions(
+    snapshot: &OptionSnapshot,
+    pricing: &PricingConfig,
+) -> anyhow::Result<Vec<ImpliedDistribution>> {
+    let surface = VolSurface::new(snapshot, pricing)?;
+    Ok(surface
+        .slices
+        .iter()
+        .filter_map(ImpliedDistribution::new)
+        .collect())
+}
+
+impl ImpliedDistribution {
+    /// The density of Breeden and Litzenberger (1978), the second derivative
+    /// of the undiscounted call price by strike, taken from calls priced at
+    /// the volatilities of the fitted smile so quotes don't add noise. Any
+    /// negative density from arbitrage in the smile is taken as zero, and
+    /// the density is scaled to sum to one over the prices it covers.
+    /// `None` for a smile without any variance at the money.
+    pub fn new(slice: &VolSlice) -> Option<Self> {
+        let (forward, t) = (slice.forward, slice.time_to_expiry);
+        let deviation = Some(slice.parameters.total_variance(0.0).sqrt())
+            .filter(|deviation| *deviation > 0.0)?;
+
+        let step = 2.0 * RANGE_DEVIATIONS * deviation / (DENSITY_POINTS - 1) as f64;
+        let calls: Vec<(f64, f64)> = (0..DENSITY_POINTS)
+            .map(|i| {
+                let k = -RANGE_DEVIATIONS * deviation + step * i as f64;
+                let strike = forward * k.exp();
+                let sigma = (slice.parameters.total_variance(k).max(0.0) / t).sqrt();
+                (
+                    strike,
+                    bs::call_price(sigma, t, 0.0, forward, strike, 0.0, 0.0),
+                )
+            })
+            .collect();
+
+        // Second differences on the uneven strikes, none at the two ends
+        let mut density = vec![0.0; DENSITY_POINTS];
+        for (density, calls) in density[1..].iter_mut().zip(calls.windows(3)) {
+            let [(k0, c0), (k1, c1), (k2, c2)] = [calls[0], calls[1], calls[2]];
+            let curvature = 2.0 * ((c2 - c1) / (k2 - k1) - (c1 - c0) / (k1 - k0)) / (k2 - k0);
+            *density = curvature.max(0.0);
+        }
+
+        // Trapezoids between neighbouring prices
+        let mut cumulative = vec![0.0; DENSITY_POINTS];
+        let mut moved = 0.0;
+        for i in 1..DENSITY_POINTS {
+            let (k0, k1) = (calls[i - 1].0, calls[i].0);
+            let mass = (density[i - 1] + density[i]) / 2.0 * (k1 - k0);
+            cumulative[i] = cumulative[i - 1] + mass;
+            moved += ((k0 + k1) / 2.0 - forward).abs() * mass;
+        }
+        let total = Some(cumulative[DENSITY_POINTS - 1]).filter(|total| *total > 0.0)?;
+
+        let density: Vec<DensityPoint> = calls
+            .iter()
+            .zip(density)
+            .zip(cumulative)
+            .map(|((&(price, _), density), cumulative)| DensityPoint {
+                price,
+                density: density / total,
+                probability_below: cumulative / total,
+            })
+            .collect();
+        let percentiles = PERCENTILES
+            .iter()
+            .map(|&probability| Percentile {
+                probability,
+                price: quantile(&density, probability),
+            })
+            .collect();
+
+        Some(Self {
+            expiration_date: slice.expiration_date.clone(),
+            time_to_expiry: t,
+            forward,
+            expected_move: moved / total,
+            percentiles,
+            density,
+        })
+    }
+
+    /// The probability of the underlying finishing below `level`.
+    pub fn cumulative_probability(&self, level: f64) -> f64 {
+        let after = self.density.partition_point(|point| point.price < level);
+        match (after.checked_sub(1), self.density.get(after)) {
+            (Some(before), Some(after)) => {
+                let before = &self.density[before];
+                let weight = (level - before.price) / (after.price - before.price);
+                before.probability_below
+                    + weight * (after.probability_below - before.probability_below)
+            }
+            (None, _) => 0.0,
+            (_, None) => 1.0,
+        }
+    }
+}
+
+/// The price below which the underlying finishes with `probability`,
+/// interpolated between the points of `density`.
+fn quantile(density: &[DensityPoint], probability: f64) -> f64 {
+    let after = density.partition_point(|point| point.probability_below < probability);
+    match (after.checked_sub(1), density.get(after)) {
+        (Some(before), Some(after)) => {
+            let before = &density[before];
+            let weight = (probability - before.probability_below)
+                / (after.probability_below - before.probability_below);
+            before.price + weight * (after.price - before.price)
+        }
+        (None, _) => density.first().map_or(0.0, |point| point.price),
+        (_, None) => density.last().map_or(0.0, |point| point.price),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math::standard_normal_cdf, types::SviParameters};
+
+    /// A flat smile, under which prices at expiration are lognormal.
+    fn flat_slice(sigma: f64, t: f64, forward: f64) -> VolSlice {
+        VolSlice {
+            expiration_date: "2021-06-18".to_string(),
+            time_to_expiry: t,
+            forward,
+            parameters: SviParameters {
+                a: sigma * sigma * t,
+                b: 0.0,
+                rho: 0.0,
+                m: 0.0,
+                sigma: 0.1,
+            },
+            fit_error: 0.0,
+            points: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lognormal_distribution() {
+        let (sigma, t, forward) = (0.25, 0.5f64, 4000.0);
+        let deviation = sigma * t.sqrt();
+        let distribution = ImpliedDistribution::new(&flat_slice(sigma, t, forward)).unwrap();
+
+        // The log of the price is normal with mean -deviation^2 / 2
+        let median = forward * (-deviation * deviation / 2.0).exp();
+        for (probability, z) in [
+            (0.05, -1.644_853_626_951_472),
+            (0.5, 0.0),
+            (0.99, 2.326_347_874_040_841),
+        ] {
+            let percentile = distribution
+                .percentiles
+                .iter()
+                .find(|percentile| percentile.probability == probability)
+                .unwrap();
+            let expected = median * (z * deviation).exp();
+            assert!(
+                (percentile.price / expected - 1.0).abs() < 1e-3,
+                "{}",
+                probability
+            );
+        }
+
+        let below = distribution.cumulative_probability(forward);
+        assert!((below - standard_normal_cdf(deviation / 2.0)).abs() < 1e-4);
+        assert_eq!(distribution.cumulative_probability(0.0), 0.0);
+        assert_eq!(distribution.cumulative_probability(1e9), 1.0);
+
+        // The straddle at the forward
+        let straddle = 2.0 * bs::call_price(sigma, t, 0.0, forward, forward, 0.0, 0.0);
+        assert!((distribution.expected_move / straddle - 1.0).abs() < 1e-3);
+
+        let mean: f64 = distribution
+            .density
+            .windows(2)
+            .map(|points| {
+                let (p0, p1) = (&points[0], &points[1]);
+                (p0.price * p0.density + p1.price * p1.density) / 2.0 * (p1.price - p0.price)
+            })
+            .sum();
+        assert!((mean / forward - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn skewed_distribution() {
+        let mut slice = flat_slice(0.2, 0.25, 100.0);
+        slice.parameters = SviParameters {
+            a: 0.005,
+            b: 0.05,
+            rho: -0.7,
+            m: 0.0,
+            sigma: 0.1,
+        };
+        let distribution = ImpliedDistribution::new(&slice).unwrap();
+
+        // Put skew fattens the left tail beyond the lognormal one
+        let deviation = slice.parameters.total_variance(0.0).sqrt();
+        let lognormal =
+            ImpliedDistribution::new(&flat_slice(deviation / 0.5, 0.25, 100.0)).unwrap();
+        assert!(distribution.cumulative_probability(80.0) > lognormal.cumulative_probability(80.0));
+        assert!(distribution
+            .density
+            .windows(2)
+            .all(|points| points[0].probability_below <= points[1].probability_below));
+
+        slice.parameters.a = 0.0;
+        slice.parameters.b = 0.0;
+        assert!(ImpliedDistribution::new(&slice).is_none());
+    }
+}
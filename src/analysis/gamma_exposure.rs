@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 
-use chrono::{Date, FixedOffset, Local, TimeZone};
+use chrono::{DateTime, Utc};
 
 use crate::{
-    math::bs::gamma,
+    math::{batch, pricing::PricingConfig},
     types::{
         gex::{GammaExposure, GammaExposureStats},
-        OptionInfo, OptionType,
+        OptionInfo, OptionType, VolSurface,
     },
 };
 
@@ -103,7 +103,11 @@ impl GammaExposure {
     }
 }
 
-pub fn gamma_exposure_by_price(option_chain: &[OptionInfo]) -> BTreeMap<String, f64> {
+/// Exposure of every strike per standard contract, so futures chains are
+/// comparable with equity and index chains.
+pub fn gamma_exposure_by_price(
+    option_chain: &[OptionInfo],
+) -> anyhow::Result<BTreeMap<String, f64>> {
     let mut strike_to_gamma_exposure: BTreeMap<String, f64> = BTreeMap::new();
 
     for option in option_chain {
@@ -111,7 +115,7 @@ pub fn gamma_exposure_by_price(option_chain: &[OptionInfo]) -> BTreeMap<String,
         let mut exposure = if !(-1.0..=1.0).contains(&option.gamma()) {
             0.0
         } else {
-            option.gamma() * option.open_interest as f64
+            option.gamma() * option.standard_contracts()?
         };
         if option.option_type == OptionType::Put {
             exposure *= -1.0;
@@ -124,24 +128,120 @@ pub fn gamma_exposure_by_price(option_chain: &[OptionInfo]) -> BTreeMap<String,
         }
     }
 
-    strike_to_gamma_exposure
+    Ok(strike_to_gamma_exposure)
 }
 
 pub fn gamma_exposure(
     symbol: &str,
     option_chain: &[OptionInfo],
 ) -> anyhow::Result<GammaExposureStats> {
-    let strike_to_gamma_exposure = gamma_exposure_by_price(option_chain);
+    let strike_to_gamma_exposure = gamma_exposure_by_price(option_chain)?;
     GammaExposureStats::new(symbol, &strike_to_gamma_exposure)
 }
 
+/// Gamma exposure of the whole chain across a range of prices, with every
+/// option's time to expiry measured from `now`. Volatilities come from
+/// `surface` when there is one, and otherwise from each option's `mid_iv`.
 pub fn gamma_exposure_aggregate(
     symbol: &str,
     option_chain: &[OptionInfo],
+    now: DateTime<Utc>,
+    pricing: &PricingConfig,
+    surface: Option<&VolSurface>,
 ) -> anyhow::Result<GammaExposureStats> {
-    let now = Local::now().date();
-    let mut strike_to_gamma_exposure_aggregate: BTreeMap<String, f64> = BTreeMap::new();
+    let prices = price_grid(option_chain);
+
+    let mut contracts = Vec::new();
+    let mut american = Vec::new();
+    for priced in priced_options(option_chain, now, pricing, surface)? {
+        let PricedOption {
+            option,
+            sigma,
+            time_to_expiry,
+            contracts_held,
+        } = priced;
+        match pricing.contract(symbol, option, sigma, time_to_expiry) {
+            Some(contract) => contracts.push((contract, contracts_held)),
+            None => {
+                // The European contract only bounds the prices worth a tree
+                let european = pricing.european_contract(symbol, option, sigma, time_to_expiry);
+                american.push(((option, sigma, time_to_expiry, european), contracts_held))
+            }
+        }
+    }
+
+    let mut exposures = batch::gamma_exposure(&contracts, &prices, valid_gamma);
+    if !american.is_empty() {
+        let american = batch::exposure_within(
+            &american,
+            &prices,
+            |(_, _, _, european)| european.support(),
+            |(option, sigma, t, _), price| {
+                valid_gamma(pricing.gamma(symbol, option, *sigma, *t, price))
+            },
+        );
+        for (exposure, american) in exposures.iter_mut().zip(american) {
+            *exposure += american;
+        }
+    }
+
+    let strike_to_gamma_exposure_aggregate: BTreeMap<String, f64> = prices
+        .iter()
+        .map(|price| price.to_string())
+        .zip(exposures)
+        .collect();
+
+    GammaExposureStats::new(symbol, &strike_to_gamma_exposure_aggregate)
+}
+
+/// An option of a chain ready to be aggregated.
+pub struct PricedOption<'a> {
+    pub option: &'a OptionInfo,
+    pub sigma: f64,
+    pub time_to_expiry: f64,
+    /// Standard contracts dealers hold, short for puts
+    pub contracts_held: f64,
+}
+
+/// Every option of `option_chain` with time left and a volatility, from
+/// `surface` when there is one and otherwise its own `mid_iv`. Options
+/// without a volatility have no meaningful greeks anywhere. Dealers are
+/// taken to be long calls and short puts.
+pub fn priced_options<'a>(
+    option_chain: &'a [OptionInfo],
+    now: DateTime<Utc>,
+    pricing: &PricingConfig,
+    surface: Option<&VolSurface>,
+) -> anyhow::Result<Vec<PricedOption<'a>>> {
+    let mut result = Vec::new();
+    for option in option_chain {
+        let time_to_expiry = option.time_to_expiry(now, pricing.day_count)?;
+        let sigma = match surface {
+            Some(surface) => surface.volatility(option.strike, time_to_expiry),
+            None => option.mid_iv,
+        };
+        let sigma = match sigma.filter(|sigma| *sigma > 0.0) {
+            Some(sigma) if time_to_expiry > 0.0 => sigma,
+            _ => continue,
+        };
 
+        let mut contracts_held = option.standard_contracts()?;
+        if option.option_type == OptionType::Put {
+            contracts_held *= -1.0;
+        }
+        result.push(PricedOption {
+            option,
+            sigma,
+            time_to_expiry,
+            contracts_held,
+        });
+    }
+    Ok(result)
+}
+
+/// Prices every half point from the lowest strike of the chain to the
+/// highest.
+pub fn price_grid(option_chain: &[OptionInfo]) -> Vec<f64> {
     let min_price = option_chain
         .iter()
         .map(|o| o.strike)
@@ -155,59 +255,79 @@ pub fn gamma_exposure_aggregate(
         .unwrap_or(0.0);
 
     let price_offset = 0.5;
+    let mut prices = Vec::new();
+    let mut price = min_price.floor();
+    while price <= max_price {
+        prices.push(price);
+        price += price_offset;
+    }
+    prices
+}
 
-    for option in option_chain {
-        let expiration_date = parse_date(&option.expiration_date)?;
-        let days_remaining = expiration_date.signed_duration_since(now).num_days();
+/// Gamma where the model gives a usable one, and none where it breaks down
+/// far from the money or right at expiry.
+fn valid_gamma(gamma: f64) -> f64 {
+    if (-1.0..=1.0).contains(&gamma) {
+        gamma
+    } else {
+        0.0
+    }
+}
 
-        let sigma = option.mid_iv.unwrap_or(0.0);
-        let expiration_time = days_remaining as f64 / 365.0;
-        let current_time = 0.0;
-        let strike = option.strike;
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
 
-        let mut price = min_price.floor();
-        while price <= max_price {
-            let price_string = price.to_string();
-            let gamma = gamma(sigma, expiration_time, current_time, price, strike);
+    use super::*;
+    use crate::math::{pricing::PricingModel, rates::RateCurve};
 
-            let mut exposure = if !(-1.0..=1.0).contains(&gamma) || gamma.is_nan() {
-                0.0
-            } else {
-                gamma * option.open_interest as f64
-            };
-            if option.option_type == OptionType::Put {
-                exposure *= -1.0;
-            }
-            match strike_to_gamma_exposure_aggregate.get_mut(&price_string) {
-                Some(exp) => *exp += exposure,
-                None => {
-                    strike_to_gamma_exposure_aggregate.insert(price_string.clone(), exposure);
-                }
-            }
+    #[test]
+    fn american_gamma_exposure() {
+        let now = Utc::now();
+        let pricing = PricingConfig {
+            rates: RateCurve::Flat(0.03),
+            model: PricingModel::Binomial,
+            ..PricingConfig::default()
+        };
 
-            price += price_offset;
-        }
-    }
+        let mut put = OptionInfo::test();
+        put.option_type = OptionType::Put;
+        put.strike = 100.0;
+        put.expiration_date = (now + Duration::days(7)).format("%Y-%m-%d").to_string();
+        put.mid_iv = Some(0.3);
+        let mut low = put.clone();
+        low.option_type = OptionType::Call;
+        low.strike = 60.0;
+        low.mid_iv = Some(0.2);
+        let mut high = low.clone();
+        high.strike = 140.0;
 
-    GammaExposureStats::new(symbol, &strike_to_gamma_exposure_aggregate)
-}
+        let chain = [put, low, high];
+        let stats = gamma_exposure_aggregate("TST", &chain, now, &pricing, None).unwrap();
+        assert_eq!(stats.prices.len(), 161);
 
-fn parse_date(date: &str) -> anyhow::Result<Date<FixedOffset>> {
-    let mut split_date = date.split('-');
-
-    let y = split_date
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Invalid year"))?
-        .parse()?;
-    let m = split_date
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Invalid month"))?
-        .parse()?;
-    let d = split_date
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Invalid day"))?
-        .parse()?;
-
-    let ny_offset = FixedOffset::east(5 * 3600);
-    Ok(ny_offset.ymd(y, m, d))
+        // Skipping prices far from each strike only drops negligible gamma
+        for exposure in &stats.prices {
+            let price: f64 = exposure.strike.parse().unwrap();
+            let expected: f64 = chain
+                .iter()
+                .map(|option| {
+                    let t = option.time_to_expiry(now, pricing.day_count).unwrap();
+                    let sign = match option.option_type {
+                        OptionType::Call => 2.0,
+                        OptionType::Put => -2.0,
+                    };
+                    let gamma = pricing.gamma("TST", option, option.mid_iv.unwrap(), t, price);
+                    sign * valid_gamma(gamma)
+                })
+                .sum();
+            assert!(
+                (exposure.gamma_exposure - expected).abs() < 1e-9,
+                "{} {} {}",
+                price,
+                exposure.gamma_exposure,
+                expected
+            );
+        }
+    }
 }
@@ -0,0 +1,77 @@
+use crate::{
+    data_apis::DataError,
+    math::pricing::PricingConfig,
+    types::{ImpliedVolatility, OptionSnapshot},
+};
+
+/// Solves the implied volatilities of every unexpired option in `snapshot`
+/// from its quotes, against the underlying price and time of the snapshot.
+pub fn implied_volatilities(
+    snapshot: &OptionSnapshot,
+    pricing: &PricingConfig,
+) -> anyhow::Result<Vec<ImpliedVolatility>> {
+    let spot = snapshot.spot().ok_or_else(|| {
+        DataError::MissingData(format!("No underlying price for {}", snapshot.symbol))
+    })?;
+
+    let mut result = Vec::new();
+    for option in &snapshot.options {
+        let time_to_expiry = option.time_to_expiry(snapshot.timestamp, pricing.day_count)?;
+        if time_to_expiry <= 0.0 {
+            continue;
+        }
+
+        let implied = pricing.quote_volatility(&snapshot.symbol, option, time_to_expiry, spot);
+        result.push(ImpliedVolatility {
+            symbol: option.symbol.clone(),
+            option_type: option.option_type,
+            strike: option.strike,
+            expiration_date: option.expiration_date.clone(),
+            bid_iv: implied.bid,
+            mid_iv: implied.mid,
+            ask_iv: implied.ask,
+            reported_mid_iv: option.mid_iv,
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{math::bs, types::OptionInfo};
+
+    #[test]
+    fn snapshot_volatilities() {
+        let now = Utc::now();
+        let mut snapshot = OptionSnapshot::test();
+        snapshot.timestamp = now;
+
+        let mut option = OptionInfo::test();
+        option.strike = 9.0;
+        option.expiration_date = (now + Duration::days(30)).format("%Y-%m-%d").to_string();
+        let t = option.time_to_expiry(now, Default::default()).unwrap();
+        let mid = bs::call_price(0.4, t, 0.0, 10.0, 9.0, 0.0, 0.0);
+        option.bid = Some(mid - 0.01);
+        option.ask = Some(mid + 0.01);
+
+        let mut expired = option.clone();
+        expired.expiration_date = "2021-06-18".to_string();
+        snapshot.options = vec![option, expired];
+
+        let volatilities = implied_volatilities(&snapshot, &PricingConfig::default()).unwrap();
+        assert_eq!(volatilities.len(), 1);
+        assert!((volatilities[0].mid_iv.unwrap() - 0.4).abs() < 1e-6);
+        assert_eq!(volatilities[0].reported_mid_iv, Some(18.0));
+
+        snapshot.quote = None;
+        let error = implied_volatilities(&snapshot, &PricingConfig::default()).unwrap_err();
+        assert!(matches!(
+            DataError::find(&error),
+            Some(DataError::MissingData(_))
+        ));
+    }
+}
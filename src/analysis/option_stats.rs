@@ -49,5 +49,5 @@ pub fn option_stats(option_chain: &[OptionInfo]) -> Vec<StrikeStats> {
         }
     }
 
-    strike_to_stats.into_iter().map(|(_, v)| v).collect()
+    strike_to_stats.into_values().collect()
 }
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    analysis::gamma_exposure::{price_grid, priced_options},
+    math::{batch, pricing::PricingConfig},
+    types::{GammaExposureStats, OptionInfo, VannaExposure, VannaExposureStats, VolSurface},
+};
+
+/// Shifts to every volatility when none are asked for
+pub const DEFAULT_IV_SHOCKS: [f64; 5] = [-0.1, -0.05, 0.0, 0.05, 0.1];
+/// Shocks never take a volatility below this
+const MIN_VOLATILITY: f64 = 0.01;
+
+impl VannaExposureStats {
+    /// Summarizes `strike_to_vanna_exposure` the same way
+    /// [`GammaExposureStats::new`] does gamma exposure.
+    pub fn new(
+        symbol: impl Into<String>,
+        iv_shock: f64,
+        strike_to_vanna_exposure: &BTreeMap<String, f64>,
+    ) -> anyhow::Result<Self> {
+        let stats = GammaExposureStats::new(symbol, strike_to_vanna_exposure)?;
+
+        Ok(Self {
+            timestamp: stats.timestamp,
+            symbol: stats.symbol,
+            iv_shock,
+            prices: stats
+                .prices
+                .into_iter()
+                .map(|price| VannaExposure {
+                    strike: price.strike,
+                    vanna_exposure: price.gamma_exposure,
+                })
+                .collect(),
+            average_absolute_exposure: stats.average_absolute_exposure,
+            average_positive_exposure: stats.average_positive_exposure,
+            average_negative_exposure: stats.average_negative_exposure,
+            maximum_vanna_exposure: stats.maximum_gamma_exposure,
+            minimum_vanna_exposure: stats.minimum_gamma_exposure,
+            absolute_maximum: stats.absolute_maximum,
+            absolute_minimum: stats.absolute_minimum,
+            weighted_average_absolute_price: stats.weighted_average_absolute_price,
+            weighted_average_positive_price: stats.weighted_average_positive_price,
+            weighted_average_negative_price: stats.weighted_average_negative_price,
+            absolute_maximum_price: stats.absolute_maximum_price,
+            absolute_minimum_price: stats.absolute_minimum_price,
+        })
+    }
+}
+
+/// Vanna exposure of the whole chain across a range of prices, once with
+/// every volatility shifted by each of `iv_shocks`. Volatilities are chosen
+/// as for [`super::gamma_exposure::gamma_exposure_aggregate`]. Vanna is
+/// European under every pricing model, as repricing American options across
+/// the grid for every shock would be far too slow.
+pub fn vanna_exposure_aggregate(
+    symbol: &str,
+    option_chain: &[OptionInfo],
+    now: DateTime<Utc>,
+    pricing: &PricingConfig,
+    surface: Option<&VolSurface>,
+    iv_shocks: &[f64],
+) -> anyhow::Result<Vec<VannaExposureStats>> {
+    let prices = price_grid(option_chain);
+    let options = priced_options(option_chain, now, pricing, surface)?;
+
+    iv_shocks
+        .iter()
+        .map(|&iv_shock| {
+            let contracts: Vec<_> = options
+                .iter()
+                .map(|priced| {
+                    let sigma = (priced.sigma + iv_shock).max(MIN_VOLATILITY);
+                    let contract = pricing.european_contract(
+                        symbol,
+                        priced.option,
+                        sigma,
+                        priced.time_to_expiry,
+                    );
+                    (contract, priced.contracts_held)
+                })
+                .collect();
+            let exposures = batch::vanna_exposure(&contracts, &prices, valid_vanna);
+
+            let strike_to_vanna_exposure: BTreeMap<String, f64> = prices
+                .iter()
+                .map(|price| price.to_string())
+                .zip(exposures)
+                .collect();
+            VannaExposureStats::new(symbol, iv_shock, &strike_to_vanna_exposure)
+        })
+        .collect()
+}
+
+fn valid_vanna(vanna: f64) -> f64 {
+    if vanna.is_finite() {
+        vanna
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        math::{bs, pricing::PricingModel, rates::RateCurve},
+        types::OptionType,
+    };
+
+    #[test]
+    fn shocked_vanna_exposure() {
+        let now = Utc::now();
+        let pricing = PricingConfig {
+            rates: RateCurve::Flat(0.03),
+            // Vanna stays European whatever the model
+            model: PricingModel::Binomial,
+            ..PricingConfig::default()
+        };
+
+        let mut call = OptionInfo::test();
+        call.strike = 100.0;
+        call.expiration_date = (now + Duration::days(30)).format("%Y-%m-%d").to_string();
+        call.mid_iv = Some(0.2);
+        let mut put = call.clone();
+        put.option_type = OptionType::Put;
+        put.strike = 110.0;
+        put.mid_iv = Some(0.03);
+        let mut expired = call.clone();
+        expired.expiration_date = "2021-06-18".to_string();
+        let t = call.time_to_expiry(now, pricing.day_count).unwrap();
+
+        let chain = [call, put, expired];
+        let stats =
+            vanna_exposure_aggregate("TST", &chain, now, &pricing, None, &[-0.05, 0.1]).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[1].iv_shock, 0.1);
+
+        for (stats, call_sigma, put_sigma) in
+            [(&stats[0], 0.15, MIN_VOLATILITY), (&stats[1], 0.3, 0.13)]
+        {
+            assert_eq!(stats.prices.len(), 21);
+            for exposure in &stats.prices {
+                let price: f64 = exposure.strike.parse().unwrap();
+                // Two standard contracts each, short the put
+                let expected = 2.0
+                    * (bs::vanna(call_sigma, t, 0.0, price, 100.0, 0.03, 0.0)
+                        - bs::vanna(put_sigma, t, 0.0, price, 110.0, 0.03, 0.0));
+                assert!(
+                    (exposure.vanna_exposure - expected).abs() < 1e-9,
+                    "{} {}",
+                    exposure.vanna_exposure,
+                    expected
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{
+    data_apis::DataError,
+    math::{pricing::PricingConfig, standard_normal_cdf, svi},
+    types::{
+        Arbitrage, ArbitrageKind, OptionSnapshot, OptionType, SurfacePoint, VolSlice, VolSurface,
+    },
+};
+
+/// Smiles are checked for arbitrage at this many log moneyness points
+/// across the strikes they were fitted to
+const ARBITRAGE_CHECK_POINTS: usize = 101;
+/// Shortfalls smaller than this are rounding
+const ARBITRAGE_TOLERANCE: f64 = 1e-9;
+
+/// The quoted volatilities of one expiration.
+struct Expiration {
+    expiration_date: String,
+    time_to_expiry: f64,
+    forward: f64,
+    /// Strike, type and volatility of every option with a volatility
+    quotes: Vec<(f64, OptionType, f64)>,
+}
+
+impl VolSurface {
+    /// Fits a smile to every unexpired expiration of `snapshot` with enough
+    /// strikes quoted, against the underlying price of the snapshot. Each
+    /// strike takes the volatility of its option out of the money, and the
+    /// smiles then smooth over the noise between strikes.
+    pub fn new(snapshot: &OptionSnapshot, pricing: &PricingConfig) -> anyhow::Result<Self> {
+        let spot = snapshot.spot().ok_or_else(|| {
+            DataError::MissingData(format!("No underlying price for {}", snapshot.symbol))
+        })?;
+
+        let mut expirations: Vec<Expiration> = Vec::new();
+        for option in &snapshot.options {
+            let time_to_expiry = option.time_to_expiry(snapshot.timestamp, pricing.day_count)?;
+            let sigma = match option
+                .mid_iv
+                .filter(|sigma| sigma.is_finite() && *sigma > 0.0)
+            {
+                Some(sigma) if time_to_expiry > 0.0 => sigma,
+                _ => continue,
+            };
+
+            // AM and PM settled options expiring the same day are separate
+            let index = expirations.iter().position(|expiration| {
+                expiration.expiration_date == option.expiration_date
+                    && expiration.time_to_expiry == time_to_expiry
+            });
+            let expiration = match index {
+                Some(index) => &mut expirations[index],
+                None => {
+                    expirations.push(Expiration {
+                        expiration_date: option.expiration_date.clone(),
+                        time_to_expiry,
+                        forward: pricing.forward(&snapshot.symbol, option, time_to_expiry, spot),
+                        quotes: Vec::new(),
+                    });
+                    expirations.last_mut().expect("just pushed")
+                }
+            };
+            expiration
+                .quotes
+                .push((option.strike, option.option_type, sigma));
+        }
+        expirations.sort_by(|e1, e2| e1.time_to_expiry.total_cmp(&e2.time_to_expiry));
+
+        let slices: Vec<VolSlice> = expirations
+            .into_iter()
+            .filter_map(Expiration::fit)
+            .collect();
+        let arbitrage = arbitrage(&slices);
+
+        Ok(Self {
+            timestamp: snapshot.timestamp.to_rfc3339(),
+            symbol: snapshot.symbol.clone(),
+            spot,
+            slices,
+            arbitrage,
+        })
+    }
+
+    /// The volatility of the surface at `strike` with `time_to_expiry` years
+    /// left, or `None` without any fitted smiles. Between expirations total
+    /// variance is interpolated linearly in time at the same log moneyness,
+    /// and beyond them volatility stays that of the nearest smile.
+    pub fn volatility(&self, strike: f64, time_to_expiry: f64) -> Option<f64> {
+        let t = time_to_expiry;
+        if t <= 0.0 || strike <= 0.0 {
+            return None;
+        }
+
+        let k = (strike / self.forward(t)?).ln();
+        let first = self.slices.first()?;
+        let last = self.slices.last()?;
+        let variance = if t <= first.time_to_expiry {
+            first.parameters.total_variance(k) * t / first.time_to_expiry
+        } else if t >= last.time_to_expiry {
+            last.parameters.total_variance(k) * t / last.time_to_expiry
+        } else {
+            let after = self
+                .slices
+                .partition_point(|slice| slice.time_to_expiry < t);
+            let (before, after) = (&self.slices[after - 1], &self.slices[after]);
+            let weight =
+                (t - before.time_to_expiry) / (after.time_to_expiry - before.time_to_expiry);
+            (1.0 - weight) * before.parameters.total_variance(k)
+                + weight * after.parameters.total_variance(k)
+        };
+
+        Some((variance.max(0.0) / t).sqrt())
+    }
+
+    /// The forward price `time_to_expiry` years out, with its logarithm
+    /// interpolated linearly between the spot price and the forwards of the
+    /// smiles, and the carry of the last smile beyond them.
+    fn forward(&self, time_to_expiry: f64) -> Option<f64> {
+        let last = self.slices.last()?;
+        let (mut t0, mut ln_f0) = (0.0, self.spot.ln());
+        for slice in &self.slices {
+            let (t1, ln_f1) = (slice.time_to_expiry, slice.forward.ln());
+            if time_to_expiry <= t1 {
+                let weight = (time_to_expiry - t0) / (t1 - t0);
+                return Some((ln_f0 + weight * (ln_f1 - ln_f0)).exp());
+            }
+            t0 = t1;
+            ln_f0 = ln_f1;
+        }
+
+        let carry = (last.forward / self.spot).ln() / last.time_to_expiry;
+        Some(self.spot * (carry * time_to_expiry).exp())
+    }
+}
+
+impl Expiration {
+    /// The smile fitted to the volatilities out of the money, or `None`
+    /// with too few strikes to fit one.
+    fn fit(mut self) -> Option<VolSlice> {
+        let (forward, t) = (self.forward, self.time_to_expiry);
+        self.quotes
+            .sort_by(|(s1, _, _), (s2, _, _)| s1.total_cmp(s2));
+
+        let mut quotes: Vec<(f64, OptionType, f64)> = Vec::new();
+        for quote in self.quotes {
+            let (strike, option_type, _) = quote;
+            let out_of_the_money = match option_type {
+                OptionType::Call => strike >= forward,
+                OptionType::Put => strike < forward,
+            };
+            match quotes.last_mut() {
+                Some(last) if last.0 == strike => {
+                    if out_of_the_money {
+                        *last = quote;
+                    }
+                }
+                _ => quotes.push(quote),
+            }
+        }
+
+        let variances: Vec<(f64, f64)> = quotes
+            .iter()
+            .map(|(strike, _, sigma)| ((strike / forward).ln(), sigma * sigma * t))
+            .collect();
+        let parameters = svi::calibrate(&variances)?;
+
+        let points: Vec<SurfacePoint> = quotes
+            .iter()
+            .zip(&variances)
+            .map(|(&(strike, option_type, market_volatility), &(k, _))| {
+                let variance = parameters.total_variance(k).max(0.0);
+                let deviation = variance.sqrt();
+                SurfacePoint {
+                    strike,
+                    option_type,
+                    log_moneyness: k,
+                    delta: standard_normal_cdf(-k / deviation + deviation / 2.0),
+                    market_volatility,
+                    volatility: (variance / t).sqrt(),
+                }
+            })
+            .collect();
+        let squared_error: f64 = points
+            .iter()
+            .map(|point| (point.volatility - point.market_volatility).powi(2))
+            .sum();
+
+        Some(VolSlice {
+            expiration_date: self.expiration_date,
+            time_to_expiry: t,
+            forward,
+            parameters,
+            fit_error: (squared_error / points.len() as f64).sqrt(),
+            points,
+        })
+    }
+}
+
+/// The worst butterfly arbitrage of every smile across its strikes, and the
+/// worst calendar arbitrage between every pair of consecutive smiles across
+/// the strikes of both.
+fn arbitrage(slices: &[VolSlice]) -> Vec<Arbitrage> {
+    let mut result = Vec::new();
+
+    for slice in slices {
+        let worst = range_grid(&[slice])
+            .map(|k| (k, slice.parameters.density_factor(k)))
+            .min_by(|(_, g1), (_, g2)| g1.total_cmp(g2));
+        if let Some((k, g)) = worst.filter(|(_, g)| *g < -ARBITRAGE_TOLERANCE) {
+            result.push(Arbitrage {
+                kind: ArbitrageKind::Butterfly,
+                expiration_date: slice.expiration_date.clone(),
+                log_moneyness: k,
+                amount: -g,
+            });
+        }
+    }
+
+    for pair in slices.windows(2) {
+        let (earlier, later) = (&pair[0], &pair[1]);
+        let worst = range_grid(&[earlier, later])
+            .map(|k| {
+                let fall =
+                    earlier.parameters.total_variance(k) - later.parameters.total_variance(k);
+                (k, fall)
+            })
+            .max_by(|(_, f1), (_, f2)| f1.total_cmp(f2));
+        if let Some((k, fall)) = worst.filter(|(_, fall)| *fall > ARBITRAGE_TOLERANCE) {
+            result.push(Arbitrage {
+                kind: ArbitrageKind::Calendar,
+                expiration_date: later.expiration_date.clone(),
+                log_moneyness: k,
+                amount: fall,
+            });
+        }
+    }
+
+    result
+}
+
+/// Evenly spaced log moneyness across the points of `slices`.
+fn range_grid(slices: &[&VolSlice]) -> impl Iterator<Item = f64> + Clone {
+    let (min, max) = slices
+        .iter()
+        .flat_map(|slice| &slice.points)
+        .fold((f64::MAX, f64::MIN), |(min, max), point| {
+            (min.min(point.log_moneyness), max.max(point.log_moneyness))
+        });
+    let step = (max - min) / (ARBITRAGE_CHECK_POINTS - 1) as f64;
+    (0..ARBITRAGE_CHECK_POINTS).map(move |i| min + step * i as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        analysis::gamma_exposure::gamma_exposure_aggregate,
+        math::rates::RateCurve,
+        types::{OptionInfo, SviParameters},
+    };
+
+    const SMILE: SviParameters = SviParameters {
+        a: 0.02,
+        b: 0.1,
+        rho: -0.4,
+        m: 0.05,
+        sigma: 0.2,
+    };
+
+    /// A chain of calls and puts on strikes 60 to 140 with volatilities
+    /// from `SMILE` scaled by the time to each of `days`.
+    fn snapshot(days: &[i64], pricing: &PricingConfig) -> OptionSnapshot {
+        let now = Utc::now();
+        let mut snapshot = OptionSnapshot::test();
+        snapshot.timestamp = now;
+        snapshot.quote.as_mut().unwrap().last = Some(100.0);
+        snapshot.options.clear();
+
+        for &days in days {
+            let mut option = OptionInfo::test();
+            option.expiration_date = (now + Duration::days(days)).format("%Y-%m-%d").to_string();
+            let t = option.time_to_expiry(now, pricing.day_count).unwrap();
+            let forward = pricing.forward(&snapshot.symbol, &option, t, 100.0);
+            for strike in (60..=140).step_by(5) {
+                let k = (strike as f64 / forward).ln();
+                // Total variance grows with time so the smiles never cross
+                let sigma = (SMILE.total_variance(k) * (1.0 + t) / t).sqrt();
+                for option_type in [OptionType::Call, OptionType::Put] {
+                    let mut option = option.clone();
+                    option.strike = strike as f64;
+                    option.option_type = option_type;
+                    option.mid_iv = Some(sigma);
+                    snapshot.options.push(option);
+                }
+            }
+        }
+        snapshot
+    }
+
+    #[test]
+    fn fitted_surface() {
+        let pricing = PricingConfig {
+            rates: RateCurve::Flat(0.03),
+            ..PricingConfig::default()
+        };
+        let mut snapshot = snapshot(&[60, 30, 120], &pricing);
+        // A put on a strike quoted for both is its call's in the money twin
+        snapshot.options[0].mid_iv = Some(5.0);
+        let surface = VolSurface::new(&snapshot, &pricing).unwrap();
+
+        assert_eq!(surface.slices.len(), 3);
+        assert!(surface.slices[0].time_to_expiry < surface.slices[1].time_to_expiry);
+        assert!(surface.arbitrage.is_empty());
+        for slice in &surface.slices {
+            assert_eq!(slice.points.len(), 17);
+            assert!(slice.fit_error < 1e-6);
+            assert!((slice.forward - 100.0 * (0.03 * slice.time_to_expiry).exp()).abs() < 1e-9);
+            assert!(slice.points.windows(2).all(|p| p[0].delta > p[1].delta));
+
+            // At a smile's own expiration the surface is the smile
+            for point in &slice.points {
+                let sigma = surface
+                    .volatility(point.strike, slice.time_to_expiry)
+                    .unwrap();
+                assert!((sigma - point.volatility).abs() < 1e-12);
+            }
+        }
+        assert_eq!(surface.slices[0].points[0].option_type, OptionType::Put);
+
+        // Between expirations total variance moves linearly with time
+        let (first, second) = (&surface.slices[0], &surface.slices[1]);
+        let t = (first.time_to_expiry + second.time_to_expiry) / 2.0;
+        let strike = 100.0 * (0.03 * t).exp();
+        let expected =
+            (first.parameters.total_variance(0.0) + second.parameters.total_variance(0.0)) / 2.0;
+        let sigma = surface.volatility(strike, t).unwrap();
+        assert!((sigma.powi(2) * t - expected).abs() < 1e-9);
+        assert!(surface.volatility(strike, 0.0).is_none());
+    }
+
+    #[test]
+    fn surface_gamma_exposure() {
+        let pricing = PricingConfig::default();
+        let mut snapshot = snapshot(&[30, 60], &pricing);
+        let surface = VolSurface::new(&snapshot, &pricing).unwrap();
+        let exposure = |snapshot: &OptionSnapshot, surface| {
+            gamma_exposure_aggregate(
+                "TST",
+                &snapshot.options,
+                snapshot.timestamp,
+                &pricing,
+                surface,
+            )
+            .unwrap()
+        };
+
+        // The smiles match the chain, so the surface changes nothing
+        let raw = exposure(&snapshot, None);
+        let smoothed = exposure(&snapshot, Some(&surface));
+        assert_eq!(raw.prices.len(), smoothed.prices.len());
+        for (raw, smoothed) in raw.prices.iter().zip(&smoothed.prices) {
+            assert!((raw.gamma_exposure - smoothed.gamma_exposure).abs() < 1e-6);
+        }
+
+        // Options without a volatility of their own take the surface's
+        for option in &mut snapshot.options {
+            option.mid_iv = None;
+        }
+        assert!(exposure(&snapshot, None)
+            .prices
+            .iter()
+            .all(|price| price.gamma_exposure == 0.0));
+        let filled = exposure(&snapshot, Some(&surface));
+        for (filled, smoothed) in filled.prices.iter().zip(&smoothed.prices) {
+            assert_eq!(filled.gamma_exposure, smoothed.gamma_exposure);
+        }
+    }
+
+    #[test]
+    fn surface_arbitrage() {
+        let pricing = PricingConfig::default();
+        let mut snapshot = snapshot(&[30, 60], &pricing);
+        // Total variance of the later expiration below that of the earlier
+        let now = snapshot.timestamp;
+        for option in &mut snapshot.options {
+            let t = option.time_to_expiry(now, pricing.day_count).unwrap();
+            if t > 45.0 / 365.0 {
+                option.mid_iv = option.mid_iv.map(|sigma| sigma * 0.5);
+            }
+        }
+
+        let surface = VolSurface::new(&snapshot, &pricing).unwrap();
+        assert_eq!(surface.arbitrage.len(), 1);
+        let arbitrage = &surface.arbitrage[0];
+        assert_eq!(arbitrage.kind, ArbitrageKind::Calendar);
+        assert_eq!(arbitrage.expiration_date, surface.slices[1].expiration_date);
+        assert!(arbitrage.amount > 0.0);
+
+        snapshot.quote = None;
+        let error = VolSurface::new(&snapshot, &pricing).unwrap_err();
+        assert!(matches!(
+            DataError::find(&error),
+            Some(DataError::MissingData(_))
+        ));
+    }
+}
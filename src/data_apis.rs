@@ -1,2 +1,93 @@
+pub mod error;
+pub mod http;
+pub mod replay;
 pub mod td;
 pub mod tradier;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+pub use error::DataError;
+
+use crate::types::{Clock, Ohlc, OhlcInterval, OptionInfo, OptionSnapshot, Quote};
+
+const PROVIDER_ENV: &str = "DATA_PROVIDER";
+const DEFAULT_PROVIDER: &str = "tradier";
+
+pub type Provider = Arc<dyn MarketDataProvider>;
+
+/// A source of market data. Implementations convert their vendor-specific
+/// responses into the crate's own types so that consumers don't need to know
+/// where the data came from.
+#[async_trait]
+pub trait MarketDataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// How long to wait in real time for `duration` to pass on the provider's
+    /// clock. Only differs from `duration` for simulated clocks.
+    fn real_duration(&self, duration: Duration) -> Duration {
+        duration
+    }
+
+    async fn quote(&self, symbol: &str) -> anyhow::Result<Quote>;
+
+    async fn clock(&self) -> anyhow::Result<Clock>;
+
+    async fn option_expirations(&self, symbol: &str) -> anyhow::Result<Vec<String>>;
+
+    async fn option_chain(&self, symbol: &str) -> anyhow::Result<Vec<OptionInfo>>;
+
+    /// The option chain along with the quote of the underlying it was priced
+    /// against. Providers that fetch the quote as part of the chain should
+    /// override this to reuse it.
+    async fn option_snapshot(&self, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+        let timestamp = Utc::now();
+        let quote = self.quote(symbol).await?;
+        let options = self.option_chain(symbol).await?;
+
+        Ok(OptionSnapshot::new(
+            timestamp,
+            symbol,
+            self.name(),
+            Some(quote),
+            options,
+        ))
+    }
+
+    async fn time_and_sales(
+        &self,
+        symbol: &str,
+        interval: OhlcInterval,
+    ) -> anyhow::Result<Vec<Ohlc>>;
+}
+
+pub fn provider(name: &str) -> anyhow::Result<Provider> {
+    Ok(match name.to_lowercase().as_ref() {
+        "tradier" => Arc::new(tradier::TradierProvider::from_env()?),
+        "td" | "tda" => Arc::new(td::TdProvider::from_env()?),
+        replay::PROVIDER_NAME => Arc::new(replay::ReplayProvider::from_env()?),
+        _ => anyhow::bail!("Invalid data provider: {}", name),
+    })
+}
+
+/// Selects the provider named by the `DATA_PROVIDER` environment variable,
+/// defaulting to Tradier. `replay` is configured by its own `REPLAY_*`
+/// variables.
+pub fn provider_from_env() -> anyhow::Result<Provider> {
+    let name = std::env::var(PROVIDER_ENV).unwrap_or_else(|_| DEFAULT_PROVIDER.to_string());
+    provider(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_names() {
+        assert_eq!(provider("tradier").unwrap().name(), "tradier");
+        assert_eq!(provider("TDA").unwrap().name(), "td");
+        assert!(provider("nope").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fmt, time::Duration};
+
+use serde::de::DeserializeOwned;
+use warp::http::StatusCode;
+
+/// Failures of a market data provider that callers may want to handle, or
+/// show, differently. They travel inside `anyhow::Error` and are recovered
+/// with [`DataError::find`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataError {
+    /// Credentials are missing or were rejected by the upstream
+    Auth(String),
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// The upstream timed out, couldn't be reached or failed on its side
+    Unavailable(String),
+    UnknownSymbol(String),
+    /// An argument of the request can't be used as given
+    InvalidInput(String),
+    /// What was stored or fetched lacks something the request needs, such as
+    /// the price of the underlying
+    MissingData(String),
+    /// The upstream responded with something we don't understand
+    Parse(String),
+}
+
+impl DataError {
+    /// The first `DataError` in the chain of `error`, if any.
+    pub fn find(error: &anyhow::Error) -> Option<&DataError> {
+        error.chain().find_map(|e| e.downcast_ref::<DataError>())
+    }
+
+    /// A stable identifier exposed to clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DataError::Auth(_) => "AUTH_FAILED",
+            DataError::RateLimited { .. } => "RATE_LIMITED",
+            DataError::Unavailable(_) => "UPSTREAM_UNAVAILABLE",
+            DataError::UnknownSymbol(_) => "UNKNOWN_SYMBOL",
+            DataError::InvalidInput(_) => "INVALID_INPUT",
+            DataError::MissingData(_) => "MISSING_DATA",
+            DataError::Parse(_) => "PARSE_ERROR",
+        }
+    }
+
+    /// Failing upstream credentials are our problem rather than the
+    /// client's, so they're reported as a bad gateway like parse errors.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DataError::Auth(_) | DataError::Parse(_) => StatusCode::BAD_GATEWAY,
+            DataError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            DataError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            DataError::UnknownSymbol(_) => StatusCode::NOT_FOUND,
+            DataError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            DataError::MissingData(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Auth(message) => write!(f, "Authentication failed: {}", message),
+            DataError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(f, "Rate limited, retry in {}s", retry_after.as_secs()),
+            DataError::RateLimited { retry_after: None } => write!(f, "Rate limited"),
+            DataError::Unavailable(message) => write!(f, "Upstream unavailable: {}", message),
+            DataError::UnknownSymbol(symbol) => write!(f, "Unknown symbol: {}", symbol),
+            DataError::InvalidInput(message) => write!(f, "Invalid input: {}", message),
+            DataError::MissingData(message) => write!(f, "Missing data: {}", message),
+            DataError::Parse(message) => write!(f, "Unexpected response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// Deserializes an upstream response, logging the body when it doesn't have
+/// the expected shape.
+pub fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, DataError> {
+    serde_json::from_str(body).map_err(|e| {
+        log::error!("{}", e);
+        log::error!("{}", body);
+        DataError::Parse(e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find() {
+        let error = anyhow::Error::new(DataError::UnknownSymbol("NOPE".to_string()))
+            .context("Loading option chain");
+        let data_error = DataError::find(&error).unwrap();
+        assert_eq!(data_error.code(), "UNKNOWN_SYMBOL");
+        assert_eq!(data_error.status(), StatusCode::NOT_FOUND);
+
+        assert!(DataError::find(&anyhow::anyhow!("Other")).is_none());
+        assert!(matches!(
+            parse_json::<Vec<f64>>("{}"),
+            Err(DataError::Parse(_))
+        ));
+    }
+}
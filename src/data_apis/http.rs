@@ -0,0 +1,321 @@
+//! A pooled HTTP client shared by the providers. Requests are throttled to
+//! the upstream quota, time out instead of hanging, and are retried with
+//! exponential backoff when the upstream is rate limiting or unavailable.
+
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use tokio::{sync::Mutex, time::Instant};
+
+use super::DataError;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A token bucket allowing bursts of up to `capacity` requests, refilled at a
+/// steady rate.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Allows `requests` requests every `period`.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let capacity = requests.max(1) as f64;
+        Self {
+            capacity,
+            per_second: capacity / period.as_secs_f64().max(f64::MIN_POSITIVE),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until a request may be sent. Waiters are served in order since
+    /// the lock is held while sleeping.
+    pub async fn acquire(&self) {
+        let mut bucket = self.bucket.lock().await;
+        self.refill(&mut bucket);
+
+        if bucket.tokens < 1.0 {
+            let wait = (1.0 - bucket.tokens) / self.per_second;
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            self.refill(&mut bucket);
+        }
+
+        bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = (now - bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.capacity);
+        bucket.updated = now;
+    }
+}
+
+/// How often and how patiently failed requests are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `attempt`, doubling every time.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    limiter: RateLimiter,
+    retry: RetryPolicy,
+}
+
+impl HttpClient {
+    pub fn new(limiter: RateLimiter, retry: RetryPolicy) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            limiter,
+            retry,
+        }
+    }
+
+    /// Sends a GET request to `url` and returns the body of the first
+    /// successful response.
+    pub async fn get(&self, url: &str, headers: &[(&str, &str)]) -> anyhow::Result<String> {
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire().await;
+
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+
+            let (error, delay) = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response.text().await?);
+                }
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = retry_after(&response);
+                    // Waiting longer than any backoff would stall the caller,
+                    // who can decide for themselves when to try again
+                    if matches!(retry_after, Some(delay) if delay > self.retry.max_delay) {
+                        return Err(DataError::RateLimited { retry_after }.into());
+                    }
+                    (
+                        DataError::RateLimited { retry_after },
+                        retry_after.unwrap_or_else(|| self.retry.delay(attempt)),
+                    )
+                }
+                Ok(response) if response.status().is_server_error() => (
+                    DataError::Unavailable(format!(
+                        "{} returned {}",
+                        redact(url),
+                        response.status()
+                    )),
+                    self.retry.delay(attempt),
+                ),
+                Ok(response)
+                    if matches!(
+                        response.status(),
+                        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                    ) =>
+                {
+                    return Err(DataError::Auth(format!(
+                        "{} returned {}",
+                        redact(url),
+                        response.status()
+                    ))
+                    .into());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    anyhow::bail!("{} returned {}: {}", redact(url), status, body.trim());
+                }
+                Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => (
+                    DataError::Unavailable(format!(
+                        "Request to {} failed: {}",
+                        redact(url),
+                        e.without_url()
+                    )),
+                    self.retry.delay(attempt),
+                ),
+                Err(e) => return Err(e.without_url().into()),
+            };
+
+            if attempt >= self.retry.max_retries {
+                return Err(error.into());
+            }
+
+            log::warn!("{}, retrying in {:?}", error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The delay requested by the upstream, when given in seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.trim().parse().ok()?))
+}
+
+/// Strips the query string, which may hold API keys, for logging.
+fn redact(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use warp::{http::StatusCode as WarpStatus, Filter};
+
+    use super::*;
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+
+    /// Serves `statuses` in order, then 200s.
+    fn serve(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let route = warp::any().map(move || {
+            let i = counter.fetch_add(1, Ordering::SeqCst);
+            let status = statuses.get(i).copied().unwrap_or(200);
+            warp::reply::with_status(
+                format!("response {}", i),
+                WarpStatus::from_u16(status).unwrap(),
+            )
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/test?apikey=secret", addr), requests)
+    }
+
+    #[test]
+    fn backoff() {
+        let retry = RetryPolicy::default();
+        assert_eq!(retry.delay(0), Duration::from_millis(500));
+        assert_eq!(retry.delay(2), Duration::from_secs(2));
+        assert_eq!(retry.delay(20), retry.max_delay);
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(100));
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire().await;
+        }
+        // Two are allowed as a burst, the other two wait for a refill
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn retries() {
+        let limiter = RateLimiter::new(100, Duration::from_secs(1));
+        let client = HttpClient::new(limiter, retry());
+
+        let (url, requests) = serve(vec![503, 429]);
+        assert_eq!(client.get(&url, &[]).await.unwrap(), "response 2");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // Client errors aren't retried
+        let (url, requests) = serve(vec![401]);
+        let error = client.get(&url, &[]).await.unwrap_err();
+        assert!(matches!(DataError::find(&error), Some(DataError::Auth(_))));
+        assert!(error.to_string().contains("401"));
+        assert!(!error.to_string().contains("secret"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let (url, requests) = serve(vec![500; 10]);
+        let error = client.get(&url, &[]).await.unwrap_err();
+        assert!(matches!(
+            DataError::find(&error),
+            Some(DataError::Unavailable(_))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        let (url, _) = serve(vec![429; 10]);
+        let error = client.get(&url, &[]).await.unwrap_err();
+        assert_eq!(
+            DataError::find(&error),
+            Some(&DataError::RateLimited { retry_after: None })
+        );
+    }
+
+    #[tokio::test]
+    async fn long_retry_after() {
+        let limiter = RateLimiter::new(100, Duration::from_secs(1));
+        let client = HttpClient::new(limiter, retry());
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let route = warp::any().map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::with_header(
+                warp::reply::with_status("slow down", WarpStatus::TOO_MANY_REQUESTS),
+                "retry-after",
+                "3600",
+            )
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let error = client
+            .get(&format!("http://{}/test", addr), &[])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            DataError::find(&error),
+            Some(&DataError::RateLimited {
+                retry_after: Some(Duration::from_secs(3600))
+            })
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,533 @@
+//! Serves previously recorded data as if it were live. Recordings are either
+//! a `FileDb` directory or a directory of JSON files holding snapshots, and
+//! time is simulated so a trading day can be replayed in minutes.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration as StdDuration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::America::New_York;
+use tokio::sync::Mutex;
+
+use super::{DataError, MarketDataProvider};
+use crate::{
+    db::{file::Symbol, FileDb},
+    types::{clock::MarketState, Clock, Ohlc, OhlcInterval, OptionInfo, OptionSnapshot, Quote},
+};
+
+const REPLAY_PATH_ENV: &str = "REPLAY_PATH";
+const REPLAY_START_ENV: &str = "REPLAY_START";
+const REPLAY_SPEED_ENV: &str = "REPLAY_SPEED";
+pub const PROVIDER_NAME: &str = "replay";
+/// Shortest real-time wait, so very fast replays don't spin
+const MIN_REAL_DURATION: StdDuration = StdDuration::from_millis(10);
+
+/// A clock that starts at `start` and runs `speed` times faster than real
+/// time.
+#[derive(Clone, Copy, Debug)]
+pub struct SimulatedClock {
+    real_start: Instant,
+    start: DateTime<Utc>,
+    speed: f64,
+}
+
+impl SimulatedClock {
+    pub fn new(start: DateTime<Utc>, speed: f64) -> Self {
+        Self {
+            real_start: Instant::now(),
+            start,
+            speed: speed.max(f64::MIN_POSITIVE),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        let elapsed = self.real_start.elapsed().mul_f64(self.speed);
+        self.start + Duration::from_std(elapsed).unwrap_or_else(|_| Duration::zero())
+    }
+
+    pub fn real_duration(&self, simulated: StdDuration) -> StdDuration {
+        simulated.div_f64(self.speed).max(MIN_REAL_DURATION)
+    }
+}
+
+enum Recordings {
+    Db(Mutex<FileDb>),
+    Files(BTreeMap<Symbol, Vec<OptionSnapshot>>),
+}
+
+pub struct ReplayProvider {
+    recordings: Recordings,
+    clock: SimulatedClock,
+}
+
+impl ReplayProvider {
+    /// Replays the recordings in `path`, starting at `start` or, without one,
+    /// at the first recorded snapshot.
+    pub fn open(
+        path: impl AsRef<Path>,
+        start: Option<DateTime<Utc>>,
+        speed: f64,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            anyhow::bail!("Replay path {} is not a directory", path.display());
+        }
+
+        let recordings = if is_file_db(path)? {
+            Recordings::Db(Mutex::new(FileDb::open_read_only(path)?))
+        } else {
+            Recordings::Files(read_recordings(path)?)
+        };
+
+        let start = match start {
+            Some(start) => start,
+            None => first_timestamp(&recordings)
+                .ok_or_else(|| anyhow::anyhow!("No recordings in {}", path.display()))?,
+        };
+
+        Ok(Self {
+            recordings,
+            clock: SimulatedClock::new(start, speed),
+        })
+    }
+
+    /// Configured by `REPLAY_PATH`, plus optional `REPLAY_START` (RFC 3339)
+    /// and `REPLAY_SPEED` (defaults to real time).
+    pub fn from_env() -> anyhow::Result<Self> {
+        let path = std::env::var(REPLAY_PATH_ENV)
+            .map_err(|_| anyhow::anyhow!("{} must be set to replay data", REPLAY_PATH_ENV))?;
+        let start = match std::env::var(REPLAY_START_ENV) {
+            Ok(start) => Some(DateTime::parse_from_rfc3339(&start)?.with_timezone(&Utc)),
+            Err(_) => None,
+        };
+        let speed = match std::env::var(REPLAY_SPEED_ENV) {
+            Ok(speed) => speed.parse()?,
+            Err(_) => 1.0,
+        };
+
+        Self::open(path, start, speed)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// The latest recording of `symbol` as of the simulated time.
+    async fn recorded_snapshot(&self, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+        let now = self.now();
+        let symbol = symbol.to_uppercase();
+
+        let snapshot = match &self.recordings {
+            Recordings::Db(db) => db.lock().await.snapshot_at(&symbol, now)?.cloned(),
+            Recordings::Files(files) => files
+                .get(&symbol)
+                .and_then(|s| s.iter().rev().find(|s| s.timestamp <= now))
+                .cloned(),
+        };
+
+        snapshot.ok_or_else(|| DataError::UnknownSymbol(symbol).into())
+    }
+
+    /// Recordings of `symbol` from the start of the simulated day until now.
+    async fn recorded_day(&self, symbol: &str) -> anyhow::Result<Vec<OptionSnapshot>> {
+        let now = self.now();
+        let symbol = symbol.to_uppercase();
+        let day_start = New_York
+            .from_local_datetime(
+                &now.with_timezone(&New_York)
+                    .date_naive()
+                    .and_time(NaiveTime::MIN),
+            )
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or(now);
+
+        Ok(match &self.recordings {
+            Recordings::Db(db) => db.lock().await.snapshots(&symbol, day_start..=now)?,
+            Recordings::Files(files) => files
+                .get(&symbol)
+                .map(|snapshots| {
+                    snapshots
+                        .iter()
+                        .filter(|s| s.timestamp >= day_start && s.timestamp <= now)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for ReplayProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn real_duration(&self, duration: StdDuration) -> StdDuration {
+        self.clock.real_duration(duration)
+    }
+
+    async fn quote(&self, symbol: &str) -> anyhow::Result<Quote> {
+        self.recorded_snapshot(symbol)
+            .await?
+            .quote
+            .ok_or_else(|| anyhow::anyhow!("No recorded quote for {}", symbol))
+    }
+
+    async fn clock(&self) -> anyhow::Result<Clock> {
+        Ok(market_clock(self.now()))
+    }
+
+    async fn option_expirations(&self, symbol: &str) -> anyhow::Result<Vec<String>> {
+        let snapshot = self.recorded_snapshot(symbol).await?;
+
+        let mut expirations: Vec<String> = snapshot
+            .options
+            .into_iter()
+            .map(|o| o.expiration_date)
+            .collect();
+        expirations.sort();
+        expirations.dedup();
+
+        Ok(expirations)
+    }
+
+    async fn option_chain(&self, symbol: &str) -> anyhow::Result<Vec<OptionInfo>> {
+        Ok(self.option_snapshot(symbol).await?.options)
+    }
+
+    /// The recording is served as if it had just been captured.
+    async fn option_snapshot(&self, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+        let recorded = self.recorded_snapshot(symbol).await?;
+        let now = self.now();
+
+        let mut options = recorded.options;
+        for option in &mut options {
+            option.timestamp = now.to_rfc3339();
+        }
+
+        Ok(OptionSnapshot::new(
+            now,
+            symbol,
+            PROVIDER_NAME,
+            recorded.quote,
+            options,
+        ))
+    }
+
+    /// Candles built from the quotes recorded so far today. Recordings only
+    /// hold one price per snapshot, so volume isn't available.
+    async fn time_and_sales(
+        &self,
+        symbol: &str,
+        interval: OhlcInterval,
+    ) -> anyhow::Result<Vec<Ohlc>> {
+        let prices: Vec<(DateTime<Utc>, f64)> = self
+            .recorded_day(symbol)
+            .await?
+            .iter()
+            .filter_map(|s| Some((s.timestamp, s.spot()?)))
+            .collect();
+
+        Ok(candles(&prices, interval))
+    }
+}
+
+fn candles(prices: &[(DateTime<Utc>, f64)], interval: OhlcInterval) -> Vec<Ohlc> {
+    let minutes = match interval {
+        OhlcInterval::Tick => 0,
+        OhlcInterval::OneMinute => 1,
+        OhlcInterval::FiveMinute => 5,
+        OhlcInterval::FifteenMinute => 15,
+    };
+
+    let mut candles: Vec<(i64, Ohlc)> = Vec::new();
+    for (i, &(time, price)) in prices.iter().enumerate() {
+        let bucket = match minutes {
+            0 => i as i64,
+            _ => time.timestamp() / (minutes * 60),
+        };
+
+        match candles.last_mut() {
+            Some((last_bucket, candle)) if *last_bucket == bucket => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+                candle.price = price;
+            }
+            _ => {
+                let start = match minutes {
+                    0 => time,
+                    _ => Utc
+                        .timestamp_opt(bucket * minutes * 60, 0)
+                        .single()
+                        .unwrap_or(time),
+                };
+                candles.push((
+                    bucket,
+                    Ohlc {
+                        interval,
+                        time: start
+                            .with_timezone(&New_York)
+                            .format("%Y-%m-%dT%H:%M:%S")
+                            .to_string(),
+                        price,
+                        open: price,
+                        high: price,
+                        low: price,
+                        close: price,
+                        volume: 0,
+                        vwap: None,
+                    },
+                ))
+            }
+        }
+    }
+
+    candles.into_iter().map(|(_, candle)| candle).collect()
+}
+
+/// Regular trading hours, 9:30 to 16:00 New York time on weekdays. Holidays
+/// aren't known, so they're treated as trading days.
+pub fn market_clock(now: DateTime<Utc>) -> Clock {
+    let local = now.with_timezone(&New_York);
+    let open_time = NaiveTime::from_hms_opt(9, 30, 0).unwrap_or_default();
+    let close_time = NaiveTime::from_hms_opt(16, 0, 0).unwrap_or_default();
+    let is_trading_day = |weekday: Weekday| !matches!(weekday, Weekday::Sat | Weekday::Sun);
+
+    let today = local.date_naive();
+    let (state, next_state, next_change) = if is_trading_day(today.weekday())
+        && local.time() >= open_time
+        && local.time() < close_time
+    {
+        (
+            MarketState::Open,
+            MarketState::Closed,
+            today.and_time(close_time),
+        )
+    } else {
+        let mut date = today;
+        if !is_trading_day(date.weekday()) || local.time() >= open_time {
+            date = date.succ_opt().unwrap_or(date);
+        }
+        while !is_trading_day(date.weekday()) {
+            date = date.succ_opt().unwrap_or(date);
+        }
+        (
+            MarketState::Closed,
+            MarketState::Open,
+            date.and_time(open_time),
+        )
+    };
+
+    let next_change = New_York
+        .from_local_datetime(&next_change)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or(now);
+
+    Clock {
+        timestamp: now.timestamp() as u64,
+        date: today.to_string(),
+        description: format!(
+            "Market is {}. {} at {}",
+            state,
+            next_state,
+            next_change.with_timezone(&New_York)
+        ),
+        state,
+        next_state,
+        next_change_minutes: (next_change - now).num_minutes().max(0),
+    }
+}
+
+/// Fails if snapshots would be stored in `db_root` while it is the recording
+/// named by `REPLAY_PATH`, since the replay would append to its own source.
+pub fn check_db_root(db_root: &Path) -> anyhow::Result<()> {
+    match std::env::var(REPLAY_PATH_ENV) {
+        Ok(replay_path) => check_distinct(Path::new(&replay_path), db_root),
+        Err(_) => Ok(()),
+    }
+}
+
+fn check_distinct(replay_path: &Path, db_root: &Path) -> anyhow::Result<()> {
+    // A database that doesn't exist yet can't be the recording
+    if let (Ok(source), Ok(target)) = (replay_path.canonicalize(), db_root.canonicalize()) {
+        if source == target {
+            anyhow::bail!(
+                "Refusing to store replayed snapshots in {}, the directory being replayed",
+                db_root.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// A `FileDb` directory has an index or per-symbol segment directories, while
+/// a directory of recordings holds JSON files.
+fn is_file_db(path: &Path) -> anyhow::Result<bool> {
+    if path.join("index.json").exists() {
+        return Ok(true);
+    }
+    for entry in std::fs::read_dir(path)? {
+        if entry?.file_type()?.is_dir() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Reads every `*.json` file in `path`, each holding one snapshot or a list
+/// of them.
+fn read_recordings(path: &Path) -> anyhow::Result<BTreeMap<Symbol, Vec<OptionSnapshot>>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<_>>()?;
+    files.retain(|f| f.extension().and_then(|e| e.to_str()) == Some("json"));
+    files.sort();
+
+    let mut recordings: BTreeMap<Symbol, Vec<OptionSnapshot>> = BTreeMap::new();
+    for file in files {
+        let json = std::fs::read_to_string(&file)?;
+        let value: serde_json::Value = serde_json::from_str(&json)?;
+        let snapshots: Vec<OptionSnapshot> = match value {
+            serde_json::Value::Array(_) => serde_json::from_value(value),
+            _ => serde_json::from_value(value).map(|s| vec![s]),
+        }
+        .map_err(|e| anyhow::anyhow!("Invalid recording {}: {}", file.display(), e))?;
+
+        for snapshot in snapshots {
+            recordings
+                .entry(snapshot.symbol.to_uppercase())
+                .or_default()
+                .push(snapshot);
+        }
+    }
+
+    for snapshots in recordings.values_mut() {
+        snapshots.sort_by_key(|s| s.timestamp);
+    }
+
+    Ok(recordings)
+}
+
+fn first_timestamp(recordings: &Recordings) -> Option<DateTime<Utc>> {
+    match recordings {
+        Recordings::Db(db) => {
+            let db = db.try_lock().ok()?;
+            db.symbols()
+                .iter()
+                .filter_map(|symbol| db.snapshot_timestamps(symbol).first().cloned())
+                .min()
+        }
+        Recordings::Files(files) => files
+            .values()
+            .filter_map(|snapshots| snapshots.first())
+            .map(|s| s.timestamp)
+            .min(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::at;
+
+    fn snapshot_at(time: &str, price: f64) -> OptionSnapshot {
+        let mut snapshot = OptionSnapshot::test();
+        snapshot.timestamp = at(time);
+        snapshot.quote.as_mut().unwrap().last = Some(price);
+        snapshot
+    }
+
+    #[test]
+    fn simulated_clock() {
+        let clock = SimulatedClock::new(at("2021-06-10T14:00:00Z"), 3600.0);
+        assert!(clock.now() >= at("2021-06-10T14:00:00Z"));
+        assert_eq!(
+            clock.real_duration(StdDuration::from_secs(2 * 3600)),
+            StdDuration::from_secs(2)
+        );
+        assert_eq!(
+            clock.real_duration(StdDuration::from_secs(1)),
+            MIN_REAL_DURATION
+        );
+    }
+
+    #[test]
+    fn clock_hours() {
+        // Thursday during the session
+        let clock = market_clock(at("2021-06-10T14:00:00Z"));
+        assert!(matches!(clock.state, MarketState::Open));
+        assert_eq!(clock.next_change_minutes, 6 * 60);
+
+        // Friday after the close opens again on Monday
+        let clock = market_clock(at("2021-06-11T21:00:00Z"));
+        assert!(matches!(clock.state, MarketState::Closed));
+        assert!(matches!(clock.next_state, MarketState::Open));
+        assert_eq!(
+            clock.next_change_minutes,
+            (at("2021-06-14T13:30:00Z") - at("2021-06-11T21:00:00Z")).num_minutes()
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_file_db() {
+        let root = Path::new("data/test/replay_db");
+        let _ = std::fs::remove_dir_all(root);
+        let mut db = FileDb::new(root);
+        db.add_snapshot(snapshot_at("2021-06-10T14:00:00Z", 10.0))
+            .unwrap();
+        db.add_snapshot(snapshot_at("2021-06-10T15:00:00Z", 11.0))
+            .unwrap();
+
+        let provider = ReplayProvider::open(root, None, 1.0).unwrap();
+        assert_eq!(provider.quote("tst").await.unwrap().last, Some(10.0));
+
+        let provider = ReplayProvider::open(root, Some(at("2021-06-10T15:30:00Z")), 1.0).unwrap();
+        let snapshot = provider.option_snapshot("TST").await.unwrap();
+        assert_eq!(snapshot.spot(), Some(11.0));
+        assert_eq!(snapshot.provider.as_deref(), Some(PROVIDER_NAME));
+        assert!(snapshot.timestamp >= at("2021-06-10T15:30:00Z"));
+
+        let candles = provider
+            .time_and_sales("TST", OhlcInterval::OneMinute)
+            .await
+            .unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].close, 11.0);
+        assert!(provider.quote("NOPE").await.is_err());
+
+        assert!(check_distinct(root, &root.join(".")).is_err());
+        assert!(check_distinct(root, Path::new("data/test/replay_db_target")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn replay_files() {
+        let dir = Path::new("data/test/replay_files");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let snapshots = vec![
+            snapshot_at("2021-06-10T15:00:00Z", 11.0),
+            snapshot_at("2021-06-10T14:00:00Z", 10.0),
+        ];
+        std::fs::write(
+            dir.join("tst.json"),
+            serde_json::to_string(&snapshots).unwrap(),
+        )
+        .unwrap();
+
+        let provider = ReplayProvider::open(dir, Some(at("2021-06-10T14:30:00Z")), 1.0).unwrap();
+        assert_eq!(provider.quote("TST").await.unwrap().last, Some(10.0));
+        assert_eq!(
+            provider.option_expirations("TST").await.unwrap(),
+            vec!["tomorrow"]
+        );
+    }
+}
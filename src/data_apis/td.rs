@@ -1,14 +1,49 @@
+mod hours;
 mod options;
+mod price_history;
+mod quote;
 
+pub use hours::*;
 pub use options::*;
+pub use price_history::*;
+pub use quote::*;
 
-use std::path::Path;
+use std::{path::Path, sync::OnceLock, time::Duration};
 
-use chrono::Utc;
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+
+use super::{
+    http::{HttpClient, RateLimiter, RetryPolicy},
+    DataError, MarketDataProvider,
+};
+use crate::types::{self, OhlcInterval};
+use crate::utils;
+use crate::{data_apis::error::parse_json, math::pricing::PricingConfig};
 
 const DATA_PATH: &str = "data";
 const API_KEY_ENV: &str = "API_KEY";
+const MARKET_DATA_URL: &str = "https://api.tdameritrade.com/v1/marketdata";
 const OPTION_CHAIN_URL: &str = "https://api.tdameritrade.com/v1/marketdata/chains";
+const PROVIDER_NAME: &str = "td";
+/// TD Ameritrade's quota for unauthenticated requests
+const REQUESTS_PER_MINUTE: u32 = 120;
+
+fn api_key() -> Result<String, DataError> {
+    std::env::var(API_KEY_ENV)
+        .map_err(|_| DataError::Auth(format!("{} must be set to use TD Ameritrade", API_KEY_ENV)))
+}
+
+/// The client shared by every TD request.
+fn client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        HttpClient::new(
+            RateLimiter::new(REQUESTS_PER_MINUTE, Duration::from_secs(60)),
+            RetryPolicy::default(),
+        )
+    })
+}
 
 pub async fn get_option_chain(symbol: &str, force_download: bool) -> anyhow::Result<OptionChain> {
     let file_date = Utc::now().format("%Y%m%d").to_string();
@@ -18,7 +53,7 @@ pub async fn get_option_chain(symbol: &str, force_download: bool) -> anyhow::Res
     if data_path.exists() && !force_download {
         log::info!("Fetching cached data for {}", symbol);
 
-        let json = std::fs::read_to_string(&data_path)?;
+        let json = std::fs::read_to_string(data_path)?;
         Ok(serde_json::from_str(&json)?)
     } else {
         log::info!("Downloading today's data for {}", symbol);
@@ -27,20 +62,214 @@ pub async fn get_option_chain(symbol: &str, force_download: bool) -> anyhow::Res
 }
 
 async fn download_data(symbol: &str, data_path: &Path) -> anyhow::Result<OptionChain> {
-    let api_key = std::env::var(API_KEY_ENV)?;
-    let params = format!("apikey={}&symbol={}", api_key, symbol);
-    let url = format!("{}?{}", OPTION_CHAIN_URL, params);
-
-    let body = reqwest::get(url).await?.text().await?;
+    let (result, body) = fetch_option_chain(OPTION_CHAIN_URL, symbol).await?;
 
     std::fs::create_dir_all(DATA_PATH)?;
-    std::fs::write(&data_path, &body)?;
+    std::fs::write(data_path, &body)?;
 
-    let result: OptionChain = serde_json::from_str(&body)?;
+    Ok(result)
+}
 
+/// Downloads the chain for `symbol` from `url`, returning it along with the
+/// raw body.
+async fn fetch_option_chain(url: &str, symbol: &str) -> anyhow::Result<(OptionChain, String)> {
+    let api_key = api_key()?;
+    let params = format!("apikey={}&symbol={}&includeQuotes=TRUE", api_key, symbol);
+    let url = format!("{}?{}", url, params);
+
+    let body = client().get(&url, &[]).await?;
+
+    let result: OptionChain = parse_json(&body)?;
+
+    // TD reports unknown symbols as a failed request
     if result.status == "FAILED" {
-        anyhow::bail!("Data request failed");
+        return Err(DataError::UnknownSymbol(symbol.to_uppercase()).into());
     }
 
-    Ok(result)
+    Ok((result, body))
+}
+
+/// Serves live data only: unlike [`get_option_chain`], every call downloads
+/// the chain again instead of reading the day's cached file.
+#[derive(Clone, Debug)]
+pub struct TdProvider {
+    /// Inputs used when computing the greeks TD omits
+    pub pricing: PricingConfig,
+    pub chain_url: String,
+}
+
+impl Default for TdProvider {
+    fn default() -> Self {
+        Self::new(PricingConfig::default())
+    }
+}
+
+impl TdProvider {
+    pub fn new(pricing: PricingConfig) -> Self {
+        Self {
+            pricing,
+            chain_url: OPTION_CHAIN_URL.to_string(),
+        }
+    }
+
+    /// Reads the pricing inputs, see [`PricingConfig::from_env`].
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self::new(PricingConfig::from_env()?))
+    }
+
+    async fn download_chain(&self, symbol: &str) -> anyhow::Result<OptionChain> {
+        log::info!("Downloading the option chain for {}", symbol);
+        Ok(fetch_option_chain(&self.chain_url, symbol).await?.0)
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for TdProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn quote(&self, symbol: &str) -> anyhow::Result<types::Quote> {
+        Ok(get_quote(symbol).await?.into())
+    }
+
+    async fn clock(&self) -> anyhow::Result<types::Clock> {
+        get_clock().await
+    }
+
+    async fn option_expirations(&self, symbol: &str) -> anyhow::Result<Vec<String>> {
+        let option_chain = self.download_chain(symbol).await?;
+
+        let mut expirations: Vec<NaiveDate> = option_chain
+            .call_exp_date_map
+            .keys()
+            .chain(option_chain.put_exp_date_map.keys())
+            .map(|key| parse_expiration_key(key))
+            .collect::<anyhow::Result<_>>()?;
+        expirations.sort();
+        expirations.dedup();
+
+        Ok(expirations.iter().map(|d| d.to_string()).collect())
+    }
+
+    async fn option_chain(&self, symbol: &str) -> anyhow::Result<Vec<types::OptionInfo>> {
+        let option_chain = self.download_chain(symbol).await?;
+        let pricing = self.pricing.clone();
+        utils::blocking(move || option_chain.into_crate_type(&pricing)).await
+    }
+
+    async fn option_snapshot(&self, symbol: &str) -> anyhow::Result<types::OptionSnapshot> {
+        let timestamp = Utc::now();
+        let option_chain = self.download_chain(symbol).await?;
+        let quote = option_chain.quote();
+        let pricing = self.pricing.clone();
+        let options = utils::blocking(move || option_chain.into_crate_type(&pricing)).await?;
+
+        Ok(types::OptionSnapshot::new(
+            timestamp,
+            symbol,
+            PROVIDER_NAME,
+            Some(quote),
+            options,
+        ))
+    }
+
+    async fn time_and_sales(
+        &self,
+        symbol: &str,
+        interval: OhlcInterval,
+    ) -> anyhow::Result<Vec<types::Ohlc>> {
+        let candles = get_price_history(symbol, interval).await?;
+        Ok(candles
+            .into_iter()
+            .map(|candle| (interval, candle).into())
+            .collect())
+    }
+}
+
+/// Expiration map keys have the form `2021-06-18:7`, the date followed by the
+/// number of days to expiration.
+fn parse_expiration_key(key: &str) -> anyhow::Result<NaiveDate> {
+    let date = key
+        .split(':')
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Invalid expiration: {}", key))?;
+    Ok(date.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use serde_json::json;
+    use tokio::sync::Mutex;
+    use warp::Filter;
+
+    use super::*;
+    use crate::{data_apis::Provider, db::FileDb, graphql};
+
+    /// Serves an empty chain for every request, returning the provider
+    /// pointing at it and the count of chains served.
+    fn serve() -> (TdProvider, Arc<AtomicUsize>) {
+        std::env::set_var(API_KEY_ENV, "test-key");
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let routes = warp::path!("chains").map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::json(&json!({
+                "symbol": "TDTEST",
+                "status": "SUCCESS",
+                "underlying": null,
+                "strategy": "SINGLE",
+                "interval": 0.0,
+                "isDelayed": false,
+                "isIndex": false,
+                "daysToExpiration": 0.0,
+                "interestRate": 0.01,
+                "underlyingPrice": 100.0,
+                "volatility": 29.0,
+                "numberOfContracts": 0,
+                "callExpDateMap": {},
+                "putExpDateMap": {}
+            }))
+        });
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let provider = TdProvider {
+            chain_url: format!("http://{}/chains", addr),
+            ..TdProvider::default()
+        };
+        (provider, requests)
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_downloaded() {
+        let (provider, requests) = serve();
+        for _ in 0..2 {
+            let snapshot = provider.option_snapshot("TDTEST").await.unwrap();
+            assert_eq!(snapshot.spot(), Some(100.0));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tda_schema_downloads() {
+        let (provider, requests) = serve();
+        let provider: Provider = Arc::new(provider);
+        let db = Arc::new(Mutex::new(FileDb::new("data/test/td_schema")));
+        let schema = graphql::tda_schema(db, provider, PricingConfig::default());
+
+        for _ in 0..2 {
+            let response = schema
+                .execute(r#"{ optionChain(symbol: "TDTEST") { strike } }"#)
+                .await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Utc};
+use chrono_tz::America::New_York;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data_apis::error::parse_json,
+    types::{self, clock::MarketState},
+};
+
+/// Number of days to look ahead for the next trading session when the market
+/// is closed for the rest of the day.
+const MAX_LOOKAHEAD_DAYS: i64 = 7;
+
+pub async fn get_market_hours(date: NaiveDate) -> anyhow::Result<MarketHours> {
+    let api_key = super::api_key()?;
+    let params = format!("apikey={}&date={}", api_key, date);
+    let url = format!("{}/EQUITY/hours?{}", super::MARKET_DATA_URL, params);
+
+    let body = super::client().get(&url, &[]).await?;
+
+    let response: HoursResponse = parse_json(&body)?;
+
+    response
+        .equity
+        .into_iter()
+        .next()
+        .map(|(_, hours)| hours)
+        .ok_or_else(|| anyhow::anyhow!("No market hours for {}", date))
+}
+
+pub async fn get_clock() -> anyhow::Result<types::Clock> {
+    let now = Utc::now();
+    let today = now.with_timezone(&New_York).date_naive();
+    let hours = get_market_hours(today).await?;
+
+    if let Some(clock) = clock_during_day(now, &hours) {
+        return Ok(clock);
+    }
+
+    for days_ahead in 1..=MAX_LOOKAHEAD_DAYS {
+        let date = today + Duration::days(days_ahead);
+        let next_hours = get_market_hours(date).await?;
+        if let Some((next_state, session)) = next_hours.sessions().into_iter().next() {
+            return Ok(clock(
+                now,
+                &hours,
+                MarketState::Closed,
+                next_state,
+                session.start,
+            ));
+        }
+    }
+
+    anyhow::bail!(
+        "No trading sessions in the next {} days",
+        MAX_LOOKAHEAD_DAYS
+    )
+}
+
+/// Builds a clock from a single day's hours, or `None` if there are no more
+/// sessions that day.
+fn clock_during_day(now: DateTime<Utc>, hours: &MarketHours) -> Option<types::Clock> {
+    let sessions = hours.sessions();
+
+    for (i, (state, session)) in sessions.iter().enumerate() {
+        if now < session.start {
+            return Some(clock(
+                now,
+                hours,
+                MarketState::Closed,
+                state.clone(),
+                session.start,
+            ));
+        }
+        if now < session.end {
+            let next_state = sessions
+                .get(i + 1)
+                .filter(|(_, next)| next.start == session.end)
+                .map(|(next_state, _)| next_state.clone())
+                .unwrap_or(MarketState::Closed);
+            return Some(clock(now, hours, state.clone(), next_state, session.end));
+        }
+    }
+
+    None
+}
+
+fn clock(
+    now: DateTime<Utc>,
+    hours: &MarketHours,
+    state: MarketState,
+    next_state: MarketState,
+    next_change: DateTime<FixedOffset>,
+) -> types::Clock {
+    let next_change_minutes = next_change.signed_duration_since(now).num_minutes().max(0);
+
+    types::Clock {
+        timestamp: now.timestamp() as u64,
+        date: hours.date.clone(),
+        description: format!("Market is {}. {} at {}", state, next_state, next_change),
+        state,
+        next_state,
+        next_change_minutes,
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct HoursResponse {
+    #[serde(default)]
+    equity: HashMap<String, MarketHours>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketHours {
+    pub date: String,
+    pub market_type: String,
+    pub is_open: bool,
+    pub session_hours: Option<SessionHours>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionHours {
+    #[serde(default)]
+    pub pre_market: Vec<Session>,
+    #[serde(default)]
+    pub regular_market: Vec<Session>,
+    #[serde(default)]
+    pub post_market: Vec<Session>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl MarketHours {
+    /// Every session of the day in chronological order.
+    pub fn sessions(&self) -> Vec<(MarketState, Session)> {
+        let session_hours = match (&self.session_hours, self.is_open) {
+            (Some(s), true) => s,
+            _ => return Vec::new(),
+        };
+
+        let mut sessions: Vec<(MarketState, Session)> = session_hours
+            .pre_market
+            .iter()
+            .map(|s| (MarketState::PreMarket, s.clone()))
+            .chain(
+                session_hours
+                    .regular_market
+                    .iter()
+                    .map(|s| (MarketState::Open, s.clone())),
+            )
+            .chain(
+                session_hours
+                    .post_market
+                    .iter()
+                    .map(|s| (MarketState::PostMarket, s.clone())),
+            )
+            .collect();
+        sessions.sort_by_key(|(_, s)| s.start);
+
+        sessions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::at;
+
+    const HOURS: &str = r#"{
+        "date": "2021-06-10",
+        "marketType": "EQUITY",
+        "isOpen": true,
+        "sessionHours": {
+            "preMarket": [{ "start": "2021-06-10T07:00:00-04:00", "end": "2021-06-10T09:30:00-04:00" }],
+            "regularMarket": [{ "start": "2021-06-10T09:30:00-04:00", "end": "2021-06-10T16:00:00-04:00" }],
+            "postMarket": [{ "start": "2021-06-10T16:00:00-04:00", "end": "2021-06-10T20:00:00-04:00" }]
+        }
+    }"#;
+
+    #[test]
+    fn clock_from_hours() {
+        let hours: MarketHours = serde_json::from_str(HOURS).unwrap();
+
+        let clock = clock_during_day(at("2021-06-10T06:00:00-04:00"), &hours).unwrap();
+        assert!(matches!(clock.state, MarketState::Closed));
+        assert!(matches!(clock.next_state, MarketState::PreMarket));
+        assert_eq!(clock.next_change_minutes, 60);
+
+        let clock = clock_during_day(at("2021-06-10T10:00:00-04:00"), &hours).unwrap();
+        assert!(matches!(clock.state, MarketState::Open));
+        assert!(matches!(clock.next_state, MarketState::PostMarket));
+        assert_eq!(clock.next_change_minutes, 360);
+
+        let clock = clock_during_day(at("2021-06-10T19:00:00-04:00"), &hours).unwrap();
+        assert!(matches!(clock.state, MarketState::PostMarket));
+        assert!(matches!(clock.next_state, MarketState::Closed));
+
+        assert!(clock_during_day(at("2021-06-10T21:00:00-04:00"), &hours).is_none());
+    }
+}
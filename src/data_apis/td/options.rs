@@ -1,8 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
+use chrono::{TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::deserialize_f64_with_nan;
+use crate::{
+    math::{expiry, pricing::PricingConfig},
+    types,
+    utils::deserialize_f64_with_nan,
+};
+
+/// TD reports unavailable greeks with this sentinel instead of omitting them.
+const MISSING_VALUE: f64 = -999.0;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -192,3 +200,285 @@ pub struct OptionDeliverables {
     pub deliverable_units: String,
     pub currency_type: String,
 }
+
+impl OptionChain {
+    /// The underlying quote returned with the chain. Without it, only the
+    /// price the chain was computed against is known.
+    pub fn quote(&self) -> types::Quote {
+        match &self.underlying {
+            Some(underlying) => types::Quote {
+                symbol: underlying.symbol.clone(),
+                last: finite(underlying.last),
+                change: finite(underlying.change),
+                volume: finite_or_zero(underlying.total_volume) as u64,
+                open: finite(underlying.open_price),
+                high: finite(underlying.high_price),
+                low: finite(underlying.low_price),
+                close: finite(underlying.close),
+                bid: finite(underlying.bid),
+                ask: finite(underlying.ask),
+            },
+            None => types::Quote {
+                symbol: self.symbol.clone(),
+                last: finite(self.underlying_price),
+                change: None,
+                volume: 0,
+                open: None,
+                high: None,
+                low: None,
+                close: None,
+                bid: None,
+                ask: None,
+            },
+        }
+    }
+
+    pub fn into_crate_type(
+        self,
+        pricing: &PricingConfig,
+    ) -> anyhow::Result<Vec<types::OptionInfo>> {
+        let symbol = self.symbol;
+        let underlying_price = self.underlying_price;
+
+        let mut result = Vec::new();
+        for exp_date_map in [self.call_exp_date_map, self.put_exp_date_map] {
+            for (expiration, strikes) in exp_date_map {
+                let expiration_date = super::parse_expiration_key(&expiration)?.to_string();
+                for option in strikes.into_values().flatten() {
+                    result.push(option.into_crate_type(
+                        &symbol,
+                        &expiration_date,
+                        underlying_price,
+                        pricing,
+                    )?);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl OptionData {
+    pub fn into_crate_type(
+        self,
+        symbol: &str,
+        expiration_date: &str,
+        current_price: f64,
+        pricing: &PricingConfig,
+    ) -> anyhow::Result<types::OptionInfo> {
+        let option_type = match self.put_call {
+            PutOrCall::Call => types::OptionType::Call,
+            PutOrCall::Put => types::OptionType::Put,
+        };
+
+        // TD quotes volatility as a percentage
+        let sigma = Some(self.volatility / 100.0).filter(|v| v.is_finite() && *v > 0.0);
+
+        let timestamp = Utc
+            .timestamp_millis_opt(self.quote_time_in_long as i64)
+            .single()
+            .filter(|_| self.quote_time_in_long > 0)
+            .unwrap_or_else(Utc::now);
+        let settlement_type = types::SettlementType::from_str(&self.settlement_type).ok();
+
+        let expiration_time = expiry::time_to_expiry(
+            symbol,
+            expiration_date,
+            settlement_type,
+            timestamp,
+            pricing.day_count,
+        )?;
+
+        let underlying_type = if self.is_index_option == Some(true) {
+            types::UnderlyingType::Index
+        } else {
+            types::UnderlyingType::infer(symbol)
+        };
+
+        let mut option = types::OptionInfo {
+            timestamp: timestamp.to_rfc3339(),
+            symbol: symbol.to_string(),
+            option_type,
+            strike: self.strike_price,
+            expiration_date: expiration_date.to_string(),
+            open_interest: finite_or_zero(self.open_interest) as u64,
+            volume: finite_or_zero(self.total_volume) as u64,
+            greeks: None,
+            last: self.last_price,
+            change: Some(self.net_change).filter(|c| c.is_finite()),
+            open: Some(self.open_price).filter(|p| p.is_finite()),
+            high: Some(self.high_price).filter(|p| p.is_finite()),
+            low: Some(self.low_price).filter(|p| p.is_finite()),
+            close: Some(self.close_price).filter(|p| p.is_finite()),
+            bid_iv: None,
+            mid_iv: sigma,
+            ask_iv: None,
+            smv_vol: None,
+            bid: self.bid_price,
+            ask: self.ask_price,
+            multiplier: Some(self.multiplier).filter(|m| m.is_finite()),
+            settlement_type,
+            days_to_expiration: Some(self.days_to_expiration),
+            underlying_type: Some(underlying_type),
+        };
+
+        // TD only reports the volatility of the mark
+        if expiration_time > 0.0 {
+            let implied = pricing.quote_volatility(symbol, &option, expiration_time, current_price);
+            option.bid_iv = implied.bid;
+            option.mid_iv = sigma.or(implied.mid);
+            option.ask_iv = implied.ask;
+        }
+
+        let model = option
+            .mid_iv
+            .filter(|_| expiration_time > 0.0)
+            .map(|sigma| pricing.greeks(symbol, &option, sigma, expiration_time, current_price));
+        // TD reports the first-order greeks and gamma, the rest come from the
+        // model
+        option.greeks = if is_valid(self.delta) && is_valid(self.gamma) {
+            Some(types::Greeks {
+                delta: self.delta,
+                gamma: self.gamma,
+                theta: self.theta,
+                vega: self.vega,
+                rho: self.rho,
+                ..model.unwrap_or_default()
+            })
+        } else {
+            model
+        };
+
+        Ok(option)
+    }
+}
+
+fn is_valid(greek: f64) -> bool {
+    greek.is_finite() && greek != MISSING_VALUE
+}
+
+fn finite(n: f64) -> Option<f64> {
+    Some(n).filter(|n| n.is_finite())
+}
+
+fn finite_or_zero(n: f64) -> f64 {
+    if n.is_finite() {
+        n.max(0.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTION_CHAIN: &str = r#"{
+        "symbol": "TST",
+        "status": "SUCCESS",
+        "underlying": null,
+        "strategy": "SINGLE",
+        "interval": 0.0,
+        "isDelayed": true,
+        "isIndex": false,
+        "daysToExpiration": 0.0,
+        "interestRate": 0.1,
+        "underlyingPrice": 10.0,
+        "volatility": 29.0,
+        "numberOfContracts": 2,
+        "callExpDateMap": {
+            "2021-06-18:7": { "9.0": [OPTION_CALL] }
+        },
+        "putExpDateMap": {
+            "2021-06-18:7": { "9.0": [OPTION_PUT] }
+        }
+    }"#;
+
+    const OPTION: &str = r#"{
+        "putCall": "PUT_CALL",
+        "symbol": "TST_061821C9",
+        "description": "TST Jun 18 2021 9 Call",
+        "exchangeName": "OPR",
+        "bidPrice": 1.0,
+        "askPrice": 1.2,
+        "lastPrice": 1.1,
+        "markPrice": 1.1,
+        "bidSize": 1,
+        "askSize": 1,
+        "lastSize": 0,
+        "highPrice": 1.3,
+        "lowPrice": 0.9,
+        "openPrice": 0.0,
+        "closePrice": 1.0,
+        "totalVolume": 12,
+        "quoteTimeInLong": 1623441599000,
+        "tradeTimeInLong": 1623441599000,
+        "netChange": 0.1,
+        "volatility": 50.0,
+        "delta": DELTA,
+        "gamma": 0.1015,
+        "theta": -0.0035,
+        "vega": 0.025,
+        "rho": 0.01,
+        "timeValue": 0.1,
+        "openInterest": 34,
+        "isInTheMoney": true,
+        "theoreticalOptionValue": 1.1,
+        "theoreticalVolatility": 29.0,
+        "isMini": false,
+        "isNonStandard": false,
+        "optionDeliverablesList": null,
+        "strikePrice": 9.0,
+        "expirationDate": 1624046400000,
+        "daysToExpiration": 7,
+        "expirationType": "R",
+        "lastTradingDay": 1624032000000,
+        "multiplier": 100.0,
+        "settlementType": "P",
+        "deliverableNote": "",
+        "isIndexOption": null,
+        "percentChange": 10.0,
+        "markChange": 0.1,
+        "markPercentChange": 10.0
+    }"#;
+
+    #[test]
+    fn option_chain_into_crate_type() {
+        let call = OPTION
+            .replace("PUT_CALL", "CALL")
+            .replace("DELTA", "0.6828");
+        let put = OPTION
+            .replace("PUT_CALL", "PUT")
+            .replace("DELTA", "\"NaN\"");
+        let json = OPTION_CHAIN
+            .replace("OPTION_CALL", &call)
+            .replace("OPTION_PUT", &put);
+
+        let option_chain: OptionChain = serde_json::from_str(&json).unwrap();
+        assert_eq!(option_chain.quote().last, Some(10.0));
+        let mut options = option_chain
+            .into_crate_type(&PricingConfig::default())
+            .unwrap();
+        options.sort_by_key(|o| o.option_type == types::OptionType::Put);
+
+        let call = &options[0];
+        assert_eq!(call.symbol, "TST");
+        assert_eq!(call.option_type, types::OptionType::Call);
+        assert_eq!(call.expiration_date, "2021-06-18");
+        assert_eq!(call.open_interest, 34);
+        assert_eq!(call.volume, 12);
+        assert_eq!(call.multiplier, Some(100.0));
+        assert_eq!(call.settlement_type, Some(types::SettlementType::PM));
+        assert_eq!(call.days_to_expiration, Some(7));
+        assert_eq!(call.mid_iv, Some(0.5));
+        assert_eq!(call.delta(), 0.6828);
+        assert_eq!(call.gamma(), 0.1015);
+
+        // TD's greeks are missing, so they come from the model
+        let put = &options[1];
+        assert_eq!(put.option_type, types::OptionType::Put);
+        assert!(put.delta() < 0.0);
+        assert!(put.bid_iv.unwrap() < put.ask_iv.unwrap());
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::{TimeZone, Utc};
+use chrono_tz::America::New_York;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data_apis::error::parse_json,
+    types::{self, OhlcInterval},
+};
+
+pub async fn get_price_history(
+    symbol: &str,
+    interval: OhlcInterval,
+) -> anyhow::Result<Vec<Candle>> {
+    let frequency = match interval {
+        OhlcInterval::Tick => anyhow::bail!("TD does not provide tick data"),
+        OhlcInterval::OneMinute => 1,
+        OhlcInterval::FiveMinute => 5,
+        OhlcInterval::FifteenMinute => 15,
+    };
+
+    let api_key = super::api_key()?;
+    let params = format!(
+        "apikey={}&periodType=day&period=3&frequencyType=minute&frequency={}",
+        api_key, frequency
+    );
+    let url = format!(
+        "{}/{}/pricehistory?{}",
+        super::MARKET_DATA_URL,
+        symbol,
+        params
+    );
+
+    let body = super::client().get(&url, &[]).await?;
+
+    let price_history: PriceHistoryResponse = parse_json(&body)?;
+
+    Ok(price_history.candles)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Candle {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+    /// Milliseconds since the epoch
+    pub datetime: i64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PriceHistoryResponse {
+    #[serde(default)]
+    candles: Vec<Candle>,
+}
+
+impl From<(OhlcInterval, Candle)> for types::Ohlc {
+    fn from((interval, candle): (OhlcInterval, Candle)) -> Self {
+        let time = Utc
+            .timestamp_millis_opt(candle.datetime)
+            .single()
+            .map(|t| {
+                t.with_timezone(&New_York)
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+
+        Self {
+            interval,
+            time,
+            price: candle.close,
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            volume: candle.volume,
+            vwap: None,
+        }
+    }
+}
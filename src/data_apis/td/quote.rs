@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data_apis::{error::parse_json, DataError},
+    types,
+};
+
+pub async fn get_quote(symbol: &str) -> anyhow::Result<Quote> {
+    let api_key = super::api_key()?;
+    let url = format!(
+        "{}/{}/quotes?apikey={}",
+        super::MARKET_DATA_URL,
+        symbol,
+        api_key
+    );
+
+    let body = super::client().get(&url, &[]).await?;
+
+    let mut quotes: HashMap<String, Quote> = parse_json(&body)?;
+
+    quotes
+        .remove(symbol)
+        .or_else(|| quotes.into_iter().next().map(|(_, q)| q))
+        .ok_or_else(|| DataError::UnknownSymbol(symbol.to_uppercase()).into())
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quote {
+    pub symbol: String,
+    pub description: Option<String>,
+    pub bid_price: Option<f64>,
+    pub ask_price: Option<f64>,
+    pub last_price: Option<f64>,
+    pub open_price: Option<f64>,
+    pub high_price: Option<f64>,
+    pub low_price: Option<f64>,
+    pub close_price: Option<f64>,
+    pub net_change: Option<f64>,
+    #[serde(default)]
+    pub total_volume: u64,
+}
+
+impl From<Quote> for types::Quote {
+    fn from(quote: Quote) -> Self {
+        Self {
+            symbol: quote.symbol,
+            last: quote.last_price,
+            change: quote.net_change,
+            volume: quote.total_volume,
+            open: quote.open_price,
+            high: quote.high_price,
+            low: quote.low_price,
+            close: quote.close_price,
+            bid: quote.bid_price,
+            ask: quote.ask_price,
+        }
+    }
+}
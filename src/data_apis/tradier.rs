@@ -3,6 +3,8 @@ mod get_option_chain;
 mod get_option_expirations;
 mod get_quote;
 mod get_time_and_sales;
+#[cfg(test)]
+pub mod mock;
 
 pub use get_clock::get_clock;
 pub use get_option_chain::get_option_chain;
@@ -10,5 +12,148 @@ pub use get_option_expirations::get_option_expirations;
 pub use get_quote::get_quote;
 pub use get_time_and_sales::get_time_and_sales;
 
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{
+    http::{HttpClient, RateLimiter, RetryPolicy},
+    DataError, MarketDataProvider,
+};
+use crate::{
+    math::pricing::PricingConfig,
+    types::{Clock, Ohlc, OhlcInterval, OptionInfo, OptionSnapshot, Quote},
+};
+
 const ACCESS_TOKEN_ENV: &str = "ACCESS_TOKEN";
+const BASE_URL_ENV: &str = "TRADIER_BASE_URL";
 const BASE_URL: &str = "https://api.tradier.com/v1";
+const PROVIDER_NAME: &str = "tradier";
+/// Tradier's market data quota
+const REQUESTS_PER_MINUTE: u32 = 120;
+
+/// Where to reach the Tradier API and how to authenticate with it. Clones
+/// share one connection pool and rate limit.
+#[derive(Clone, Debug)]
+pub struct TradierConfig {
+    pub base_url: String,
+    pub access_token: Option<String>,
+    /// Inputs used when recomputing greeks
+    pub pricing: PricingConfig,
+    http: HttpClient,
+}
+
+impl Default for TradierConfig {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            access_token: None,
+            pricing: PricingConfig::default(),
+            http: http_client(),
+        }
+    }
+}
+
+impl TradierConfig {
+    pub fn new(base_url: impl Into<String>, access_token: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            access_token: Some(access_token.into()),
+            pricing: PricingConfig::default(),
+            http: http_client(),
+        }
+    }
+
+    /// Reads `ACCESS_TOKEN`, the pricing inputs and, to point at something
+    /// other than the production API, `TRADIER_BASE_URL`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            base_url: std::env::var(BASE_URL_ENV).unwrap_or_else(|_| BASE_URL.to_string()),
+            access_token: std::env::var(ACCESS_TOKEN_ENV).ok(),
+            pricing: PricingConfig::from_env()?,
+            http: http_client(),
+        })
+    }
+
+    /// Sends a GET request to `endpoint`, relative to the base URL, and
+    /// returns the response body.
+    async fn get(&self, endpoint: &str) -> anyhow::Result<String> {
+        let access_token = self.access_token.as_ref().ok_or_else(|| {
+            DataError::Auth(format!("{} must be set to use Tradier", ACCESS_TOKEN_ENV))
+        })?;
+        let url = format!("{}/{}", self.base_url.trim_end_matches('/'), endpoint);
+
+        let authorization = format!("Bearer {}", access_token);
+
+        self.http
+            .get(
+                &url,
+                &[
+                    ("Accept", "application/json"),
+                    ("Authorization", &authorization),
+                ],
+            )
+            .await
+    }
+}
+
+fn http_client() -> HttpClient {
+    HttpClient::new(
+        RateLimiter::new(REQUESTS_PER_MINUTE, Duration::from_secs(60)),
+        RetryPolicy::default(),
+    )
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TradierProvider {
+    config: TradierConfig,
+}
+
+impl TradierProvider {
+    pub fn new(config: TradierConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self::new(TradierConfig::from_env()?))
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for TradierProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn quote(&self, symbol: &str) -> anyhow::Result<Quote> {
+        Ok(get_quote(&self.config, symbol).await?.into())
+    }
+
+    async fn clock(&self) -> anyhow::Result<Clock> {
+        get_clock(&self.config).await
+    }
+
+    async fn option_expirations(&self, symbol: &str) -> anyhow::Result<Vec<String>> {
+        get_option_expirations(&self.config, symbol).await
+    }
+
+    async fn option_chain(&self, symbol: &str) -> anyhow::Result<Vec<OptionInfo>> {
+        Ok(get_option_chain(&self.config, symbol).await?.options)
+    }
+
+    async fn option_snapshot(&self, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+        get_option_chain(&self.config, symbol).await
+    }
+
+    async fn time_and_sales(
+        &self,
+        symbol: &str,
+        interval: OhlcInterval,
+    ) -> anyhow::Result<Vec<Ohlc>> {
+        let time_and_sales = get_time_and_sales(&self.config, symbol, interval).await?;
+        Ok(time_and_sales
+            .into_iter()
+            .map(|ts| (interval, ts).into())
+            .collect())
+    }
+}
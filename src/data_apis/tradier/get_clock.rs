@@ -1,29 +1,15 @@
 use std::convert::{TryFrom, TryInto};
 
-use crate::types;
+use super::TradierConfig;
+use crate::{data_apis::error::parse_json, types};
 use serde::{Deserialize, Serialize};
 
-pub async fn get_clock() -> anyhow::Result<types::Clock> {
-    let access_token = std::env::var(super::ACCESS_TOKEN_ENV)?;
-    let url = format!("{}/markets/clock", super::BASE_URL);
+pub async fn get_clock(config: &TradierConfig) -> anyhow::Result<types::Clock> {
+    let body = config.get("markets/clock").await?;
 
-    let client = reqwest::Client::new();
-    let body = client
-        .get(url)
-        .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?
-        .text()
-        .await?;
+    let clock: ClockResponse = parse_json(&body)?;
 
-    let clock: ClockResponse = serde_json::from_str(&body).map_err(|e| {
-        log::error!("{}", e);
-        log::error!("{}", &body);
-        e
-    })?;
-
-    Ok(clock.clock.try_into()?)
+    clock.clock.try_into()
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1,47 +1,74 @@
 use std::str::FromStr;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 
-use crate::{math::bs, types};
+use super::TradierConfig;
+use crate::{
+    data_apis::error::parse_json,
+    math::{expiry, pricing::PricingConfig},
+    types, utils,
+};
 
-pub async fn get_option_chain(symbol: &str) -> anyhow::Result<Vec<types::OptionInfo>> {
-    let access_token = std::env::var(super::ACCESS_TOKEN_ENV)?;
+/// Chains are fetched a few expirations at a time, within the rate limit
+const MAX_CONCURRENT_EXPIRATIONS: usize = 4;
+/// Tradier refreshes greeks hourly. Ones this much older than the option's
+/// quotes are replaced by volatilities implied from the quotes.
+const STALE_GREEKS_HOURS: i64 = 2;
 
-    let expirations = super::get_option_expirations(symbol).await?;
-    let quote = super::get_quote(symbol).await?;
+/// Fetches every expiration of `symbol` along with the quote used to compute
+/// the greeks.
+pub async fn get_option_chain(
+    config: &TradierConfig,
+    symbol: &str,
+) -> anyhow::Result<types::OptionSnapshot> {
+    let timestamp = Utc::now();
+
+    let expirations = super::get_option_expirations(config, symbol).await?;
+    let quote: types::Quote = super::get_quote(config, symbol).await?.into();
     let current_price = quote.last.unwrap_or(0.0);
 
-    let mut option_info = Vec::new();
-
-    for expiration in expirations {
-        let params = format!("symbol={}&expiration={}&greeks=true", symbol, expiration);
-        let url = format!("{}/markets/options/chains?{}", super::BASE_URL, params);
-
-        let client = reqwest::Client::new();
-        let body = client
-            .get(url)
-            .header("Accept", "application/json")
-            .header("Authorization", format!("Bearer {}", access_token))
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        let response = serde_json::from_str::<OptionChainResponse>(&body).map_err(|e| {
-            log::error!("{}", e);
-            log::error!("{}", &body);
-            e
-        })?;
-        option_info.extend(response.options.option);
-    }
+    let chains: Vec<Vec<OptionInfo>> = stream::iter(expirations)
+        .map(|expiration| get_expiration(config, symbol, expiration))
+        .buffered(MAX_CONCURRENT_EXPIRATIONS)
+        .try_collect()
+        .await?;
 
-    let mut result = Vec::new();
-    for oi in option_info {
-        result.push(oi.into_crate_type(current_price).await?);
-    }
+    // Under an American model every option's greeks take a few trees
+    let pricing = config.pricing.clone();
+    let result = utils::blocking(move || {
+        chains
+            .into_iter()
+            .flatten()
+            .map(|oi| oi.into_crate_type(timestamp, current_price, &pricing))
+            .collect()
+    })
+    .await?;
 
-    Ok(result)
+    Ok(types::OptionSnapshot::new(
+        timestamp,
+        symbol,
+        super::PROVIDER_NAME,
+        Some(quote),
+        result,
+    ))
+}
+
+async fn get_expiration(
+    config: &TradierConfig,
+    symbol: &str,
+    expiration: String,
+) -> anyhow::Result<Vec<OptionInfo>> {
+    let params = format!("symbol={}&expiration={}&greeks=true", symbol, expiration);
+    let body = config
+        .get(&format!("markets/options/chains?{}", params))
+        .await?;
+
+    let response = parse_json::<OptionChainResponse>(&body)?;
+
+    // An expiration without any listed options comes back as null
+    Ok(response.options.map(|o| o.option).unwrap_or_default())
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -101,7 +128,7 @@ pub struct OptionGreeks {
 
 #[derive(Clone, Debug, Deserialize)]
 struct OptionChainResponse {
-    options: OptionChainResponseInner,
+    options: Option<OptionChainResponseInner>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -110,70 +137,25 @@ struct OptionChainResponseInner {
 }
 
 impl OptionInfo {
-    pub async fn into_crate_type(self, current_price: f64) -> anyhow::Result<types::OptionInfo> {
+    pub fn into_crate_type(
+        self,
+        timestamp: DateTime<Utc>,
+        current_price: f64,
+        pricing: &PricingConfig,
+    ) -> anyhow::Result<types::OptionInfo> {
         let option_type = types::OptionType::from_str(&self.option_type)?;
+        let expiration_time = expiry::time_to_expiry(
+            &self.root_symbol,
+            &self.expiration_date,
+            None,
+            timestamp,
+            pricing.day_count,
+        )?;
 
-        let greeks = match &self.greeks {
-            Some(g) => {
-                let expiration_time = 180.0;
-                let current_time = 0.0;
-
-                let delta = match option_type {
-                    types::OptionType::Call => bs::call_delta,
-                    types::OptionType::Put => bs::put_delta,
-                };
-
-                Some(types::Greeks {
-                    delta: delta(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                    gamma: bs::gamma(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                    theta: bs::theta(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                    vega: bs::vega(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                    rho: g.rho,
-                    vanna: bs::vanna(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                    charm: bs::charm(
-                        g.mid_iv,
-                        expiration_time,
-                        current_time,
-                        current_price,
-                        self.strike,
-                    ),
-                })
-            }
-            None => None,
-        };
+        let underlying_type = types::UnderlyingType::infer(&self.underlying);
 
-        Ok(types::OptionInfo {
-            timestamp: Utc::now().to_rfc3339(),
+        let mut option = types::OptionInfo {
+            timestamp: timestamp.to_rfc3339(),
             symbol: self.root_symbol,
             option_type,
             strike: self.strike,
@@ -186,11 +168,141 @@ impl OptionInfo {
             high: self.high,
             low: self.low,
             close: self.close,
-            greeks,
-            bid_iv: self.greeks.as_ref().map(|g| g.bid_iv),
-            mid_iv: self.greeks.as_ref().map(|g| g.mid_iv),
-            ask_iv: self.greeks.as_ref().map(|g| g.ask_iv),
+            greeks: None,
+            bid_iv: None,
+            mid_iv: None,
+            ask_iv: None,
             smv_vol: self.greeks.as_ref().map(|g| g.smv_vol),
-        })
+            bid: self.bid,
+            ask: self.ask,
+            multiplier: Some(self.contract_size as f64),
+            settlement_type: None,
+            days_to_expiration: None,
+            underlying_type: Some(underlying_type),
+        };
+
+        let quote_time = Utc
+            .timestamp_millis_opt(self.bid_date.max(self.ask_date) as i64)
+            .single();
+        let reported = self
+            .greeks
+            .as_ref()
+            .filter(|g| g.mid_iv.is_finite() && g.mid_iv > 0.0);
+        let implied = match reported {
+            Some(g) if !g.is_stale(quote_time) => None,
+            _ if expiration_time > 0.0 => Some(pricing.quote_volatility(
+                &self.underlying,
+                &option,
+                expiration_time,
+                current_price,
+            ))
+            .filter(|iv| iv.mid.is_some()),
+            _ => None,
+        };
+
+        match (implied, &self.greeks) {
+            (Some(iv), _) => {
+                option.bid_iv = iv.bid;
+                option.mid_iv = iv.mid;
+                option.ask_iv = iv.ask;
+            }
+            (None, Some(g)) => {
+                option.bid_iv = Some(g.bid_iv);
+                option.mid_iv = Some(g.mid_iv);
+                option.ask_iv = Some(g.ask_iv);
+            }
+            (None, None) => {}
+        }
+
+        // Expired contracts have no meaningful greeks
+        if let Some(sigma) = option.mid_iv.filter(|s| *s > 0.0 && expiration_time > 0.0) {
+            option.greeks = Some(pricing.greeks(
+                &self.underlying,
+                &option,
+                sigma,
+                expiration_time,
+                current_price,
+            ));
+        }
+
+        Ok(option)
+    }
+}
+
+impl OptionGreeks {
+    /// Whether the greeks were computed well before the quotes at
+    /// `quote_time` were made.
+    fn is_stale(&self, quote_time: Option<DateTime<Utc>>) -> bool {
+        let updated_at = NaiveDateTime::parse_from_str(&self.updated_at, "%Y-%m-%d %H:%M:%S")
+            .map(|t| Utc.from_utc_datetime(&t));
+        match (updated_at, quote_time) {
+            (Ok(updated_at), Some(quote_time)) => {
+                quote_time - updated_at > Duration::hours(STALE_GREEKS_HOURS)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::bs;
+    use crate::types::at;
+
+    const CHAINS: &str = include_str!("fixtures/chains.json");
+
+    fn option() -> OptionInfo {
+        let chains: serde_json::Value = serde_json::from_str(CHAINS).unwrap();
+        let response: OptionChainResponse =
+            serde_json::from_value(chains["2021-06-18"].clone()).unwrap();
+        response.options.unwrap().option.remove(0)
+    }
+
+    #[test]
+    fn greeks_use_time_to_expiry() {
+        let option = option();
+        let sigma = option.greeks.as_ref().unwrap().mid_iv;
+        let strike = option.strike;
+
+        // A day before the Friday close
+        let converted = option
+            .clone()
+            .into_crate_type(at("2021-06-17T20:00:00Z"), 420.0, &PricingConfig::default())
+            .unwrap();
+        let expected = bs::gamma(sigma, 1.0 / 365.0, 0.0, 420.0, strike, 0.0, 0.0);
+        assert!((converted.gamma() - expected).abs() < 1e-9);
+
+        let expired = option
+            .into_crate_type(at("2021-06-18T20:00:00Z"), 420.0, &PricingConfig::default())
+            .unwrap();
+        assert!(expired.greeks.is_none());
+        assert_eq!(expired.mid_iv, Some(sigma));
+    }
+
+    #[test]
+    fn implied_volatility_fallback() {
+        let now = at("2021-06-17T20:00:00Z");
+        let pricing = PricingConfig::default();
+        let t = 1.0 / 365.0;
+
+        let mut missing = option();
+        missing.greeks = None;
+        let strike = missing.strike;
+        let converted = missing.into_crate_type(now, 420.0, &pricing).unwrap();
+        let sigma = converted.mid_iv.unwrap();
+        let mid = bs::call_price(sigma, t, 0.0, 420.0, strike, 0.0, 0.0);
+        assert!((mid - 12.0).abs() < 1e-6);
+        assert!(converted.bid_iv.unwrap() < sigma && sigma < converted.ask_iv.unwrap());
+        assert!(converted.gamma() > 0.0);
+
+        // Greeks from the day before the quotes are replaced too
+        let mut stale = option();
+        stale.greeks.as_mut().unwrap().updated_at = "2021-06-09 19:59:59".to_string();
+        let converted = stale.into_crate_type(now, 420.0, &pricing).unwrap();
+        assert_eq!(converted.mid_iv, Some(sigma));
+
+        let current = option().into_crate_type(now, 420.0, &pricing).unwrap();
+        assert_eq!(current.mid_iv, Some(0.18));
     }
 }
@@ -1,29 +1,22 @@
 use serde::Deserialize;
 
-pub async fn get_option_expirations(symbol: &str) -> anyhow::Result<Vec<String>> {
-    let access_token = std::env::var(super::ACCESS_TOKEN_ENV)?;
-    let params = format!("symbol={}&includeAllRoots=true", symbol);
-    let url = format!("{}/markets/options/expirations?{}", super::BASE_URL, params);
+use super::TradierConfig;
+use crate::data_apis::{error::parse_json, DataError};
 
-    let client = reqwest::Client::new();
-    let body = client
-        .get(url)
-        .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?
-        .text()
+pub async fn get_option_expirations(
+    config: &TradierConfig,
+    symbol: &str,
+) -> anyhow::Result<Vec<String>> {
+    let params = format!("symbol={}&includeAllRoots=true", symbol);
+    let body = config
+        .get(&format!("markets/options/expirations?{}", params))
         .await?;
 
-    let expirations: ExpirationResponse = serde_json::from_str(&body).map_err(|e| {
-        log::error!("{}", e);
-        log::error!("{}", &body);
-        e
-    })?;
+    let expirations: ExpirationResponse = parse_json(&body)?;
 
     Ok(expirations
         .expirations
-        .ok_or_else(|| anyhow::anyhow!("No expirations"))?
+        .ok_or_else(|| DataError::UnknownSymbol(symbol.to_uppercase()))?
         .date)
 }
 
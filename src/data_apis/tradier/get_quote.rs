@@ -1,28 +1,20 @@
-use crate::types;
+use super::TradierConfig;
+use crate::{
+    data_apis::{error::parse_json, DataError},
+    types,
+};
 use serde::{Deserialize, Serialize};
 
-pub async fn get_quote(symbol: &str) -> anyhow::Result<Quote> {
-    let access_token = std::env::var(super::ACCESS_TOKEN_ENV)?;
+pub async fn get_quote(config: &TradierConfig, symbol: &str) -> anyhow::Result<Quote> {
     let params = format!("symbols={}", symbol);
-    let url = format!("{}/markets/quotes?{}", super::BASE_URL, params);
+    let body = config.get(&format!("markets/quotes?{}", params)).await?;
 
-    let client = reqwest::Client::new();
-    let body = client
-        .get(url)
-        .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?
-        .text()
-        .await?;
+    let quotes: QuoteResponse = parse_json(&body)?;
 
-    let quotes: QuoteResponse = serde_json::from_str(&body).map_err(|e| {
-        log::error!("{}", e);
-        log::error!("{}", &body);
-        e
-    })?;
-
-    Ok(quotes.quotes.quote)
+    quotes
+        .quotes
+        .quote
+        .ok_or_else(|| DataError::UnknownSymbol(symbol.to_uppercase()).into())
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,7 +57,8 @@ struct QuoteResponse {
 
 #[derive(Clone, Debug, Deserialize)]
 struct QuoteResponseInner {
-    quote: Quote,
+    /// Missing when the symbol is listed in `unmatched_symbols` instead
+    quote: Option<Quote>,
 }
 
 impl From<Quote> for types::Quote {
@@ -79,6 +72,8 @@ impl From<Quote> for types::Quote {
             high: quote.high,
             low: quote.low,
             close: quote.close,
+            bid: quote.bid,
+            ask: quote.ask,
         }
     }
 }
@@ -1,9 +1,14 @@
 use chrono::{Datelike, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::types::{self as graphql, OhlcInterval};
+use super::TradierConfig;
+use crate::{
+    data_apis::error::parse_json,
+    types::{self as graphql, OhlcInterval},
+};
 
 pub async fn get_time_and_sales(
+    config: &TradierConfig,
     symbol: &str,
     interval: OhlcInterval,
 ) -> anyhow::Result<Vec<TimeAndSales>> {
@@ -18,25 +23,10 @@ pub async fn get_time_and_sales(
         .format("%Y-%m-%d %H:%M")
         .to_string();
 
-    let access_token = std::env::var(super::ACCESS_TOKEN_ENV)?;
     let params = format!("symbol={}&interval={}&start={}", symbol, interval, start);
-    let url = format!("{}/markets/timesales?{}", super::BASE_URL, params);
+    let body = config.get(&format!("markets/timesales?{}", params)).await?;
 
-    let client = reqwest::Client::new();
-    let body = client
-        .get(url)
-        .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let time_and_sales: TimeAndSalesResponse = serde_json::from_str(&body).map_err(|e| {
-        log::error!("{}", e);
-        log::error!("{}", &body);
-        e
-    })?;
+    let time_and_sales: TimeAndSalesResponse = parse_json(&body)?;
 
     Ok(time_and_sales.series.unwrap_or_default().data)
 }
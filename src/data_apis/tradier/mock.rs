@@ -0,0 +1,182 @@
+//! A stand-in for the Tradier API that serves canned responses for `SPY`, so
+//! the whole pipeline can be tested without a token or a network.
+
+use std::{collections::HashMap, net::SocketAddr};
+
+use serde_json::{json, Value};
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+use super::TradierConfig;
+
+pub const ACCESS_TOKEN: &str = "mock-token";
+pub const SYMBOL: &str = "SPY";
+
+const QUOTES: &str = include_str!("fixtures/quotes.json");
+const CLOCK: &str = include_str!("fixtures/clock.json");
+const EXPIRATIONS: &str = include_str!("fixtures/expirations.json");
+/// Chains keyed by expiration date
+const CHAINS: &str = include_str!("fixtures/chains.json");
+const TIME_AND_SALES: &str = include_str!("fixtures/timesales.json");
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("v1" / "markets" / ..))
+        .and(warp::path::tail())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(
+            |tail: warp::path::Tail, query: HashMap<String, String>, auth: Option<String>| {
+                if auth != Some(format!("Bearer {}", ACCESS_TOKEN)) {
+                    return warp::reply::with_status(
+                        "Invalid Access Token".to_string(),
+                        StatusCode::UNAUTHORIZED,
+                    );
+                }
+
+                match respond(tail.as_str(), &query) {
+                    Some(body) => warp::reply::with_status(body, StatusCode::OK),
+                    None => warp::reply::with_status(String::new(), StatusCode::NOT_FOUND),
+                }
+            },
+        )
+        .map(|reply| warp::reply::with_header(reply, "Content-Type", "application/json"))
+}
+
+fn respond(endpoint: &str, query: &HashMap<String, String>) -> Option<String> {
+    let symbol = query
+        .get("symbol")
+        .or_else(|| query.get("symbols"))
+        .map(|s| s.to_uppercase());
+    let known = symbol.as_deref() == Some(SYMBOL);
+
+    let body = match endpoint {
+        "clock" => CLOCK.to_string(),
+        "quotes" if known => QUOTES.to_string(),
+        "quotes" => json!({ "quotes": { "unmatched_symbols": { "symbol": symbol } } }).to_string(),
+        "options/expirations" if known => EXPIRATIONS.to_string(),
+        "options/expirations" => json!({ "expirations": null }).to_string(),
+        "options/chains" => {
+            let chains: Value = serde_json::from_str(CHAINS).ok()?;
+            let chain = query
+                .get("expiration")
+                .and_then(|expiration| chains.get(expiration))
+                .filter(|_| known);
+            match chain {
+                Some(chain) => chain.to_string(),
+                None => json!({ "options": null }).to_string(),
+            }
+        }
+        "timesales" if known => TIME_AND_SALES.to_string(),
+        "timesales" => json!({ "series": null }).to_string(),
+        _ => return None,
+    };
+
+    Some(body)
+}
+
+/// Starts the mock server on a free local port.
+pub fn serve() -> SocketAddr {
+    let (addr, server) = warp::serve(routes()).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+/// A config pointing at a mock server listening on `addr`.
+pub fn config(addr: SocketAddr) -> TradierConfig {
+    TradierConfig::new(format!("http://{}/v1", addr), ACCESS_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        data_apis::{tradier::TradierProvider, DataError, MarketDataProvider, Provider},
+        db::FileDb,
+        graphql,
+        math::pricing::PricingConfig,
+        types::{clock::MarketState, OhlcInterval},
+    };
+
+    #[tokio::test]
+    async fn endpoints() {
+        let provider = TradierProvider::new(config(serve()));
+
+        let quote = provider.quote(SYMBOL).await.unwrap();
+        assert_eq!(quote.last, Some(420.0));
+        assert_eq!(quote.bid, Some(419.99));
+
+        let clock = provider.clock().await.unwrap();
+        assert!(matches!(clock.state, MarketState::Open));
+
+        let expirations = provider.option_expirations(SYMBOL).await.unwrap();
+        assert_eq!(expirations, vec!["2021-06-18", "2021-07-16"]);
+
+        let candles = provider
+            .time_and_sales(SYMBOL, OhlcInterval::OneMinute)
+            .await
+            .unwrap();
+        assert_eq!(candles.len(), 3);
+
+        let error = provider.option_expirations("NOPE").await.unwrap_err();
+        assert_eq!(
+            DataError::find(&error),
+            Some(&DataError::UnknownSymbol("NOPE".to_string()))
+        );
+        let error = provider.quote("NOPE").await.unwrap_err();
+        assert_eq!(error.to_string(), "Unknown symbol: NOPE");
+    }
+
+    #[tokio::test]
+    async fn unauthorized() {
+        let mut config = config(serve());
+        config.access_token = Some("wrong".to_string());
+        let provider = TradierProvider::new(config);
+        let error = provider.quote(SYMBOL).await.unwrap_err();
+        assert!(matches!(DataError::find(&error), Some(DataError::Auth(_))));
+
+        let provider = TradierProvider::new(TradierConfig {
+            access_token: None,
+            ..super::config(serve())
+        });
+        let error = provider.clock().await.unwrap_err();
+        assert!(matches!(DataError::find(&error), Some(DataError::Auth(_))));
+    }
+
+    /// Fetches a chain through the provider, stores it and reads gamma
+    /// exposure back through GraphQL.
+    #[tokio::test]
+    async fn pipeline() {
+        let root = std::path::Path::new("data/test/tradier_mock");
+        let _ = std::fs::remove_dir_all(root);
+        let db = Arc::new(Mutex::new(FileDb::new(root)));
+        let provider: Provider = Arc::new(TradierProvider::new(config(serve())));
+
+        let schema = graphql::schema(db.clone(), provider, PricingConfig::default());
+        let response = schema
+            .execute(
+                r#"{ gammaExposure(symbol: "SPY") { symbol prices { strike gammaExposure } } }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["gammaExposure"]["symbol"], "SPY");
+        assert_eq!(data["gammaExposure"]["prices"].as_array().unwrap().len(), 5);
+
+        let response = schema
+            .execute(r#"{ gammaExposure(symbol: "NOPE") { symbol } }"#)
+            .await;
+        let extensions = serde_json::to_value(&response.errors[0].extensions).unwrap();
+        assert_eq!(extensions["code"], "UNKNOWN_SYMBOL");
+
+        let mut db = FileDb::open(root).unwrap();
+        let snapshot = db.snapshot(SYMBOL).unwrap().unwrap();
+        assert_eq!(snapshot.options.len(), 20);
+        assert_eq!(snapshot.provider.as_deref(), Some("tradier"));
+        assert_eq!(snapshot.spot(), Some(420.0));
+    }
+}
@@ -1,32 +1,93 @@
+pub mod atomic;
+pub mod csv;
 pub mod file;
-
-use crate::{data_apis::tradier, types::OptionInfo};
+pub mod migrations;
+pub mod parquet;
+pub mod retention;
+pub mod segment;
+pub mod sqlite;
+
+use crate::{
+    data_apis::{MarketDataProvider, Provider},
+    types::{OptionInfo, OptionSnapshot},
+};
+use chrono::{DateTime, Utc};
 use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 
 pub use file::FileDb;
+pub use retention::RetentionPolicy;
+pub use sqlite::SqliteDb;
+
+pub async fn option_chain(
+    symbol: &str,
+    db: Arc<Mutex<FileDb>>,
+    provider: &dyn MarketDataProvider,
+) -> anyhow::Result<Vec<OptionInfo>> {
+    Ok(snapshot(symbol, db, provider).await?.options)
+}
+
+pub async fn historical_option_chain(
+    symbol: &str,
+    time: DateTime<Utc>,
+    db: Arc<Mutex<FileDb>>,
+) -> anyhow::Result<Vec<OptionInfo>> {
+    Ok(historical_snapshot(symbol, time, db).await?.options)
+}
 
-pub async fn option_chain(symbol: &str, db: Arc<Mutex<FileDb>>) -> anyhow::Result<Vec<OptionInfo>> {
+/// The latest snapshot of `symbol`, fetching one first if the symbol isn't
+/// stored yet.
+pub async fn snapshot(
+    symbol: &str,
+    db: Arc<Mutex<FileDb>>,
+    provider: &dyn MarketDataProvider,
+) -> anyhow::Result<OptionSnapshot> {
     let has_symbol = {
         let db = db.lock().await;
         db.has_symbol(symbol)
     };
 
     if !has_symbol {
-        update_symbol(symbol, db.clone()).await?;
+        update_symbol(symbol, db.clone(), provider).await?;
     }
 
-    let db = db.lock().await;
-    let option_chain = db
-        .option_chain(symbol)
+    let mut db = db.lock().await;
+    let snapshot = db
+        .snapshot(symbol)?
         .ok_or_else(|| anyhow::anyhow!("Error loading data for {}", symbol))?;
 
-    Ok(option_chain.clone())
+    Ok(snapshot.clone())
 }
 
-pub fn start_db_update_loop(db: Arc<Mutex<FileDb>>) -> anyhow::Result<()> {
+pub async fn historical_snapshot(
+    symbol: &str,
+    time: DateTime<Utc>,
+    db: Arc<Mutex<FileDb>>,
+) -> anyhow::Result<OptionSnapshot> {
+    let mut db = db.lock().await;
+    let snapshot = db
+        .snapshot_at(symbol, time)?
+        .ok_or_else(|| anyhow::anyhow!("No data for {} at or before {}", symbol, time))?;
+
+    Ok(snapshot.clone())
+}
+
+/// Periodically fetches new snapshots of every stored symbol. When `sqlite` is
+/// given, it is kept in sync with `db` after every pass.
+pub fn start_db_update_loop(
+    db: Arc<Mutex<FileDb>>,
+    provider: Provider,
+    sqlite: Option<SqliteDb>,
+) -> anyhow::Result<()> {
+    let sqlite = sqlite.map(|sqlite| Arc::new(std::sync::Mutex::new(sqlite)));
+
     tokio::task::spawn(async move {
-        let mut symbol_delay = tokio::time::interval(Duration::from_secs(60));
+        if let Some(sqlite) = &sqlite {
+            sync_sqlite(sqlite, db.clone()).await;
+        }
+
+        let mut symbol_delay =
+            tokio::time::interval(provider.real_duration(Duration::from_secs(60)));
         loop {
             log::info!("Updating all symbols");
 
@@ -37,25 +98,60 @@ pub fn start_db_update_loop(db: Arc<Mutex<FileDb>>) -> anyhow::Result<()> {
 
             for symbol in symbols {
                 symbol_delay.tick().await;
-                if let Err(e) = update_symbol(&symbol, db.clone()).await {
+                if let Err(e) = update_symbol(&symbol, db.clone(), provider.as_ref()).await {
                     log::error!("{}", e);
                 }
             }
 
             log::info!("Successfully updated all symbols");
 
-            let sleep_duration = duration_until_next_check().await;
+            if let Some(sqlite) = &sqlite {
+                sync_sqlite(sqlite, db.clone()).await;
+            }
+
+            let sleep_duration = duration_until_next_check(provider.as_ref()).await;
             log::info!("Next update {} minutes", sleep_duration.as_secs() / 60);
 
-            tokio::time::sleep(sleep_duration).await;
+            tokio::time::sleep(provider.real_duration(sleep_duration)).await;
         }
     });
 
     Ok(())
 }
 
-pub async fn duration_until_next_check() -> Duration {
-    let clock = match tradier::get_clock().await {
+async fn sync_sqlite(sqlite: &Arc<std::sync::Mutex<SqliteDb>>, db: Arc<Mutex<FileDb>>) {
+    match copy_to_sqlite(sqlite, db).await {
+        Ok(count) => log::info!("Copied {} snapshots to SQLite", count),
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+/// Copies new snapshots one segment, so one symbol and day, at a time on the
+/// blocking thread pool. `db` is only locked to list the segments, so the
+/// first sync of a long history doesn't hold up updates and queries.
+async fn copy_to_sqlite(
+    sqlite: &Arc<std::sync::Mutex<SqliteDb>>,
+    db: Arc<Mutex<FileDb>>,
+) -> anyhow::Result<usize> {
+    let segments = db.lock().await.segment_refs();
+
+    let mut count = 0;
+    for segment in segments {
+        let sqlite = sqlite.clone();
+        count += tokio::task::spawn_blocking(move || {
+            let mut sqlite = sqlite
+                .lock()
+                .map_err(|_| anyhow::anyhow!("SQLite sync panicked"))?;
+            sqlite.sync_segment(&segment)
+        })
+        .await??;
+    }
+
+    Ok(count)
+}
+
+pub async fn duration_until_next_check(provider: &dyn MarketDataProvider) -> Duration {
+    let clock = match provider.clock().await {
         Ok(c) => c,
         Err(e) => {
             log::error!("{}", e);
@@ -74,11 +170,15 @@ pub async fn duration_until_next_check() -> Duration {
     }
 }
 
-pub async fn update_symbol(symbol: &str, db: Arc<Mutex<FileDb>>) -> anyhow::Result<()> {
-    log::info!("Updating data for {}", symbol);
-    let option_chain = tradier::get_option_chain(&symbol.to_uppercase()).await?;
+pub async fn update_symbol(
+    symbol: &str,
+    db: Arc<Mutex<FileDb>>,
+    provider: &dyn MarketDataProvider,
+) -> anyhow::Result<()> {
+    log::info!("Updating data for {} from {}", symbol, provider.name());
+    let snapshot = provider.option_snapshot(&symbol.to_uppercase()).await?;
     let mut db = db.lock().await;
-    db.add_option_info(&symbol, option_chain);
+    db.add_snapshot(snapshot)?;
     log::info!("Successfully updated data for {}", symbol);
 
     Ok(())
@@ -0,0 +1,172 @@
+//! Crash-safe file writes. Files are written to a temporary file and renamed
+//! into place so a crash leaves either the old or the new contents, never a
+//! mix of both.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const HEADER_MAGIC: &str = "market_analyzer";
+
+pub fn write(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let temp_path = with_suffix(path, "tmp");
+    write_file(&temp_path, bytes)?;
+    replace(&temp_path, path)
+}
+
+/// Writes `bytes` next to `path` without touching it, for [`replace`] to move
+/// into place later.
+pub fn write_pending(path: &Path, bytes: &[u8]) -> anyhow::Result<PathBuf> {
+    let pending_path = with_suffix(path, "pending");
+    write_file(&pending_path, bytes)?;
+    Ok(pending_path)
+}
+
+/// Moves the fully written `temp_path` to `path`.
+pub fn replace(temp_path: &Path, path: &Path) -> anyhow::Result<()> {
+    std::fs::rename(temp_path, path)?;
+
+    // Make sure the rename itself is durable
+    if let Ok(dir) = File::open(parent(path)) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(parent(path))?;
+
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Like `write`, but first keeps the current contents of `path` as
+/// `path.1`, shifting older copies up to `path.{count}`.
+pub fn write_with_backups(path: &Path, bytes: &[u8], count: usize) -> anyhow::Result<()> {
+    rotate_backups(path, count)?;
+    write(path, bytes)
+}
+
+pub fn rotate_backups(path: &Path, count: usize) -> anyhow::Result<()> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for i in (1..count).rev() {
+        let from = backup_path(path, i);
+        if from.exists() {
+            std::fs::rename(&from, backup_path(path, i + 1))?;
+        }
+    }
+    std::fs::copy(path, backup_path(path, 1))?;
+
+    Ok(())
+}
+
+/// Existing backups of `path`, newest first.
+pub fn backups(path: &Path, count: usize) -> Vec<PathBuf> {
+    (1..=count)
+        .map(|i| backup_path(path, i))
+        .filter(|p| p.exists())
+        .collect()
+}
+
+pub fn backup_path(path: &Path, i: usize) -> PathBuf {
+    with_suffix(path, &i.to_string())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    path.into()
+}
+
+/// Prefixes `payload` with a one line header holding the format version and a
+/// CRC32 of the payload.
+pub fn encode(version: u32, payload: &[u8]) -> Vec<u8> {
+    let header = format!(
+        "{} {} {:08x}\n",
+        HEADER_MAGIC,
+        version,
+        crc32fast::hash(payload)
+    );
+
+    let mut bytes = header.into_bytes();
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Checks the header written by `encode`, returning the version and payload.
+pub fn decode(bytes: &[u8]) -> anyhow::Result<(u32, &[u8])> {
+    let newline = bytes
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| anyhow::anyhow!("Missing header"))?;
+    let header = std::str::from_utf8(&bytes[..newline])?;
+    let payload = &bytes[newline + 1..];
+
+    let mut fields = header.split(' ');
+    if fields.next() != Some(HEADER_MAGIC) {
+        anyhow::bail!("Invalid header: {}", header);
+    }
+    let version: u32 = fields
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Missing version: {}", header))?
+        .parse()?;
+    let checksum = fields
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Missing checksum: {}", header))?;
+    let checksum = u32::from_str_radix(checksum, 16)?;
+
+    if crc32fast::hash(payload) != checksum {
+        anyhow::bail!("Checksum mismatch");
+    }
+
+    Ok((version, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let bytes = encode(3, b"{}");
+        assert_eq!(decode(&bytes).unwrap(), (3, &b"{}"[..]));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() = b']';
+        assert!(decode(&corrupt).is_err());
+        assert!(decode(b"{}").is_err());
+    }
+
+    #[test]
+    fn backups() {
+        let dir = Path::new("data/test/atomic");
+        let _ = std::fs::remove_dir_all(dir);
+        let path = dir.join("file");
+
+        for i in 0..4 {
+            write_with_backups(&path, i.to_string().as_bytes(), 2).unwrap();
+        }
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"3");
+        let backups = super::backups(&path, 2);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(std::fs::read(&backups[0]).unwrap(), b"2");
+        assert_eq!(std::fs::read(&backups[1]).unwrap(), b"1");
+    }
+}
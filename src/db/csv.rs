@@ -0,0 +1,798 @@
+//! CSV import and export of option chains. Vendors all name their columns
+//! differently, so which column holds which field is described by a
+//! `ColumnMapping`, either one of the built-in ones or a JSON file in
+//! `data/mappings`.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::America::New_York;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Greeks, OptionInfo, OptionSnapshot, OptionType, Quote, UnderlyingType};
+
+pub const CONTENT_TYPE: &str = "text/csv";
+pub const PROVIDER_NAME: &str = "csv";
+const MAPPINGS_PATH: &str = "data/mappings";
+const STORED_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The column holding each field, by header name. Fields without a column,
+/// or whose column is missing from a file, are left empty. `option_type`,
+/// `strike` and `expiration_date` are required.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub timestamp: Option<String>,
+    /// `chrono` format of `timestamp`, which is otherwise RFC 3339. Formats
+    /// without a time are taken as the 16:00 New York close.
+    pub timestamp_format: Option<String>,
+    pub symbol: Option<String>,
+    pub option_type: String,
+    pub strike: String,
+    pub expiration_date: String,
+    /// `chrono` format of `expiration_date`. Without one the value is kept
+    /// as is.
+    pub expiration_format: Option<String>,
+    pub open_interest: Option<String>,
+    pub volume: Option<String>,
+    pub delta: Option<String>,
+    pub gamma: Option<String>,
+    pub theta: Option<String>,
+    pub vega: Option<String>,
+    pub rho: Option<String>,
+    pub vanna: Option<String>,
+    pub charm: Option<String>,
+    pub speed: Option<String>,
+    pub zomma: Option<String>,
+    pub color: Option<String>,
+    pub vomma: Option<String>,
+    pub veta: Option<String>,
+    pub ultima: Option<String>,
+    pub last: Option<String>,
+    pub change: Option<String>,
+    pub open: Option<String>,
+    pub high: Option<String>,
+    pub low: Option<String>,
+    pub close: Option<String>,
+    pub bid_iv: Option<String>,
+    pub mid_iv: Option<String>,
+    pub ask_iv: Option<String>,
+    pub smv_vol: Option<String>,
+    pub bid: Option<String>,
+    pub ask: Option<String>,
+    pub multiplier: Option<String>,
+    pub settlement_type: Option<String>,
+    pub days_to_expiration: Option<String>,
+    /// `equity`, `index` or `future`. Without one it's inferred from the
+    /// symbol.
+    pub underlying_type: Option<String>,
+    /// Price of the underlying, stored as the snapshot's quote
+    pub underlying_price: Option<String>,
+    /// Implied volatilities are given in percent rather than as fractions
+    pub iv_percent: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Timestamp,
+    Symbol,
+    OptionType,
+    Strike,
+    ExpirationDate,
+    OpenInterest,
+    Volume,
+    Delta,
+    Gamma,
+    Theta,
+    Vega,
+    Rho,
+    Vanna,
+    Charm,
+    Speed,
+    Zomma,
+    Color,
+    Vomma,
+    Veta,
+    Ultima,
+    Last,
+    Change,
+    Open,
+    High,
+    Low,
+    Close,
+    BidIv,
+    MidIv,
+    AskIv,
+    SmvVol,
+    Bid,
+    Ask,
+    Multiplier,
+    SettlementType,
+    DaysToExpiration,
+    UnderlyingType,
+    UnderlyingPrice,
+}
+
+/// Uses the crate's own field names, so exported files can be imported
+/// again as they are.
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        let column = |name: &str| Some(name.to_string());
+
+        Self {
+            timestamp: column("timestamp"),
+            timestamp_format: None,
+            symbol: column("symbol"),
+            option_type: "option_type".to_string(),
+            strike: "strike".to_string(),
+            expiration_date: "expiration_date".to_string(),
+            expiration_format: None,
+            open_interest: column("open_interest"),
+            volume: column("volume"),
+            delta: column("delta"),
+            gamma: column("gamma"),
+            theta: column("theta"),
+            vega: column("vega"),
+            rho: column("rho"),
+            vanna: column("vanna"),
+            charm: column("charm"),
+            speed: column("speed"),
+            zomma: column("zomma"),
+            color: column("color"),
+            vomma: column("vomma"),
+            veta: column("veta"),
+            ultima: column("ultima"),
+            last: column("last"),
+            change: column("change"),
+            open: column("open"),
+            high: column("high"),
+            low: column("low"),
+            close: column("close"),
+            bid_iv: column("bid_iv"),
+            mid_iv: column("mid_iv"),
+            ask_iv: column("ask_iv"),
+            smv_vol: column("smv_vol"),
+            bid: column("bid"),
+            ask: column("ask"),
+            multiplier: column("multiplier"),
+            settlement_type: column("settlement_type"),
+            days_to_expiration: column("days_to_expiration"),
+            underlying_type: column("underlying_type"),
+            underlying_price: column("underlying_price"),
+            iv_percent: false,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// The CBOE DataShop end-of-day option summary with calcs.
+    pub fn cboe() -> Self {
+        let column = |name: &str| Some(name.to_string());
+
+        Self {
+            timestamp: column("quote_date"),
+            timestamp_format: Some("%Y-%m-%d".to_string()),
+            symbol: column("root"),
+            option_type: "option_type".to_string(),
+            strike: "strike".to_string(),
+            expiration_date: "expiration".to_string(),
+            expiration_format: Some("%Y-%m-%d".to_string()),
+            open_interest: column("open_interest"),
+            volume: column("trade_volume"),
+            delta: column("delta_1545"),
+            gamma: column("gamma_1545"),
+            theta: column("theta_1545"),
+            vega: column("vega_1545"),
+            rho: column("rho_1545"),
+            vanna: None,
+            charm: None,
+            speed: None,
+            zomma: None,
+            color: None,
+            vomma: None,
+            veta: None,
+            ultima: None,
+            last: column("close"),
+            change: None,
+            open: column("open"),
+            high: column("high"),
+            low: column("low"),
+            close: column("close"),
+            bid_iv: None,
+            mid_iv: column("implied_volatility_1545"),
+            ask_iv: None,
+            smv_vol: None,
+            bid: column("bid_eod"),
+            ask: column("ask_eod"),
+            multiplier: None,
+            settlement_type: None,
+            days_to_expiration: None,
+            underlying_type: None,
+            underlying_price: column("active_underlying_price_1545"),
+            iv_percent: false,
+        }
+    }
+
+    /// A built-in mapping (`default` or `cboe`), or `data/mappings/<name>.json`.
+    pub fn named(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "default" => Ok(Self::default()),
+            "cboe" => Ok(Self::cboe()),
+            _ => {
+                if !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    anyhow::bail!("Invalid column mapping name: {}", name);
+                }
+                let path = Path::new(MAPPINGS_PATH).join(format!("{}.json", name));
+                let json = std::fs::read_to_string(&path).map_err(|e| {
+                    anyhow::anyhow!("Failed to read column mapping {}: {}", path.display(), e)
+                })?;
+                Ok(serde_json::from_str(&json)?)
+            }
+        }
+    }
+
+    fn columns(&self) -> Vec<(Field, &str)> {
+        use Field::*;
+
+        let optional = [
+            (Timestamp, &self.timestamp),
+            (Symbol, &self.symbol),
+            (OpenInterest, &self.open_interest),
+            (Volume, &self.volume),
+            (Delta, &self.delta),
+            (Gamma, &self.gamma),
+            (Theta, &self.theta),
+            (Vega, &self.vega),
+            (Rho, &self.rho),
+            (Vanna, &self.vanna),
+            (Charm, &self.charm),
+            (Speed, &self.speed),
+            (Zomma, &self.zomma),
+            (Color, &self.color),
+            (Vomma, &self.vomma),
+            (Veta, &self.veta),
+            (Ultima, &self.ultima),
+            (Last, &self.last),
+            (Change, &self.change),
+            (Open, &self.open),
+            (High, &self.high),
+            (Low, &self.low),
+            (Close, &self.close),
+            (BidIv, &self.bid_iv),
+            (MidIv, &self.mid_iv),
+            (AskIv, &self.ask_iv),
+            (SmvVol, &self.smv_vol),
+            (Bid, &self.bid),
+            (Ask, &self.ask),
+            (Multiplier, &self.multiplier),
+            (SettlementType, &self.settlement_type),
+            (DaysToExpiration, &self.days_to_expiration),
+            (UnderlyingType, &self.underlying_type),
+            (UnderlyingPrice, &self.underlying_price),
+        ];
+
+        let mut columns = vec![
+            (OptionType, self.option_type.as_str()),
+            (Strike, self.strike.as_str()),
+            (ExpirationDate, self.expiration_date.as_str()),
+        ];
+        columns.extend(
+            optional
+                .iter()
+                .filter_map(|(field, column)| Some((*field, column.as_deref()?))),
+        );
+        columns
+    }
+
+    fn iv_scale(&self) -> f64 {
+        if self.iv_percent {
+            100.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// One parsed row along with the parts that belong to the snapshot rather
+/// than the option.
+struct Row {
+    timestamp: Option<DateTime<Utc>>,
+    underlying_price: Option<f64>,
+    option: OptionInfo,
+}
+
+/// Reads a list of options. Rows without a timestamp are stamped with the
+/// current time.
+pub fn read_options(reader: impl Read, mapping: &ColumnMapping) -> anyhow::Result<Vec<OptionInfo>> {
+    Ok(read_rows(reader, None, mapping)?
+        .into_iter()
+        .map(|row| row.option)
+        .collect())
+}
+
+/// Reads the options of `symbol`, grouped into one snapshot per timestamp.
+/// Rows without a symbol of their own are taken to be options on `symbol`.
+pub fn read_snapshots(
+    reader: impl Read,
+    symbol: &str,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<Vec<OptionSnapshot>> {
+    let mut snapshots: BTreeMap<DateTime<Utc>, OptionSnapshot> = BTreeMap::new();
+    let now = Utc::now();
+
+    for row in read_rows(reader, Some(symbol), mapping)? {
+        let timestamp = row.timestamp.unwrap_or(now);
+        let snapshot = snapshots.entry(timestamp).or_insert_with(|| {
+            OptionSnapshot::new(timestamp, symbol, PROVIDER_NAME, None, Vec::new())
+        });
+        if let (None, Some(price)) = (&snapshot.quote, row.underlying_price) {
+            snapshot.quote = Some(Quote {
+                symbol: snapshot.symbol.clone(),
+                last: Some(price),
+                change: None,
+                volume: 0,
+                open: None,
+                high: None,
+                low: None,
+                close: None,
+                bid: None,
+                ask: None,
+            });
+        }
+        snapshot.options.push(row.option);
+    }
+
+    Ok(snapshots.into_values().collect())
+}
+
+fn read_rows(
+    reader: impl Read,
+    symbol: Option<&str>,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<Vec<Row>> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .trim(::csv::Trim::All)
+        .from_reader(reader);
+
+    let headers = reader.headers()?.clone();
+    let mut columns = Vec::new();
+    for (field, name) in mapping.columns() {
+        match headers.iter().position(|h| h == name) {
+            Some(i) => columns.push((field, i)),
+            None if is_required(field) => anyhow::bail!("Missing column {}", name),
+            None => {}
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = read_row(&record, &columns, symbol, mapping)
+            .map_err(|e| anyhow::anyhow!("Invalid row {}: {}", i + 1, e))?;
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn is_required(field: Field) -> bool {
+    matches!(
+        field,
+        Field::OptionType | Field::Strike | Field::ExpirationDate
+    )
+}
+
+/// `symbol` is the underlying the file was imported for, if any. It stands in
+/// for a missing symbol column and decides the underlying type unless the
+/// row has one.
+fn read_row(
+    record: &::csv::StringRecord,
+    columns: &[(Field, usize)],
+    symbol: Option<&str>,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<Row> {
+    let mut row = Row {
+        timestamp: None,
+        underlying_price: None,
+        option: OptionInfo {
+            timestamp: Utc::now().to_rfc3339(),
+            symbol: symbol.unwrap_or_default().to_uppercase(),
+            option_type: OptionType::Call,
+            strike: 0.0,
+            expiration_date: String::new(),
+            open_interest: 0,
+            volume: 0,
+            greeks: None,
+            last: None,
+            change: None,
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+            bid_iv: None,
+            mid_iv: None,
+            ask_iv: None,
+            smv_vol: None,
+            bid: None,
+            ask: None,
+            multiplier: None,
+            settlement_type: None,
+            days_to_expiration: None,
+            underlying_type: None,
+        },
+    };
+
+    for &(field, i) in columns {
+        let value = match record.get(i) {
+            Some(value) if !value.is_empty() => value,
+            _ if is_required(field) => anyhow::bail!("Missing {:?}", field),
+            _ => continue,
+        };
+        set_field(&mut row, field, value, mapping)?;
+    }
+
+    let option = &mut row.option;
+    if option.underlying_type.is_none() {
+        let underlying = symbol.unwrap_or(&option.symbol);
+        option.underlying_type = Some(UnderlyingType::infer(underlying));
+    }
+
+    Ok(row)
+}
+
+fn set_field(
+    row: &mut Row,
+    field: Field,
+    value: &str,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<()> {
+    use Field::*;
+
+    let option = &mut row.option;
+    let number = || -> anyhow::Result<Option<f64>> {
+        let number: f64 = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid {:?}: {}", field, value))?;
+        Ok(Some(number).filter(|n| n.is_finite()))
+    };
+    let iv = || -> anyhow::Result<Option<f64>> { Ok(number()?.map(|v| v / mapping.iv_scale())) };
+
+    match field {
+        Timestamp => {
+            let timestamp = parse_timestamp(value, mapping.timestamp_format.as_deref())?;
+            option.timestamp = timestamp.to_rfc3339();
+            row.timestamp = Some(timestamp);
+        }
+        Symbol => option.symbol = value.to_uppercase(),
+        OptionType => option.option_type = crate::types::OptionType::from_str(value)?,
+        Strike => option.strike = number()?.ok_or_else(|| anyhow::anyhow!("Invalid strike"))?,
+        ExpirationDate => {
+            option.expiration_date = match &mapping.expiration_format {
+                Some(format) => NaiveDate::parse_from_str(value, format)?
+                    .format(STORED_DATE_FORMAT)
+                    .to_string(),
+                None => value.to_string(),
+            }
+        }
+        OpenInterest => option.open_interest = number()?.unwrap_or(0.0).max(0.0) as u64,
+        Volume => option.volume = number()?.unwrap_or(0.0).max(0.0) as u64,
+        Delta | Gamma | Theta | Vega | Rho | Vanna | Charm | Speed | Zomma | Color | Vomma
+        | Veta | Ultima => {
+            if let Some(value) = number()? {
+                let greeks = option.greeks.get_or_insert_with(Greeks::default);
+                match field {
+                    Delta => greeks.delta = value,
+                    Gamma => greeks.gamma = value,
+                    Theta => greeks.theta = value,
+                    Vega => greeks.vega = value,
+                    Rho => greeks.rho = value,
+                    Vanna => greeks.vanna = value,
+                    Charm => greeks.charm = value,
+                    Speed => greeks.speed = value,
+                    Zomma => greeks.zomma = value,
+                    Color => greeks.color = value,
+                    Vomma => greeks.vomma = value,
+                    Veta => greeks.veta = value,
+                    _ => greeks.ultima = value,
+                }
+            }
+        }
+        Last => option.last = number()?,
+        Change => option.change = number()?,
+        Open => option.open = number()?,
+        High => option.high = number()?,
+        Low => option.low = number()?,
+        Close => option.close = number()?,
+        BidIv => option.bid_iv = iv()?,
+        MidIv => option.mid_iv = iv()?,
+        AskIv => option.ask_iv = iv()?,
+        SmvVol => option.smv_vol = iv()?,
+        Bid => option.bid = number()?,
+        Ask => option.ask = number()?,
+        Multiplier => option.multiplier = number()?,
+        SettlementType => {
+            option.settlement_type = crate::types::SettlementType::from_str(value).ok()
+        }
+        DaysToExpiration => option.days_to_expiration = number()?.map(|d| d as i64),
+        UnderlyingType => {
+            option.underlying_type = Some(crate::types::UnderlyingType::from_str(value)?)
+        }
+        UnderlyingPrice => row.underlying_price = number()?,
+    }
+
+    Ok(())
+}
+
+fn parse_timestamp(value: &str, format: Option<&str>) -> anyhow::Result<DateTime<Utc>> {
+    let format = match format {
+        Some(format) => format,
+        None => return Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc)),
+    };
+
+    let time = match NaiveDateTime::parse_from_str(value, format) {
+        Ok(time) => time,
+        Err(_) => NaiveDate::parse_from_str(value, format)?.and_time(market_close()),
+    };
+    New_York
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp: {}", value))
+}
+
+fn market_close() -> NaiveTime {
+    NaiveTime::from_hms_opt(16, 0, 0).unwrap_or_default()
+}
+
+/// Writes a list of options.
+pub fn write_options(
+    writer: impl Write,
+    options: &[OptionInfo],
+    mapping: &ColumnMapping,
+) -> anyhow::Result<()> {
+    write(writer, options, Default::default(), mapping)
+}
+
+/// Writes the options of a snapshot, stamped with the snapshot's time and
+/// including its underlying price.
+pub fn write_snapshot(
+    writer: impl Write,
+    snapshot: &OptionSnapshot,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<()> {
+    let snapshot_columns = SnapshotColumns {
+        timestamp: Some(snapshot.timestamp),
+        underlying_price: snapshot.spot(),
+    };
+    write(writer, &snapshot.options, snapshot_columns, mapping)
+}
+
+/// Values shared by every row of a snapshot
+#[derive(Clone, Copy, Debug, Default)]
+struct SnapshotColumns {
+    timestamp: Option<DateTime<Utc>>,
+    underlying_price: Option<f64>,
+}
+
+fn write(
+    writer: impl Write,
+    options: &[OptionInfo],
+    snapshot: SnapshotColumns,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<()> {
+    let mut writer = ::csv::Writer::from_writer(writer);
+
+    let columns = mapping.columns();
+    writer.write_record(columns.iter().map(|(_, name)| name))?;
+
+    for option in options {
+        let record = columns
+            .iter()
+            .map(|(field, _)| format_field(option, *field, snapshot, mapping))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn format_field(
+    option: &OptionInfo,
+    field: Field,
+    snapshot: SnapshotColumns,
+    mapping: &ColumnMapping,
+) -> anyhow::Result<String> {
+    use Field::*;
+
+    let number = |n: Option<f64>| n.map(|n| n.to_string()).unwrap_or_default();
+    let iv = |n: Option<f64>| number(n.map(|n| n * mapping.iv_scale()));
+    let greek = |f: fn(&Greeks) -> f64| number(option.greeks.as_ref().map(f));
+
+    Ok(match field {
+        Timestamp => match (snapshot.timestamp, &mapping.timestamp_format) {
+            (Some(timestamp), Some(format)) => timestamp
+                .with_timezone(&New_York)
+                .format(format)
+                .to_string(),
+            (Some(timestamp), None) => timestamp.to_rfc3339(),
+            (None, Some(format)) => DateTime::parse_from_rfc3339(&option.timestamp)?
+                .with_timezone(&New_York)
+                .format(format)
+                .to_string(),
+            (None, None) => option.timestamp.clone(),
+        },
+        Symbol => option.symbol.clone(),
+        OptionType => format!("{:?}", option.option_type),
+        Strike => option.strike.to_string(),
+        ExpirationDate => match &mapping.expiration_format {
+            Some(format) => NaiveDate::parse_from_str(&option.expiration_date, STORED_DATE_FORMAT)?
+                .format(format)
+                .to_string(),
+            None => option.expiration_date.clone(),
+        },
+        OpenInterest => option.open_interest.to_string(),
+        Volume => option.volume.to_string(),
+        Delta => greek(|g| g.delta),
+        Gamma => greek(|g| g.gamma),
+        Theta => greek(|g| g.theta),
+        Vega => greek(|g| g.vega),
+        Rho => greek(|g| g.rho),
+        Vanna => greek(|g| g.vanna),
+        Charm => greek(|g| g.charm),
+        Speed => greek(|g| g.speed),
+        Zomma => greek(|g| g.zomma),
+        Color => greek(|g| g.color),
+        Vomma => greek(|g| g.vomma),
+        Veta => greek(|g| g.veta),
+        Ultima => greek(|g| g.ultima),
+        Last => number(option.last),
+        Change => number(option.change),
+        Open => number(option.open),
+        High => number(option.high),
+        Low => number(option.low),
+        Close => number(option.close),
+        BidIv => iv(option.bid_iv),
+        MidIv => iv(option.mid_iv),
+        AskIv => iv(option.ask_iv),
+        SmvVol => iv(option.smv_vol),
+        Bid => number(option.bid),
+        Ask => number(option.ask),
+        Multiplier => number(option.multiplier),
+        SettlementType => option
+            .settlement_type
+            .map(|s| format!("{:?}", s))
+            .unwrap_or_default(),
+        DaysToExpiration => option
+            .days_to_expiration
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+        UnderlyingType => option
+            .underlying_type
+            .map(|u| format!("{:?}", u))
+            .unwrap_or_default(),
+        UnderlyingPrice => number(snapshot.underlying_price),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SettlementType;
+
+    const CBOE: &str = "\
+underlying_symbol,quote_date,root,expiration,strike,option_type,open,high,low,close,trade_volume,bid_1545,ask_1545,active_underlying_price_1545,implied_volatility_1545,delta_1545,gamma_1545,theta_1545,vega_1545,rho_1545,bid_eod,ask_eod,open_interest
+^SPX,2021-06-10,SPXW,2021-06-11,4200.000,C,0,0,0,0,0,40.1,40.9,4239.18,0.1301,0.9051,0.0032,-1.2051,0.4021,0.1101,39.2,40.0,120
+^SPX,2021-06-10,SPXW,2021-06-11,4200.000,P,1.1,1.5,0.9,1.0,2345,0.95,1.05,4239.18,0.1744,-0.0921,0.0029,-1.4102,0.4402,-0.0102,0.9,1.0,5400
+^SPX,2021-06-11,SPXW,2021-06-11,4250.000,P,2.0,3.0,1.0,2.5,10,2.4,2.6,4247.44,0.0902,-0.4,0.02,-5.0,0.1,-0.01,2.4,2.6,800
+";
+
+    #[test]
+    fn read_cboe() {
+        let snapshots = read_snapshots(CBOE.as_bytes(), "spx", &ColumnMapping::cboe()).unwrap();
+        assert_eq!(snapshots.len(), 2);
+
+        let snapshot = &snapshots[0];
+        assert_eq!(snapshot.symbol, "SPX");
+        assert_eq!(snapshot.timestamp.to_rfc3339(), "2021-06-10T20:00:00+00:00");
+        assert_eq!(snapshot.spot(), Some(4239.18));
+        assert_eq!(snapshot.options.len(), 2);
+
+        let put = &snapshot.options[1];
+        assert_eq!(put.symbol, "SPXW");
+        assert_eq!(put.option_type, OptionType::Put);
+        assert_eq!(put.strike, 4200.0);
+        assert_eq!(put.expiration_date, "2021-06-11");
+        assert_eq!(put.open_interest, 5400);
+        assert_eq!(put.volume, 2345);
+        assert_eq!(put.gamma(), 0.0029);
+        assert_eq!(put.mid_iv, Some(0.1744));
+        assert_eq!(put.bid, Some(0.9));
+    }
+
+    #[test]
+    fn import_without_symbol() {
+        let csv = "option_type,strike,expiration_date\nCall,4200,2021-06-18\nPut,4100,2021-06-18\n";
+        let snapshots = read_snapshots(csv.as_bytes(), "/es", &ColumnMapping::default()).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].symbol, "/ES");
+
+        for option in &snapshots[0].options {
+            assert_eq!(option.symbol, "/ES");
+            assert_eq!(option.underlying_type, Some(UnderlyingType::Future));
+        }
+
+        let options = read_options(csv.as_bytes(), &ColumnMapping::default()).unwrap();
+        assert_eq!(options[0].symbol, "");
+
+        let csv = "symbol,option_type,strike,expiration_date\nspxw,Call,4200,2021-06-18\n";
+        let options = read_options(csv.as_bytes(), &ColumnMapping::default()).unwrap();
+        assert_eq!(options[0].symbol, "SPXW");
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut snapshot = OptionSnapshot::test();
+        let mut put = OptionInfo::test();
+        put.option_type = OptionType::Put;
+        put.greeks = None;
+        put.settlement_type = Some(SettlementType::AM);
+        snapshot.options.push(put);
+        for option in &mut snapshot.options {
+            option.timestamp = snapshot.timestamp.to_rfc3339();
+        }
+
+        let mut bytes = Vec::new();
+        write_snapshot(&mut bytes, &snapshot, &ColumnMapping::default()).unwrap();
+
+        let snapshots = read_snapshots(&*bytes, "TST", &ColumnMapping::default()).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].spot(), Some(10.0));
+
+        let options = &snapshots[0].options;
+        let original = &snapshot.options;
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            serde_json::to_value(original).unwrap()
+        );
+    }
+
+    #[test]
+    fn custom_mapping() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{
+                "option_type": "Type",
+                "strike": "Strike Price",
+                "expiration_date": "Expiry",
+                "expiration_format": "%m/%d/%Y",
+                "mid_iv": "IV",
+                "iv_percent": true
+            }"#,
+        )
+        .unwrap();
+        let csv = "Type,Strike Price,Expiry,IV\nCall,10,06/18/2021,25.5\nPut,9,06/18/2021,\n";
+
+        let options = read_options(csv.as_bytes(), &mapping).unwrap();
+        assert_eq!(options.len(), 2);
+        assert_eq!(options[0].expiration_date, "2021-06-18");
+        assert_eq!(options[0].mid_iv, Some(0.255));
+        assert_eq!(options[1].mid_iv, None);
+
+        let mut bytes = Vec::new();
+        write_options(&mut bytes, &options, &mapping).unwrap();
+        let written = String::from_utf8(bytes).unwrap();
+        assert!(written.contains("06/18/2021"));
+        assert!(written.contains("25.5"));
+
+        assert!(read_options("Type,Strike Price\nCall,10\n".as_bytes(), &mapping).is_err());
+        assert!(ColumnMapping::named("../secrets").is_err());
+    }
+}
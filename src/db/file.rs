@@ -1,127 +1,1123 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     io::{Read, Write},
+    ops::RangeBounds,
     path::{Path, PathBuf},
 };
 
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::America::New_York;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::types::OptionInfo;
+use super::{
+    atomic, migrations, parquet,
+    retention::{CompactionReport, RetentionPolicy},
+    segment,
+};
+use crate::types::{OptionInfo, OptionSnapshot};
 
+pub const DEFAULT_DB_PATH: &str = "data/db";
+/// Where replayed snapshots are stored, so replays never mix with collected
+/// history
+pub const REPLAY_DB_PATH: &str = "data/replay_db";
+/// The single-file format used before segments were introduced
 pub const DEFAULT_FILE_PATH: &str = "data/db.gz";
 
+const DB_PATH_ENV: &str = "DB_PATH";
+const INDEX_FILE: &str = "index.json";
+const INDEX_VERSION: u32 = 1;
+const INDEX_BACKUP_COUNT: usize = 3;
+
 pub type Symbol = String;
-pub type OptionSnapshot = Vec<OptionInfo>;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Snapshot storage split into one append-only segment per symbol per day.
+/// The index of segments is kept in memory and segments are only read from
+/// disk when a query needs them.
+#[derive(Clone, Debug)]
 pub struct FileDb {
+    root: PathBuf,
+    index: Index,
+    segments: HashMap<(Symbol, NaiveDate), Vec<OptionSnapshot>>,
+    /// Opened with [`FileDb::open_read_only`]
+    read_only: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Index {
+    symbols: BTreeMap<Symbol, Vec<SegmentInfo>>,
+    /// Set once the legacy `db.gz` has been imported in full
+    #[serde(default)]
+    legacy_imported: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SegmentInfo {
+    date: NaiveDate,
+    /// Oldest first, whatever order the snapshots were added in
+    timestamps: Vec<DateTime<Utc>>,
+}
+
+/// The layout of the legacy `db.gz` file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct LegacyDb<T> {
     file_path: PathBuf,
-    options: HashMap<Symbol, Vec<OptionSnapshot>>,
+    options: HashMap<Symbol, Vec<T>>,
 }
 
 impl FileDb {
-    pub fn new(path: impl AsRef<Path>) -> Self {
-        Self::from_data(path, HashMap::new())
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().into(),
+            index: Index::default(),
+            segments: HashMap::new(),
+            read_only: false,
+        }
     }
 
-    pub fn from_data(
-        path: impl AsRef<Path>,
-        options: HashMap<Symbol, Vec<OptionSnapshot>>,
-    ) -> Self {
-        Self {
-            file_path: path.as_ref().into(),
-            options,
+    /// Opens the database at `root`. An unreadable index is replaced by its
+    /// newest readable backup or, failing that, rebuilt from the segments.
+    pub fn open(root: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::open_with(root, false)
+    }
+
+    /// Opens the database at `root` like [`FileDb::open`], but never writes
+    /// to it. A stale index and damaged segments are only worked around in
+    /// memory, and storing snapshots fails.
+    pub fn open_read_only(root: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::open_with(root, true)
+    }
+
+    fn open_with(root: impl AsRef<Path>, read_only: bool) -> anyhow::Result<Self> {
+        let mut db = Self::new(root);
+        db.read_only = read_only;
+
+        let index_path = db.root.join(INDEX_FILE);
+        if index_path.exists() {
+            db.index = match read_index(&index_path) {
+                Ok(index) => index,
+                Err(e) => {
+                    log::error!("Unreadable index {}: {}", index_path.display(), e);
+                    recover_index(&index_path)
+                }
+            };
+        }
+
+        // Timestamps are kept in order, which indexes written before that
+        // may not be
+        for info in db.index.symbols.values_mut().flatten() {
+            info.timestamps.sort();
+        }
+
+        if db.reconcile()? && !db.read_only {
+            db.write_index()?;
+        }
+
+        Ok(db)
+    }
+
+    /// Opens the database at `root`, importing the legacy file at
+    /// `legacy_path` if one exists and hasn't been imported in full yet.
+    pub fn load(root: impl AsRef<Path>, legacy_path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut db = Self::open(root)?;
+
+        let legacy_path = legacy_path.filter(|path| !db.index.legacy_imported && path.exists());
+        if let Some(legacy_path) = legacy_path {
+            log::info!("Importing {}", legacy_path.display());
+            db.import_legacy(legacy_path)?;
+        }
+
+        Ok(db)
+    }
+
+    /// Appends every snapshot of a legacy file the database doesn't have yet,
+    /// so an import cut short by a crash picks up where it stopped. The index
+    /// is written once at the end, marking the import as done.
+    pub fn import_legacy(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        for (symbol, snapshots) in read_legacy(path)? {
+            let symbol = symbol.to_uppercase();
+            let mut days: BTreeMap<NaiveDate, Vec<OptionSnapshot>> = BTreeMap::new();
+            for snapshot in snapshots {
+                days.entry(segment_date(snapshot.timestamp))
+                    .or_default()
+                    .push(snapshot);
+            }
+
+            for (date, snapshots) in days {
+                // The saved index may predate an interrupted import, so check
+                // the segment itself
+                let stored = self.read_segment_info(&symbol, date)?;
+                for snapshot in snapshots {
+                    if stored.binary_search(&snapshot.timestamp).is_err() {
+                        self.append_snapshot(snapshot)?;
+                    }
+                }
+            }
+        }
+
+        self.index.legacy_imported = true;
+        self.write_index()
+    }
+
+    /// Updates the index entry of a segment from disk, returning its sorted
+    /// timestamps.
+    fn read_segment_info(
+        &mut self,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> anyhow::Result<Vec<DateTime<Utc>>> {
+        let path = segment::path(&self.root, symbol, date);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut timestamps: Vec<DateTime<Utc>> = segment::read(&path, symbol)?
+            .iter()
+            .map(|r| r.timestamp)
+            .collect();
+        timestamps.sort();
+
+        let segments = self.index.symbols.entry(symbol.to_string()).or_default();
+        match segments.iter_mut().find(|s| s.date == date) {
+            Some(info) => info.timestamps = timestamps.clone(),
+            None => {
+                segments.push(SegmentInfo {
+                    date,
+                    timestamps: timestamps.clone(),
+                });
+                segments.sort_by_key(|s| s.date);
+            }
         }
+        self.segments.remove(&(symbol.to_string(), date));
+
+        Ok(timestamps)
+    }
+
+    /// Writes the whole database in the legacy single-file format.
+    pub fn export_legacy(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let compressed_bytes = self.export_legacy_bytes()?;
+        atomic::write(path.as_ref(), &compressed_bytes)
     }
 
-    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let bytes = std::fs::read(path)?;
+    /// Reads every segment from disk without caching them, so exporting doesn't
+    /// pull the entire history into memory for the lifetime of the server.
+    pub fn export_legacy_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        encode_legacy(&self.segment_refs())
+    }
+
+    /// Writes one Parquet file per day of `symbol` within `dates` to
+    /// `dir/<symbol>/<date>.parquet`, returning the files written.
+    pub fn export_parquet(
+        &self,
+        dir: impl AsRef<Path>,
+        symbol: &str,
+        dates: impl RangeBounds<NaiveDate>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let symbol = symbol.to_uppercase();
 
-        let mut decoder = GzDecoder::new(&*bytes);
-        let mut decoded_bytes = String::new();
-        decoder.read_to_string(&mut decoded_bytes)?;
+        let mut paths = Vec::new();
+        for date in self.segment_dates(&symbol, dates) {
+            let snapshots = segment::read(&segment::path(&self.root, &symbol, date), &symbol)?;
+            let path =
+                segment::path(dir.as_ref(), &symbol, date).with_extension(parquet::EXTENSION);
+            atomic::write(&path, &parquet::encode(&snapshots)?)?;
+            paths.push(path);
+        }
 
-        Ok(serde_json::from_str(&decoded_bytes)?)
+        Ok(paths)
     }
 
-    pub fn load() -> anyhow::Result<Self> {
-        Self::from_file(DEFAULT_FILE_PATH)
+    /// Every snapshot of `symbol` within `dates` as a single Parquet file. Like
+    /// `export_legacy_bytes`, segments are read without being cached.
+    pub fn export_parquet_bytes(
+        &self,
+        symbol: &str,
+        dates: impl RangeBounds<NaiveDate>,
+    ) -> anyhow::Result<Vec<u8>> {
+        encode_parquet(&self.symbol_segment_refs(symbol, dates))
     }
 
-    pub fn add_option_info(&mut self, symbol: &str, data: Vec<OptionInfo>) {
+    /// Every segment as indexed right now, to be read after the database is
+    /// released.
+    pub fn segment_refs(&self) -> Vec<SegmentRef> {
+        self.index
+            .symbols
+            .iter()
+            .flat_map(|(symbol, segments)| {
+                segments
+                    .iter()
+                    .map(move |info| SegmentRef::new(&self.root, symbol, info))
+            })
+            .collect()
+    }
+
+    /// The segments of `symbol` within `dates`, like [`Self::segment_refs`].
+    pub fn symbol_segment_refs(
+        &self,
+        symbol: &str,
+        dates: impl RangeBounds<NaiveDate>,
+    ) -> Vec<SegmentRef> {
         let symbol = symbol.to_uppercase();
 
-        let entry = self.options.entry(symbol).or_insert_with(Vec::new);
+        self.index
+            .symbols
+            .get(&symbol)
+            .map(|segments| {
+                segments
+                    .iter()
+                    .filter(|s| dates.contains(&s.date))
+                    .map(|info| SegmentRef::new(&self.root, &symbol, info))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn segment_dates(&self, symbol: &str, dates: impl RangeBounds<NaiveDate>) -> Vec<NaiveDate> {
+        self.index
+            .symbols
+            .get(symbol)
+            .map(|segments| {
+                segments
+                    .iter()
+                    .map(|s| s.date)
+                    .filter(|d| dates.contains(d))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn add_option_info(&mut self, symbol: &str, data: Vec<OptionInfo>) -> anyhow::Result<()> {
+        self.add_snapshot(OptionSnapshot {
+            timestamp: snapshot_timestamp(&data).unwrap_or_else(Utc::now),
+            symbol: symbol.to_string(),
+            provider: None,
+            quote: None,
+            options: data,
+        })
+    }
+
+    pub fn add_snapshot(&mut self, record: OptionSnapshot) -> anyhow::Result<()> {
+        self.append_snapshot(record)?;
+        self.write_index()
+    }
+
+    /// Adds a snapshot to its segment and the in-memory index only. Segments
+    /// left out of the saved index are picked up by `reconcile` on the next
+    /// open.
+    fn append_snapshot(&mut self, mut record: OptionSnapshot) -> anyhow::Result<()> {
+        self.check_writable()?;
+        record.symbol = record.symbol.to_uppercase();
+        let symbol = record.symbol.clone();
+        let date = segment_date(record.timestamp);
 
-        entry.push(data);
-        if let Err(e) = self.write() {
-            log::error!("{}", e);
+        segment::append(&segment::path(&self.root, &symbol, date), &record)?;
+
+        let segments = self.index.symbols.entry(symbol.clone()).or_default();
+        match segments.iter_mut().find(|s| s.date == date) {
+            Some(info) => {
+                let i = info.timestamps.partition_point(|t| *t <= record.timestamp);
+                info.timestamps.insert(i, record.timestamp);
+            }
+            None => {
+                segments.push(SegmentInfo {
+                    date,
+                    timestamps: vec![record.timestamp],
+                });
+                segments.sort_by_key(|s| s.date);
+            }
+        }
+
+        if let Some(records) = self.segments.get_mut(&(symbol, date)) {
+            let i = records.partition_point(|r| r.timestamp <= record.timestamp);
+            records.insert(i, record);
         }
+
+        Ok(())
     }
 
     pub fn has_symbol(&self, symbol: &str) -> bool {
         let symbol = symbol.to_uppercase();
 
-        self.options.contains_key(&symbol)
+        self.index.symbols.contains_key(&symbol)
+    }
+
+    pub fn option_chain(&mut self, symbol: &str) -> anyhow::Result<Option<&Vec<OptionInfo>>> {
+        Ok(self.snapshot(symbol)?.map(|s| &s.options))
+    }
+
+    /// The most recent option chain taken at or before `time`.
+    pub fn option_chain_at(
+        &mut self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Option<&Vec<OptionInfo>>> {
+        Ok(self.snapshot_at(symbol, time)?.map(|s| &s.options))
+    }
+
+    pub fn snapshot(&mut self, symbol: &str) -> anyhow::Result<Option<&OptionSnapshot>> {
+        let symbol = symbol.to_uppercase();
+
+        let date = match self.index.symbols.get(&symbol).and_then(|s| s.last()) {
+            Some(info) => info.date,
+            None => return Ok(None),
+        };
+
+        let records = self.segment(&symbol, date)?;
+        Ok(records.last())
+    }
+
+    /// The most recent snapshot taken at or before `time`.
+    pub fn snapshot_at(
+        &mut self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Option<&OptionSnapshot>> {
+        let symbol = symbol.to_uppercase();
+
+        let date = self.index.symbols.get(&symbol).and_then(|segments| {
+            segments
+                .iter()
+                .rev()
+                .find(|s| s.timestamps.iter().any(|t| *t <= time))
+                .map(|s| s.date)
+        });
+        let date = match date {
+            Some(date) => date,
+            None => return Ok(None),
+        };
+
+        let records = self.segment(&symbol, date)?;
+        Ok(records.iter().rev().find(|r| r.timestamp <= time))
+    }
+
+    /// Every snapshot of `symbol` taken within `range`, oldest first.
+    pub fn snapshots(
+        &mut self,
+        symbol: &str,
+        range: impl RangeBounds<DateTime<Utc>>,
+    ) -> anyhow::Result<Vec<OptionSnapshot>> {
+        let symbol = symbol.to_uppercase();
+
+        let dates: Vec<NaiveDate> = self
+            .index
+            .symbols
+            .get(&symbol)
+            .map(|segments| {
+                segments
+                    .iter()
+                    .filter(|s| s.timestamps.iter().any(|t| range.contains(t)))
+                    .map(|s| s.date)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut result = Vec::new();
+        for date in dates {
+            let records = self.segment(&symbol, date)?;
+            result.extend(
+                records
+                    .iter()
+                    .filter(|r| range.contains(&r.timestamp))
+                    .cloned(),
+            );
+        }
+
+        Ok(result)
     }
 
-    pub fn option_chain(&self, symbol: &str) -> Option<&Vec<OptionInfo>> {
+    pub fn snapshot_timestamps(&self, symbol: &str) -> Vec<DateTime<Utc>> {
         let symbol = symbol.to_uppercase();
 
-        self.options.get(&symbol).map(|v| v.last()).flatten()
+        self.index
+            .symbols
+            .get(&symbol)
+            .map(|segments| {
+                segments
+                    .iter()
+                    .flat_map(|s| s.timestamps.iter().cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     pub fn symbols(&self) -> Vec<String> {
-        self.options.keys().cloned().collect()
+        self.index.symbols.keys().cloned().collect()
     }
 
-    fn write(&self) -> anyhow::Result<()> {
-        let json = serde_json::to_vec_pretty(&self)?;
+    /// Removes the snapshots `policy` no longer keeps as of `now`. A dry run
+    /// only reports them. Which snapshots go is decided from the index, so
+    /// only segments that actually lose snapshots are read and rewritten.
+    pub fn compact(
+        &mut self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> anyhow::Result<CompactionReport> {
+        let (report, segments) = self.plan_compaction(policy, now, dry_run);
+        for (segment, expired) in segments {
+            let rewrite = segment.rewrite_without(&expired)?;
+            self.replace_segment(rewrite)?;
+        }
 
-        // Debug
-        // std::fs::write("data/db.json", &json)?;
+        Ok(report)
+    }
 
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
-        encoder.write_all(&json)?;
-        let compressed_bytes = encoder.finish()?;
+    /// What `compact` would remove, along with the segments to rewrite and
+    /// the snapshots each of them loses, none for a dry run. Only the index
+    /// is read, so the segments can be rewritten without holding the
+    /// database.
+    pub fn plan_compaction(
+        &self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+        dry_run: bool,
+    ) -> (CompactionReport, Vec<ExpiredSegment>) {
+        let mut report = CompactionReport {
+            dry_run,
+            ..Default::default()
+        };
+        let mut rewrites = Vec::new();
 
-        if let Some(parent) = self.file_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        for (symbol, segments) in &self.index.symbols {
+            let timestamps = self.snapshot_timestamps(symbol);
+            let expired = policy.expired(&timestamps, now);
+            report.kept += timestamps.len() - expired.len();
+            if expired.is_empty() {
+                continue;
+            }
+
+            if !dry_run {
+                let expired: HashSet<DateTime<Utc>> = expired.iter().cloned().collect();
+                for info in segments {
+                    let lost: HashSet<DateTime<Utc>> = info
+                        .timestamps
+                        .iter()
+                        .filter(|t| expired.contains(t))
+                        .cloned()
+                        .collect();
+                    if !lost.is_empty() {
+                        rewrites.push((SegmentRef::new(&self.root, symbol, info), lost));
+                    }
+                }
+            }
+            report.removed.insert(symbol.clone(), expired);
+        }
+
+        (report, rewrites)
+    }
+
+    /// Swaps in a segment rewritten by [`SegmentRef::rewrite_without`], unless
+    /// snapshots were added to it after it was listed. Returns whether it was
+    /// swapped in.
+    pub fn replace_segment(&mut self, rewrite: SegmentRewrite) -> anyhow::Result<bool> {
+        let SegmentRewrite {
+            segment,
+            pending_path,
+            remaining,
+        } = rewrite;
+        let key = (segment.symbol.clone(), segment.date);
+
+        let current: HashSet<DateTime<Utc>> = self
+            .index
+            .symbols
+            .get(&segment.symbol)
+            .and_then(|segments| segments.iter().find(|s| s.date == segment.date))
+            .map(|info| info.timestamps.iter().cloned().collect())
+            .unwrap_or_default();
+        if current != segment.timestamps {
+            if let Some(pending_path) = pending_path {
+                std::fs::remove_file(pending_path)?;
+            }
+            return Ok(false);
+        }
+
+        match pending_path {
+            Some(pending_path) => atomic::replace(&pending_path, &segment.path)?,
+            None if segment.path.exists() => std::fs::remove_file(&segment.path)?,
+            None => {}
+        }
+
+        if let Some(segments) = self.index.symbols.get_mut(&segment.symbol) {
+            for info in segments.iter_mut().filter(|s| s.date == segment.date) {
+                info.timestamps = remaining.clone();
+            }
+            segments.retain(|s| !s.timestamps.is_empty());
+            if segments.is_empty() {
+                self.index.symbols.remove(&segment.symbol);
+            }
         }
-        std::fs::write(&self.file_path, &compressed_bytes)?;
+        self.segments.remove(&key);
+        self.write_index()?;
 
+        Ok(true)
+    }
+
+    fn segment(&mut self, symbol: &str, date: NaiveDate) -> anyhow::Result<&Vec<OptionSnapshot>> {
+        let key = (symbol.to_string(), date);
+
+        if !self.segments.contains_key(&key) {
+            let path = segment::path(&self.root, symbol, date);
+            let mut records = if self.read_only {
+                segment::read_without_repair(&path, symbol)?
+            } else {
+                segment::read(&path, symbol)?
+            };
+            records.sort_by_key(|r| r.timestamp);
+            self.segments.insert(key.clone(), records);
+        }
+
+        Ok(&self.segments[&key])
+    }
+
+    /// Brings the index up to date with the segments on disk. Anything written
+    /// after the index was last saved, e.g. right before a crash, can only be
+    /// in the latest segment of a symbol or in segments the index doesn't know.
+    fn reconcile(&mut self) -> anyhow::Result<bool> {
+        let mut changed = false;
+
+        for (symbol, date) in segment::list(&self.root)? {
+            let last_date = self
+                .index
+                .symbols
+                .get(&symbol)
+                .and_then(|s| s.last())
+                .map(|s| s.date);
+            if matches!(last_date, Some(last_date) if date < last_date) {
+                continue;
+            }
+
+            let timestamps: Vec<DateTime<Utc>> = self
+                .segment(&symbol, date)?
+                .iter()
+                .map(|r| r.timestamp)
+                .collect();
+
+            let segments = self.index.symbols.entry(symbol).or_default();
+            match segments.iter_mut().find(|s| s.date == date) {
+                Some(info) if info.timestamps == timestamps => {}
+                Some(info) => {
+                    info.timestamps = timestamps;
+                    changed = true;
+                }
+                None => {
+                    segments.push(SegmentInfo { date, timestamps });
+                    segments.sort_by_key(|s| s.date);
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    fn check_writable(&self) -> anyhow::Result<()> {
+        if self.read_only {
+            anyhow::bail!("{} was opened read-only", self.root.display());
+        }
         Ok(())
     }
+
+    fn write_index(&self) -> anyhow::Result<()> {
+        self.check_writable()?;
+        let json = serde_json::to_vec(&self.index)?;
+        let bytes = atomic::encode(INDEX_VERSION, &json);
+
+        atomic::write_with_backups(&self.root.join(INDEX_FILE), &bytes, INDEX_BACKUP_COUNT)
+    }
+}
+
+/// The root named by `DB_PATH`, or `default` when it isn't set.
+pub fn root_from_env(default: &str) -> PathBuf {
+    std::env::var(DB_PATH_ENV)
+        .unwrap_or_else(|_| default.to_string())
+        .into()
+}
+
+/// A segment as the index knew it, which can be read without holding the
+/// database. Snapshots appended after it was taken are left out, and the
+/// segment is never repaired since a damaged member may be an append still
+/// being written.
+#[derive(Clone, Debug)]
+pub struct SegmentRef {
+    symbol: Symbol,
+    date: NaiveDate,
+    path: PathBuf,
+    timestamps: HashSet<DateTime<Utc>>,
+}
+
+impl SegmentRef {
+    fn new(root: &Path, symbol: &str, info: &SegmentInfo) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            date: info.date,
+            path: segment::path(root, symbol, info.date),
+            timestamps: info.timestamps.iter().cloned().collect(),
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The times of the snapshots the segment held when it was listed.
+    pub fn timestamps(&self) -> &HashSet<DateTime<Utc>> {
+        &self.timestamps
+    }
+
+    /// The snapshots of the segment, none if compaction has since removed it.
+    pub fn read(&self) -> anyhow::Result<Vec<OptionSnapshot>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut records = segment::read_without_repair(&self.path, &self.symbol)?;
+        records.retain(|r| self.timestamps.contains(&r.timestamp));
+        records.sort_by_key(|r| r.timestamp);
+        Ok(records)
+    }
+
+    /// Writes the segment without the snapshots in `expired` next to it,
+    /// leaving the segment itself as it is until
+    /// [`FileDb::replace_segment`].
+    pub fn rewrite_without(
+        self,
+        expired: &HashSet<DateTime<Utc>>,
+    ) -> anyhow::Result<SegmentRewrite> {
+        let mut records = self.read()?;
+        records.retain(|r| !expired.contains(&r.timestamp));
+
+        let pending_path = if records.is_empty() {
+            None
+        } else {
+            Some(segment::write_pending(&self.path, &records)?)
+        };
+        Ok(SegmentRewrite {
+            remaining: records.iter().map(|r| r.timestamp).collect(),
+            segment: self,
+            pending_path,
+        })
+    }
+}
+
+/// A segment to compact and the snapshots it loses.
+pub type ExpiredSegment = (SegmentRef, HashSet<DateTime<Utc>>);
+
+/// A compacted segment waiting to be swapped in. Without a pending file,
+/// nothing is left and the segment is removed.
+#[derive(Debug)]
+pub struct SegmentRewrite {
+    segment: SegmentRef,
+    pending_path: Option<PathBuf>,
+    /// Oldest first
+    remaining: Vec<DateTime<Utc>>,
+}
+
+/// The segments in the legacy single-file format.
+pub fn encode_legacy(segments: &[SegmentRef]) -> anyhow::Result<Vec<u8>> {
+    let mut legacy: LegacyDb<Vec<OptionInfo>> = LegacyDb {
+        file_path: DEFAULT_FILE_PATH.into(),
+        options: HashMap::new(),
+    };
+
+    for segment in segments {
+        let snapshots = legacy.options.entry(segment.symbol.clone()).or_default();
+        snapshots.extend(segment.read()?.into_iter().map(|r| r.options));
+    }
+
+    let json = serde_json::to_vec(&legacy)?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&json)?;
+    Ok(encoder.finish()?)
+}
+
+/// The snapshots of the segments as a single Parquet file.
+pub fn encode_parquet(segments: &[SegmentRef]) -> anyhow::Result<Vec<u8>> {
+    let mut snapshots = Vec::new();
+    for segment in segments {
+        snapshots.extend(segment.read()?);
+    }
+
+    parquet::encode(&snapshots)
+}
+
+fn read_index(path: &Path) -> anyhow::Result<Index> {
+    let bytes = std::fs::read(path)?;
+    let (version, json) = atomic::decode(&bytes)?;
+    if version > INDEX_VERSION {
+        anyhow::bail!("Unsupported index version {}", version);
+    }
+
+    Ok(serde_json::from_slice(json)?)
+}
+
+/// Falls back to the newest readable backup, or an empty index that will be
+/// rebuilt from the segments.
+fn recover_index(path: &Path) -> Index {
+    for backup in atomic::backups(path, INDEX_BACKUP_COUNT) {
+        match read_index(&backup) {
+            Ok(index) => {
+                log::warn!("Recovered index from {}", backup.display());
+                return index;
+            }
+            Err(e) => log::error!("Unreadable index backup {}: {}", backup.display(), e),
+        }
+    }
+
+    log::warn!("Rebuilding index from segments");
+    Index::default()
+}
+
+/// Reads the snapshots stored in a legacy `db.gz` file, upgrading them to the
+/// current layout.
+pub fn read_legacy(path: impl AsRef<Path>) -> anyhow::Result<HashMap<Symbol, Vec<OptionSnapshot>>> {
+    let bytes = std::fs::read(path)?;
+
+    let mut decoder = GzDecoder::new(&*bytes);
+    let mut decoded_bytes = String::new();
+    decoder.read_to_string(&mut decoded_bytes)?;
+
+    let legacy: LegacyDb<Value> = serde_json::from_str(&decoded_bytes)?;
+    legacy
+        .options
+        .into_iter()
+        .map(|(symbol, snapshots)| {
+            let records = snapshots
+                .into_iter()
+                .map(|options| migrations::migrate_legacy_snapshot(options, &symbol))
+                .collect::<anyhow::Result<_>>()?;
+            Ok((symbol, records))
+        })
+        .collect()
+}
+
+/// The day, in New York, of the segment a snapshot taken at `timestamp`
+/// belongs to.
+fn segment_date(timestamp: DateTime<Utc>) -> NaiveDate {
+    timestamp.with_timezone(&New_York).date_naive()
+}
+
+/// Bare option lists don't carry their own timestamp, so use the time the
+/// first option was captured.
+fn snapshot_timestamp(snapshot: &[OptionInfo]) -> Option<DateTime<Utc>> {
+    let option = snapshot.first()?;
+    DateTime::parse_from_rfc3339(&option.timestamp)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
 }
 
 impl Default for FileDb {
     fn default() -> Self {
-        Self::new(DEFAULT_FILE_PATH)
+        Self::new(DEFAULT_DB_PATH)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::at;
+
+    fn test_db(name: &str) -> FileDb {
+        let path = Path::new("data/test").join(name);
+        let _ = std::fs::remove_dir_all(&path);
+        FileDb::new(path)
+    }
 
-    pub const TEST_FILE_PATH: &str = "data/test_db.gz";
+    fn option_at(time: &str, open_interest: u64) -> OptionInfo {
+        let mut option = OptionInfo::test();
+        option.timestamp = time.to_string();
+        option.open_interest = open_interest;
+        option
+    }
 
     #[test]
     fn db() {
-        let mut db = FileDb::new(TEST_FILE_PATH);
+        let mut db = test_db("db");
 
-        db.add_option_info("TST", vec![OptionInfo::test()]);
-        db.add_option_info("TST", vec![OptionInfo::test()]);
-        db.add_option_info("TST", vec![OptionInfo::test()]);
+        db.add_option_info("TST", vec![OptionInfo::test()]).unwrap();
+        db.add_option_info("TST", vec![OptionInfo::test()]).unwrap();
+        db.add_option_info("TST", vec![OptionInfo::test()]).unwrap();
 
-        let oi = db.option_chain("TST").unwrap();
+        let oi = db.option_chain("TST").unwrap().unwrap();
         assert_eq!(oi[0].symbol, "TST");
 
-        db.write().unwrap();
+        db.add_snapshot(OptionSnapshot::test()).unwrap();
+
+        let mut db2 = FileDb::open(&db.root).unwrap();
+
+        assert!(db2.has_symbol("tst"));
+        assert_eq!(db2.snapshot_timestamps("TST").len(), 4);
+        assert_eq!(db2.option_chain("TST").unwrap().unwrap()[0].symbol, "TST");
+        let snapshot = db2.snapshot("TST").unwrap().unwrap();
+        assert_eq!(snapshot.provider.as_deref(), Some("test"));
+        assert_eq!(snapshot.spot(), Some(10.0));
+    }
+
+    #[test]
+    fn history() {
+        let mut db = test_db("history");
+        let times = [
+            "2021-06-10T14:00:00+00:00",
+            "2021-06-10T15:00:00+00:00",
+            "2021-06-11T16:00:00+00:00",
+        ];
+        for (i, time) in times.iter().enumerate() {
+            db.add_option_info("TST", vec![option_at(time, i as u64)])
+                .unwrap();
+        }
+
+        let timestamps = db.snapshot_timestamps("tst");
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(timestamps[1].to_rfc3339(), times[1]);
+
+        let mut db = FileDb::open(&db.root).unwrap();
+        assert!(db
+            .option_chain_at("TST", at("2021-06-10T13:59:59+00:00"))
+            .unwrap()
+            .is_none());
+        let chain = db
+            .option_chain_at("TST", at("2021-06-10T15:00:00+00:00"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(chain[0].open_interest, 1);
+        let chain = db
+            .option_chain_at("TST", at("2021-06-12T00:00:00+00:00"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(chain[0].open_interest, 2);
+
+        let snapshots = db.snapshots("TST", at(times[1])..).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].timestamp, at(times[1]));
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut db = test_db("out_of_order");
+        db.add_option_info("TST", vec![option_at("2021-06-10T15:00:00+00:00", 2)])
+            .unwrap();
+        // Read the segment into the cache before the earlier snapshot arrives
+        db.snapshot("TST").unwrap();
+        db.add_option_info("TST", vec![option_at("2021-06-10T14:00:00+00:00", 1)])
+            .unwrap();
+
+        for mut db in [db.clone(), FileDb::open(&db.root).unwrap()] {
+            assert_eq!(
+                db.snapshot_timestamps("TST"),
+                vec![at("2021-06-10T14:00:00Z"), at("2021-06-10T15:00:00Z")]
+            );
+            let latest = db.snapshot("TST").unwrap().unwrap();
+            assert_eq!(latest.options[0].open_interest, 2);
+            let earlier = db
+                .snapshot_at("TST", at("2021-06-10T14:30:00Z"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(earlier.options[0].open_interest, 1);
+        }
+    }
+
+    #[test]
+    fn recovery() {
+        let mut db = test_db("recovery");
+        let times = ["2021-06-10T14:00:00+00:00", "2021-06-11T14:00:00+00:00"];
+        for time in times {
+            db.add_option_info("TST", vec![option_at(time, 1)]).unwrap();
+        }
+        let index_path = db.root.join(INDEX_FILE);
+
+        // A snapshot that made it to disk before the index was updated
+        let record = OptionSnapshot {
+            timestamp: at("2021-06-11T15:00:00+00:00"),
+            symbol: "TST".to_string(),
+            provider: None,
+            quote: None,
+            options: vec![option_at("2021-06-11T15:00:00+00:00", 2)],
+        };
+        let date = NaiveDate::from_ymd_opt(2021, 6, 11).unwrap();
+        segment::append(&segment::path(&db.root, "TST", date), &record).unwrap();
+        let mut db = FileDb::open(&db.root).unwrap();
+        assert_eq!(db.snapshot_timestamps("TST").len(), 3);
+        assert_eq!(db.option_chain("TST").unwrap().unwrap()[0].open_interest, 2);
+
+        // A corrupt index falls back to a backup
+        std::fs::write(&index_path, b"market_analyzer 1 00000000\n{}").unwrap();
+        let db = FileDb::open(&db.root).unwrap();
+        assert_eq!(db.snapshot_timestamps("TST").len(), 3);
+
+        // Without any backups the index is rebuilt from the segments
+        for backup in atomic::backups(&index_path, INDEX_BACKUP_COUNT) {
+            std::fs::remove_file(backup).unwrap();
+        }
+        std::fs::write(&index_path, b"garbage").unwrap();
+        let db = FileDb::open(&db.root).unwrap();
+        assert_eq!(db.snapshot_timestamps("TST").len(), 3);
+        assert!(read_index(&index_path).is_ok());
+    }
+
+    #[test]
+    fn read_only() {
+        let mut db = test_db("read_only");
+        db.add_option_info("TST", vec![option_at("2021-06-11T14:00:00+00:00", 1)])
+            .unwrap();
+        db.add_option_info("TST", vec![option_at("2021-06-11T15:00:00+00:00", 1)])
+            .unwrap();
+
+        let index_path = db.root.join(INDEX_FILE);
+        std::fs::remove_file(&index_path).unwrap();
+        let date = NaiveDate::from_ymd_opt(2021, 6, 11).unwrap();
+        let segment_path = segment::path(&db.root, "TST", date);
+        let mut bytes = std::fs::read(&segment_path).unwrap();
+        bytes.extend(b"torn append");
+        std::fs::write(&segment_path, &bytes).unwrap();
+
+        let mut db = FileDb::open_read_only(&db.root).unwrap();
+        assert_eq!(db.snapshots("TST", ..).unwrap().len(), 2);
+        assert!(db
+            .add_snapshot(OptionSnapshot::new(
+                Utc::now(),
+                "TST",
+                "test",
+                None,
+                Vec::new()
+            ))
+            .is_err());
+        assert!(!index_path.exists());
+        assert_eq!(std::fs::read(&segment_path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut db = test_db("compaction");
+        let times = [
+            "2021-06-01T14:00:00+00:00",
+            "2021-06-01T20:00:00+00:00",
+            "2021-06-10T14:00:00+00:00",
+            "2021-06-10T14:30:00+00:00",
+            "2021-06-29T14:00:00+00:00",
+        ];
+        for time in times {
+            db.add_option_info("TST", vec![option_at(time, 1)]).unwrap();
+        }
+        let policy: RetentionPolicy = "all=5,hourly=20,daily=forever".parse().unwrap();
+        let now = at("2021-06-30T20:00:00+00:00");
+
+        let report = db.compact(&policy, now, true).unwrap();
+        assert_eq!(report.removed_count(), 2);
+        assert_eq!(report.kept, 3);
+        assert_eq!(db.snapshot_timestamps("TST").len(), 5);
+
+        let report = db.compact(&policy, now, false).unwrap();
+        assert_eq!(report.removed["TST"], vec![at(times[0]), at(times[2])]);
+
+        let mut db = FileDb::open(&db.root).unwrap();
+        let expected: Vec<_> = [times[1], times[3], times[4]]
+            .iter()
+            .map(|t| at(t))
+            .collect();
+        assert_eq!(db.snapshot_timestamps("TST"), expected);
+        assert_eq!(db.snapshots("TST", ..).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn compaction_skips_changed_segment() {
+        let mut db = test_db("compaction_skips_changed_segment");
+        db.add_option_info("TST", vec![option_at("2021-06-01T14:00:00+00:00", 1)])
+            .unwrap();
+        db.add_option_info("TST", vec![option_at("2021-06-01T15:00:00+00:00", 1)])
+            .unwrap();
+        let policy: RetentionPolicy = "all=5,hourly=0,daily=forever".parse().unwrap();
+        let now = at("2021-06-30T20:00:00+00:00");
+
+        let (_, mut segments) = db.plan_compaction(&policy, now, false);
+        let (segment, expired) = segments.pop().unwrap();
+        let rewrite = segment.rewrite_without(&expired).unwrap();
+
+        db.add_option_info("TST", vec![option_at("2021-06-01T16:00:00+00:00", 1)])
+            .unwrap();
+        assert!(!db.replace_segment(rewrite).unwrap());
+        assert_eq!(db.snapshot_timestamps("TST").len(), 3);
+
+        let mut db = FileDb::open(&db.root).unwrap();
+        assert_eq!(db.snapshots("TST", ..).unwrap().len(), 3);
+        assert_eq!(std::fs::read_dir(db.root.join("TST")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn parquet_export() {
+        let mut db = test_db("parquet");
+        for time in [
+            "2021-06-10T14:00:00+00:00",
+            "2021-06-10T15:00:00+00:00",
+            "2021-06-11T14:00:00+00:00",
+        ] {
+            db.add_option_info("/ES", vec![option_at(time, 1)]).unwrap();
+        }
+
+        let day = NaiveDate::from_ymd_opt(2021, 6, 10).unwrap();
+        let paths = db
+            .export_parquet(db.root.join("export"), "/es", day..)
+            .unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("%2FES/2021-06-10.parquet"));
+        assert!(paths.iter().all(|p| p.exists()));
+
+        let next_day = day.succ_opt().unwrap();
+        assert!(!db
+            .export_parquet_bytes("/ES", next_day..)
+            .unwrap()
+            .is_empty());
+        assert!(db.export_parquet("x", "/ES", ..day).unwrap().is_empty());
+    }
+
+    #[test]
+    fn segment_refs() {
+        let mut db = test_db("segment_refs");
+        db.add_option_info("TST", vec![option_at("2021-06-10T14:00:00+00:00", 1)])
+            .unwrap();
+        let segments = db.symbol_segment_refs("tst", ..);
+        assert_eq!(segments.len(), 1);
+
+        // Snapshots added after the segments were listed are left out
+        db.add_option_info("TST", vec![option_at("2021-06-10T15:00:00+00:00", 2)])
+            .unwrap();
+        let snapshots = segments[0].read().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].options[0].open_interest, 1);
+        assert_eq!(db.segment_refs()[0].read().unwrap().len(), 2);
+
+        db.compact(&RetentionPolicy::default(), Utc::now(), false)
+            .unwrap();
+        assert!(segments[0].read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let mut db = test_db("legacy");
+        db.add_option_info("TST", vec![option_at("2021-06-10T14:00:00+00:00", 1)])
+            .unwrap();
+        db.add_option_info("/ES", vec![option_at("2021-06-10T14:00:00+00:00", 2)])
+            .unwrap();
+
+        let legacy_path = db.root.join("db.gz");
+        db.export_legacy(&legacy_path).unwrap();
+
+        // An import that stopped after writing one segment, before the index
+        let root = test_db("legacy_import").root;
+        let es = &read_legacy(&legacy_path).unwrap()["/ES"][0];
+        segment::append(&segment::path(&root, "/ES", segment_date(es.timestamp)), es).unwrap();
 
-        let db2 = FileDb::from_file(TEST_FILE_PATH).unwrap();
+        let mut imported = FileDb::load(&root, Some(&legacy_path)).unwrap();
+        assert_eq!(imported.symbols(), vec!["/ES", "TST"]);
+        assert_eq!(imported.snapshot_timestamps("/ES").len(), 1);
+        let chain = imported.option_chain("/es").unwrap().unwrap();
+        assert_eq!(chain[0].open_interest, 2);
 
-        assert_eq!(db2.option_chain("TST").unwrap()[0].symbol, "TST");
+        // Finished imports aren't repeated, even once the file has changed
+        db.add_option_info("TST", vec![option_at("2021-06-10T15:00:00+00:00", 3)])
+            .unwrap();
+        db.export_legacy(&legacy_path).unwrap();
+        let reopened = FileDb::load(&root, Some(&legacy_path)).unwrap();
+        assert_eq!(reopened.snapshot_timestamps("TST").len(), 1);
     }
 }
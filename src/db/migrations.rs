@@ -0,0 +1,256 @@
+//! Upgrades snapshots written by older versions of the app. Every change to
+//! the stored layout of `OptionSnapshot` or `OptionInfo` bumps `CURRENT_VERSION` and
+//! adds a step to `MIGRATIONS` that converts the previous layout, so old data
+//! keeps loading after the types change.
+
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+use crate::types::{OptionSnapshot, UnderlyingType};
+
+pub const CURRENT_VERSION: u32 = 5;
+
+/// Records written before versioning was added
+const UNVERSIONED: u32 = 1;
+
+/// Steps get the symbol the snapshot is stored under, since older layouts
+/// didn't record it themselves.
+type Migration = fn(&mut Map<String, Value>, &str) -> anyhow::Result<()>;
+
+/// `MIGRATIONS[i]` upgrades version `i + 1` to version `i + 2`.
+const MIGRATIONS: &[Migration] = &[
+    default_multiplier,
+    snapshot_metadata,
+    underlying_type,
+    higher_order_greeks,
+];
+
+/// Greeks added in version 5
+const HIGHER_ORDER_GREEKS: &[&str] = &["speed", "zomma", "color", "vomma", "veta", "ultima"];
+
+pub fn migrate_record(value: Value, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+    let mut record = match value {
+        Value::Object(record) => record,
+        _ => anyhow::bail!("Snapshot is not an object"),
+    };
+
+    let version = match record.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Invalid snapshot version: {}", version))?
+            as u32,
+        None => UNVERSIONED,
+    };
+    if version == 0 || version > CURRENT_VERSION {
+        anyhow::bail!(
+            "Unsupported snapshot version {} (current is {})",
+            version,
+            CURRENT_VERSION
+        );
+    }
+
+    for migration in &MIGRATIONS[(version - UNVERSIONED) as usize..] {
+        migration(&mut record, symbol)?;
+    }
+    record.insert("version".to_string(), CURRENT_VERSION.into());
+
+    Ok(serde_json::from_value(Value::Object(record))?)
+}
+
+/// Snapshots in the legacy `db.gz` file are bare lists of options in the
+/// unversioned layout, timestamped by their first option.
+pub fn migrate_legacy_snapshot(options: Value, symbol: &str) -> anyhow::Result<OptionSnapshot> {
+    let timestamp = options
+        .get(0)
+        .and_then(|option| option.get("timestamp"))
+        .and_then(Value::as_str)
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+
+    let mut record = Map::new();
+    record.insert("timestamp".to_string(), serde_json::to_value(timestamp)?);
+    record.insert("options".to_string(), options);
+
+    migrate_record(Value::Object(record), symbol)
+}
+
+fn options_mut(
+    record: &mut Map<String, Value>,
+) -> anyhow::Result<impl Iterator<Item = &mut Map<String, Value>>> {
+    let options = record
+        .get_mut("options")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow::anyhow!("Snapshot has no options"))?;
+
+    Ok(options.iter_mut().filter_map(Value::as_object_mut))
+}
+
+/// Version 1 didn't store contract multipliers. Everything collected then was
+/// a standard equity or index option.
+fn default_multiplier(record: &mut Map<String, Value>, _symbol: &str) -> anyhow::Result<()> {
+    for option in options_mut(record)? {
+        if option.get("multiplier").is_none_or(Value::is_null) {
+            option.insert("multiplier".to_string(), 100.0.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 2 stored only the capture time and the options. Neither the quote
+/// of the underlying nor the provider the chain came from were kept.
+fn snapshot_metadata(record: &mut Map<String, Value>, symbol: &str) -> anyhow::Result<()> {
+    record.insert("symbol".to_string(), symbol.to_uppercase().into());
+    record.entry("provider").or_insert(Value::Null);
+    record.entry("quote").or_insert(Value::Null);
+
+    Ok(())
+}
+
+/// Version 3 didn't record what the options were on, which is now needed to
+/// pick the pricing model. It's inferred from the symbol the snapshot is
+/// stored under.
+fn underlying_type(record: &mut Map<String, Value>, symbol: &str) -> anyhow::Result<()> {
+    let underlying_type = serde_json::to_value(UnderlyingType::infer(symbol))?;
+    for option in options_mut(record)? {
+        if option.get("underlying_type").is_none_or(Value::is_null) {
+            option.insert("underlying_type".to_string(), underlying_type.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 4 stored greeks up to vanna and charm. The higher-order ones
+/// weren't computed then, so they're left at zero like any other greek a
+/// provider doesn't report.
+fn higher_order_greeks(record: &mut Map<String, Value>, _symbol: &str) -> anyhow::Result<()> {
+    for option in options_mut(record)? {
+        if let Some(greeks) = option.get_mut("greeks").and_then(Value::as_object_mut) {
+            for greek in HIGHER_ORDER_GREEKS {
+                greeks.entry(*greek).or_insert_with(|| 0.0.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OptionInfo;
+
+    fn unversioned_option() -> Value {
+        let mut option = serde_json::to_value(OptionInfo::test()).unwrap();
+        let fields = option.as_object_mut().unwrap();
+        for field in [
+            "multiplier",
+            "settlement_type",
+            "days_to_expiration",
+            "bid",
+            "ask",
+            "underlying_type",
+        ] {
+            fields.remove(field);
+        }
+        let greeks = fields["greeks"].as_object_mut().unwrap();
+        for greek in HIGHER_ORDER_GREEKS {
+            greeks.remove(*greek);
+        }
+        option
+    }
+
+    #[test]
+    fn unversioned_record() {
+        let value = serde_json::json!({
+            "timestamp": "2021-06-10T14:00:00Z",
+            "options": [unversioned_option()],
+        });
+
+        let record = migrate_record(value, "tst").unwrap();
+        assert_eq!(record.symbol, "TST");
+        assert_eq!(record.options[0].multiplier, Some(100.0));
+        assert_eq!(record.options[0].bid, None);
+        assert!(record.provider.is_none());
+        assert!(record.quote.is_none());
+    }
+
+    #[test]
+    fn legacy_snapshot() {
+        let mut option = unversioned_option();
+        option["timestamp"] = "2021-06-10T14:00:00+00:00".into();
+
+        let record = migrate_legacy_snapshot(Value::Array(vec![option]), "TST").unwrap();
+        assert_eq!(record.timestamp.to_rfc3339(), "2021-06-10T14:00:00+00:00");
+        assert_eq!(record.options[0].multiplier, Some(100.0));
+    }
+
+    #[test]
+    fn version_two_record() {
+        let value = serde_json::json!({
+            "version": 2,
+            "timestamp": "2021-06-10T14:00:00Z",
+            "options": [OptionInfo::test()],
+        });
+
+        let record = migrate_record(value, "/es").unwrap();
+        assert_eq!(record.symbol, "/ES");
+        assert_eq!(record.options.len(), 1);
+        assert!(record.quote.is_none());
+    }
+
+    #[test]
+    fn version_three_record() {
+        let mut option = serde_json::to_value(OptionInfo::test()).unwrap();
+        option.as_object_mut().unwrap().remove("underlying_type");
+        let value = serde_json::json!({
+            "version": 3,
+            "timestamp": "2021-06-10T14:00:00Z",
+            "symbol": "/ES",
+            "options": [option],
+        });
+
+        let record = migrate_record(value, "/ES").unwrap();
+        assert_eq!(
+            record.options[0].underlying_type,
+            Some(UnderlyingType::Future)
+        );
+    }
+
+    #[test]
+    fn version_four_record() {
+        let mut option = serde_json::to_value(OptionInfo::test()).unwrap();
+        let greeks = option["greeks"].as_object_mut().unwrap();
+        for greek in HIGHER_ORDER_GREEKS {
+            greeks.remove(*greek);
+        }
+        let mut without_greeks = OptionInfo::test();
+        without_greeks.greeks = None;
+        let value = serde_json::json!({
+            "version": 4,
+            "timestamp": "2021-06-10T14:00:00Z",
+            "symbol": "TST",
+            "options": [option, without_greeks],
+        });
+
+        let record = migrate_record(value, "TST").unwrap();
+        let greeks = record.options[0].greeks.as_ref().unwrap();
+        assert_eq!(greeks.charm, 16.0);
+        assert_eq!(greeks.speed, 0.0);
+        assert_eq!(greeks.ultima, 0.0);
+        assert!(record.options[1].greeks.is_none());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let value = serde_json::json!({
+            "version": CURRENT_VERSION + 1,
+            "timestamp": "2021-06-10T14:00:00Z",
+            "options": [],
+        });
+
+        assert!(migrate_record(value, "TST").is_err());
+    }
+}
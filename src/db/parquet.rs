@@ -0,0 +1,219 @@
+//! Columnar export of stored snapshots for analysis outside the app. Every
+//! option of every snapshot becomes one row, with the greeks and the
+//! snapshot's underlying quote flattened into their own columns.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use crate::types::{Greeks, OptionInfo, OptionSnapshot, Quote};
+
+pub const CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+pub const EXTENSION: &str = "parquet";
+
+fn schema() -> Schema {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    let float = |name: &str| Field::new(name, DataType::Float64, true);
+
+    Schema::new(vec![
+        Field::new("snapshot_timestamp", timestamp, false),
+        Field::new("provider", DataType::Utf8, true),
+        float("underlying_last"),
+        float("underlying_bid"),
+        float("underlying_ask"),
+        Field::new("timestamp", DataType::Utf8, false),
+        Field::new("symbol", DataType::Utf8, false),
+        Field::new("option_type", DataType::Utf8, false),
+        Field::new("strike", DataType::Float64, false),
+        Field::new("expiration_date", DataType::Utf8, false),
+        Field::new("open_interest", DataType::UInt64, false),
+        Field::new("volume", DataType::UInt64, false),
+        float("delta"),
+        float("gamma"),
+        float("theta"),
+        float("vega"),
+        float("rho"),
+        float("vanna"),
+        float("charm"),
+        float("speed"),
+        float("zomma"),
+        float("color"),
+        float("vomma"),
+        float("veta"),
+        float("ultima"),
+        float("last"),
+        float("change"),
+        float("open"),
+        float("high"),
+        float("low"),
+        float("close"),
+        float("bid_iv"),
+        float("mid_iv"),
+        float("ask_iv"),
+        float("smv_vol"),
+        float("bid"),
+        float("ask"),
+        float("multiplier"),
+        Field::new("settlement_type", DataType::Utf8, true),
+        Field::new("days_to_expiration", DataType::Int64, true),
+        Field::new("underlying_type", DataType::Utf8, true),
+    ])
+}
+
+/// Encodes `snapshots` as a single Parquet file.
+pub fn encode(snapshots: &[OptionSnapshot]) -> anyhow::Result<Vec<u8>> {
+    let schema = Arc::new(schema());
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    let mut bytes = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut bytes, schema.clone(), Some(properties))?;
+    for snapshot in snapshots {
+        writer.write(&record_batch(schema.clone(), snapshot)?)?;
+    }
+    writer.close()?;
+
+    Ok(bytes)
+}
+
+fn record_batch(schema: Arc<Schema>, snapshot: &OptionSnapshot) -> anyhow::Result<RecordBatch> {
+    let options = &snapshot.options;
+    let rows = options.len();
+
+    let quote = |f: fn(&Quote) -> Option<f64>| -> ArrayRef {
+        let value = snapshot.quote.as_ref().and_then(f);
+        Arc::new(Float64Array::from(vec![value; rows]))
+    };
+    let option = |f: fn(&OptionInfo) -> Option<f64>| -> ArrayRef {
+        Arc::new(options.iter().map(f).collect::<Float64Array>())
+    };
+    let greek = |f: fn(&Greeks) -> f64| -> ArrayRef {
+        Arc::new(
+            options
+                .iter()
+                .map(|o| o.greeks.as_ref().map(f))
+                .collect::<Float64Array>(),
+        )
+    };
+    let text = |f: fn(&OptionInfo) -> String| -> ArrayRef {
+        Arc::new(options.iter().map(|o| Some(f(o))).collect::<StringArray>())
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            TimestampMicrosecondArray::from(vec![snapshot.timestamp.timestamp_micros(); rows])
+                .with_timezone("UTC"),
+        ),
+        Arc::new(StringArray::from(vec![snapshot.provider.clone(); rows])),
+        quote(|q| q.last),
+        quote(|q| q.bid),
+        quote(|q| q.ask),
+        text(|o| o.timestamp.clone()),
+        text(|o| o.symbol.clone()),
+        text(|o| format!("{:?}", o.option_type)),
+        option(|o| Some(o.strike)),
+        text(|o| o.expiration_date.clone()),
+        Arc::new(UInt64Array::from_iter_values(
+            options.iter().map(|o| o.open_interest),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            options.iter().map(|o| o.volume),
+        )),
+        greek(|g| g.delta),
+        greek(|g| g.gamma),
+        greek(|g| g.theta),
+        greek(|g| g.vega),
+        greek(|g| g.rho),
+        greek(|g| g.vanna),
+        greek(|g| g.charm),
+        greek(|g| g.speed),
+        greek(|g| g.zomma),
+        greek(|g| g.color),
+        greek(|g| g.vomma),
+        greek(|g| g.veta),
+        greek(|g| g.ultima),
+        option(|o| o.last),
+        option(|o| o.change),
+        option(|o| o.open),
+        option(|o| o.high),
+        option(|o| o.low),
+        option(|o| o.close),
+        option(|o| o.bid_iv),
+        option(|o| o.mid_iv),
+        option(|o| o.ask_iv),
+        option(|o| o.smv_vol),
+        option(|o| o.bid),
+        option(|o| o.ask),
+        option(|o| o.multiplier),
+        Arc::new(
+            options
+                .iter()
+                .map(|o| o.settlement_type.map(|s| format!("{:?}", s)))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            options
+                .iter()
+                .map(|o| o.days_to_expiration)
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            options
+                .iter()
+                .map(|o| o.underlying_type.map(|u| format!("{:?}", u)))
+                .collect::<StringArray>(),
+        ),
+    ];
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn round_trip() {
+        let mut snapshot = OptionSnapshot::test();
+        let mut put = OptionInfo::test();
+        put.greeks = None;
+        snapshot.options.push(put);
+
+        let path = std::path::Path::new("data/test/parquet/round_trip.parquet");
+        let bytes = encode(&[snapshot.clone(), snapshot]).unwrap();
+        crate::db::atomic::write(path, &bytes).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 4);
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), schema().fields().len());
+        let gamma = batch
+            .column_by_name("gamma")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(gamma.value(0), 11.0);
+        assert!(gamma.is_null(1));
+        let last = batch
+            .column_by_name("underlying_last")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(last.value(1), 10.0);
+    }
+}
@@ -0,0 +1,282 @@
+//! Thins out old snapshots so the database stays bounded. Recent snapshots
+//! are all kept, older ones are reduced to one per hour and eventually to the
+//! last one of each trading day.
+
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration as StdDuration};
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use chrono_tz::America::New_York;
+use tokio::sync::Mutex;
+
+use super::{file::Symbol, FileDb};
+use crate::utils;
+
+const RETENTION_POLICY_ENV: &str = "RETENTION_POLICY";
+const RETENTION_DRY_RUN_ENV: &str = "RETENTION_DRY_RUN";
+const COMPACTION_INTERVAL: StdDuration = StdDuration::from_secs(6 * 60 * 60);
+
+/// The age, in days, up to which snapshots are kept at each resolution.
+/// `None` keeps them forever.
+///
+/// Written as `all=5,hourly=90,daily=forever` in `RETENTION_POLICY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub all_days: Option<i64>,
+    pub hourly_days: Option<i64>,
+    pub daily_days: Option<i64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            all_days: Some(5),
+            hourly_days: Some(90),
+            daily_days: None,
+        }
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = Self::default();
+
+        for tier in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (name, days) = tier
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid retention tier: {}", tier))?;
+            let days = match days.trim() {
+                "forever" => None,
+                days => Some(
+                    days.parse::<i64>()
+                        .map_err(|_| anyhow::anyhow!("Invalid retention days in {}", tier))?,
+                ),
+            };
+
+            match name.trim() {
+                "all" => policy.all_days = days,
+                "hourly" => policy.hourly_days = days,
+                "daily" => policy.daily_days = days,
+                _ => anyhow::bail!("Unknown retention tier: {}", name),
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Bucket {
+    All(DateTime<Utc>),
+    Hour(DateTime<Utc>),
+    Day(NaiveDate),
+}
+
+impl RetentionPolicy {
+    /// Reads `RETENTION_POLICY`. Compaction is disabled when it isn't set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var(RETENTION_POLICY_ENV) {
+            Ok(policy) => Ok(Some(policy.parse()?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// The timestamps in `timestamps` that the policy drops as of `now`.
+    pub fn expired(&self, timestamps: &[DateTime<Utc>], now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        // The latest snapshot in each bucket survives
+        let mut latest: BTreeMap<Bucket, DateTime<Utc>> = BTreeMap::new();
+        let mut expired = Vec::new();
+
+        for &timestamp in timestamps {
+            match self.bucket(timestamp, now) {
+                Some(bucket) => {
+                    let entry = latest.entry(bucket).or_insert(timestamp);
+                    if *entry < timestamp {
+                        expired.push(*entry);
+                        *entry = timestamp;
+                    } else if *entry > timestamp {
+                        expired.push(timestamp);
+                    }
+                }
+                None => expired.push(timestamp),
+            }
+        }
+
+        expired.sort();
+        expired
+    }
+
+    fn bucket(&self, timestamp: DateTime<Utc>, now: DateTime<Utc>) -> Option<Bucket> {
+        let age = now - timestamp;
+        if within(age, self.all_days) {
+            Some(Bucket::All(timestamp))
+        } else if within(age, self.hourly_days) {
+            let hour = timestamp.duration_trunc(Duration::hours(1)).ok()?;
+            Some(Bucket::Hour(hour))
+        } else if within(age, self.daily_days) {
+            Some(Bucket::Day(timestamp.with_timezone(&New_York).date_naive()))
+        } else {
+            None
+        }
+    }
+}
+
+fn within(age: Duration, days: Option<i64>) -> bool {
+    days.is_none_or(|days| age < Duration::days(days))
+}
+
+/// What a compaction pass removed, or would remove in a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct CompactionReport {
+    pub dry_run: bool,
+    pub kept: usize,
+    pub removed: BTreeMap<Symbol, Vec<DateTime<Utc>>>,
+}
+
+impl CompactionReport {
+    pub fn removed_count(&self) -> usize {
+        self.removed.values().map(Vec::len).sum()
+    }
+
+    pub fn log(&self) {
+        let verb = if self.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        for (symbol, timestamps) in &self.removed {
+            if let (Some(first), Some(last)) = (timestamps.first(), timestamps.last()) {
+                log::info!(
+                    "{} {} snapshots of {} between {} and {}",
+                    verb,
+                    timestamps.len(),
+                    symbol,
+                    first.to_rfc3339(),
+                    last.to_rfc3339()
+                );
+            }
+        }
+        log::info!(
+            "{} {} snapshots, keeping {}",
+            verb,
+            self.removed_count(),
+            self.kept
+        );
+    }
+}
+
+/// Periodically applies `policy` to `db`. With `RETENTION_DRY_RUN=true`, only
+/// reports what would be removed.
+pub fn start_compaction_loop(
+    db: Arc<Mutex<FileDb>>,
+    policy: RetentionPolicy,
+) -> anyhow::Result<()> {
+    let dry_run = match std::env::var(RETENTION_DRY_RUN_ENV) {
+        Ok(value) => {
+            parse_flag(&value).with_context(|| format!("Invalid {}", RETENTION_DRY_RUN_ENV))?
+        }
+        Err(_) => false,
+    };
+
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(COMPACTION_INTERVAL);
+        // The first tick completes immediately, leave startup to the updates
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+
+            match compact(&db, &policy, dry_run).await {
+                Ok(report) => report.log(),
+                Err(e) => log::error!("Compaction failed: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Like [`FileDb::compact`], but segments are rewritten on the blocking pool
+/// and `db` is only locked to plan and to swap each rewrite in.
+async fn compact(
+    db: &Arc<Mutex<FileDb>>,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> anyhow::Result<CompactionReport> {
+    let (report, segments) = db.lock().await.plan_compaction(policy, Utc::now(), dry_run);
+
+    for (segment, expired) in segments {
+        let rewrite = utils::blocking(move || segment.rewrite_without(&expired)).await?;
+        let mut db = db.clone().lock_owned().await;
+        let replaced = utils::blocking(move || db.replace_segment(rewrite)).await?;
+        if !replaced {
+            log::warn!("Skipped compacting a segment that changed while it was rewritten");
+        }
+    }
+
+    Ok(report)
+}
+
+fn parse_flag(value: &str) -> anyhow::Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" | "" => Ok(false),
+        other => anyhow::bail!("Expected true or false, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::at;
+
+    #[test]
+    fn parse_dry_run_flag() {
+        assert!(parse_flag("true").unwrap());
+        assert!(parse_flag("1").unwrap());
+        assert!(!parse_flag("false").unwrap());
+        assert!(!parse_flag("0").unwrap());
+        assert!(parse_flag("maybe").is_err());
+    }
+
+    #[test]
+    fn parse_policy() {
+        let policy: RetentionPolicy = "all=2, hourly=30, daily=forever".parse().unwrap();
+        assert_eq!(policy.all_days, Some(2));
+        assert_eq!(policy.hourly_days, Some(30));
+        assert_eq!(policy.daily_days, None);
+        assert_eq!("".parse::<RetentionPolicy>().unwrap(), Default::default());
+        assert!("weekly=4".parse::<RetentionPolicy>().is_err());
+        assert!("all=x".parse::<RetentionPolicy>().is_err());
+    }
+
+    #[test]
+    fn expired() {
+        let policy: RetentionPolicy = "all=5,hourly=90,daily=365".parse().unwrap();
+        let now = at("2021-06-30T20:00:00Z");
+        let timestamps = [
+            // Older than every tier
+            at("2019-06-10T14:00:00Z"),
+            // Daily: only the last of the day survives
+            at("2021-01-04T15:00:00Z"),
+            at("2021-01-04T20:00:00Z"),
+            // Hourly
+            at("2021-06-10T14:00:00Z"),
+            at("2021-06-10T14:30:00Z"),
+            at("2021-06-10T15:00:00Z"),
+            // Everything
+            at("2021-06-29T14:00:00Z"),
+            at("2021-06-29T14:01:00Z"),
+        ];
+
+        assert_eq!(
+            policy.expired(&timestamps, now),
+            vec![
+                at("2019-06-10T14:00:00Z"),
+                at("2021-01-04T15:00:00Z"),
+                at("2021-06-10T14:00:00Z"),
+            ]
+        );
+    }
+}
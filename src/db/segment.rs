@@ -0,0 +1,243 @@
+//! Append-only segment files. A segment holds every snapshot of one symbol for
+//! one day as a series of concatenated gzip members, one JSON record per
+//! member, so adding a snapshot never rewrites what is already on disk.
+
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{atomic, migrations};
+use crate::types::OptionSnapshot;
+
+const SEGMENT_EXTENSION: &str = "gz";
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+/// Copies of a damaged segment kept before it is rewritten
+const BACKUP_COUNT: usize = 1;
+
+/// A snapshot as written to disk, tagged with the layout version so it can be
+/// migrated when the layout changes.
+#[derive(Serialize)]
+struct VersionedRecord<'a> {
+    version: u32,
+    #[serde(flatten)]
+    record: &'a OptionSnapshot,
+}
+
+pub fn path(root: &Path, symbol: &str, date: NaiveDate) -> PathBuf {
+    root.join(directory_name(symbol))
+        .join(format!("{}.{}", date, SEGMENT_EXTENSION))
+}
+
+/// Futures symbols like `/ES` can't be used as directory names as-is.
+fn directory_name(symbol: &str) -> String {
+    symbol.replace('%', "%25").replace('/', "%2F")
+}
+
+fn symbol_from_directory_name(name: &str) -> String {
+    name.replace("%2F", "/").replace("%25", "%")
+}
+
+/// Every segment under `root` as `(symbol, date)` pairs.
+pub fn list(root: &Path) -> anyhow::Result<Vec<(String, NaiveDate)>> {
+    let mut result = Vec::new();
+    if !root.exists() {
+        return Ok(result);
+    }
+
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let symbol = symbol_from_directory_name(&entry.file_name().to_string_lossy());
+
+        for file in std::fs::read_dir(entry.path())? {
+            let file_path = file?.path();
+            if file_path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let date = file_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok());
+            if let Some(date) = date {
+                result.push((symbol.clone(), date));
+            }
+        }
+    }
+
+    result.sort();
+    Ok(result)
+}
+
+pub fn append(path: &Path, record: &OptionSnapshot) -> anyhow::Result<()> {
+    let compressed_bytes = encode(record)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&compressed_bytes)?;
+    file.sync_data()?;
+
+    Ok(())
+}
+
+/// Replaces the contents of a segment.
+pub fn write(path: &Path, records: &[OptionSnapshot]) -> anyhow::Result<()> {
+    atomic::write(path, &encode_all(records)?)
+}
+
+/// Writes a replacement for a segment next to it, see [`atomic::write_pending`].
+pub fn write_pending(path: &Path, records: &[OptionSnapshot]) -> anyhow::Result<PathBuf> {
+    atomic::write_pending(path, &encode_all(records)?)
+}
+
+fn encode_all(records: &[OptionSnapshot]) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for record in records {
+        bytes.extend(encode(record)?);
+    }
+    Ok(bytes)
+}
+
+/// Reads every intact record of a segment. Damaged members, such as a partial
+/// write left behind by a crash, are dropped and the segment is rewritten
+/// without them so later appends stay readable.
+pub fn read(path: &Path, symbol: &str) -> anyhow::Result<Vec<OptionSnapshot>> {
+    let (records, damaged) = read_records(path, symbol)?;
+
+    if damaged {
+        log::warn!(
+            "Recovered {} snapshots from damaged segment {}",
+            records.len(),
+            path.display()
+        );
+        atomic::rotate_backups(path, BACKUP_COUNT)?;
+        write(path, &records)?;
+    }
+
+    Ok(records)
+}
+
+/// Reads every intact record of a segment, leaving any damage in place.
+pub fn read_without_repair(path: &Path, symbol: &str) -> anyhow::Result<Vec<OptionSnapshot>> {
+    Ok(read_records(path, symbol)?.0)
+}
+
+fn read_records(path: &Path, symbol: &str) -> anyhow::Result<(Vec<OptionSnapshot>, bool)> {
+    let bytes = std::fs::read(path)?;
+    let (values, damaged) = decode(&bytes);
+
+    // Records that decode but can't be migrated are an error rather than
+    // damage, so they're never dropped from the file.
+    let records = values
+        .into_iter()
+        .map(|value| migrations::migrate_record(value, symbol))
+        .collect::<anyhow::Result<Vec<OptionSnapshot>>>()?;
+
+    Ok((records, damaged))
+}
+
+fn encode(record: &OptionSnapshot) -> anyhow::Result<Vec<u8>> {
+    let json = serde_json::to_vec(&VersionedRecord {
+        version: migrations::CURRENT_VERSION,
+        record,
+    })?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode(bytes: &[u8]) -> (Vec<Value>, bool) {
+    let mut records = Vec::new();
+    let mut damaged = false;
+    let mut remaining = bytes;
+
+    while !remaining.is_empty() {
+        match decode_member(remaining) {
+            Ok((record, rest)) => {
+                records.push(record);
+                remaining = rest;
+            }
+            Err(_) => {
+                damaged = true;
+                // Skip ahead to the next member, if there is one
+                remaining = match remaining[1..].windows(3).position(|w| w == GZIP_MAGIC) {
+                    Some(i) => &remaining[i + 1..],
+                    None => &[],
+                };
+            }
+        }
+    }
+
+    (records, damaged)
+}
+
+fn decode_member(bytes: &[u8]) -> anyhow::Result<(Value, &[u8])> {
+    let mut decoder = GzDecoder::new(bytes);
+    let mut json = Vec::new();
+    decoder.read_to_end(&mut json)?;
+
+    let record = serde_json::from_slice(&json)?;
+    Ok((record, decoder.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recover_damaged_segment() {
+        let dir = Path::new("data/test/segment");
+        let _ = std::fs::remove_dir_all(dir);
+        let path = path(dir, "/ES", NaiveDate::from_ymd_opt(2021, 6, 10).unwrap());
+
+        for _ in 0..3 {
+            append(&path, &OptionSnapshot::test()).unwrap();
+        }
+
+        // Corrupt the second member and leave a partial write at the end
+        let mut bytes = std::fs::read(&path).unwrap();
+        let second = bytes[1..].windows(3).position(|w| w == GZIP_MAGIC).unwrap() + 1;
+        bytes[second + 20] ^= 0xff;
+        let partial = bytes[..10].to_vec();
+        bytes.extend(partial);
+        std::fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(read(&path, "/ES").unwrap().len(), 2);
+        let (records, damaged) = decode(&std::fs::read(&path).unwrap());
+        assert_eq!(records.len(), 2);
+        assert!(!damaged);
+        assert_eq!(atomic::backups(&path, BACKUP_COUNT).len(), 1);
+        assert_eq!(list(dir).unwrap()[0].0, "/ES");
+    }
+
+    #[test]
+    fn newer_version_is_not_dropped() {
+        let dir = Path::new("data/test/segment_version");
+        let _ = std::fs::remove_dir_all(dir);
+        let path = path(dir, "TST", NaiveDate::from_ymd_opt(2021, 6, 10).unwrap());
+
+        let json = serde_json::json!({
+            "version": migrations::CURRENT_VERSION + 1,
+            "timestamp": "2021-06-10T14:00:00Z",
+            "options": [],
+        });
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.to_string().as_bytes()).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        assert!(read(&path, "TST").is_err());
+        assert_eq!(decode(&std::fs::read(&path).unwrap()).0.len(), 1);
+    }
+}
@@ -0,0 +1,592 @@
+use std::{collections::HashSet, path::Path, str::FromStr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::file::{FileDb, SegmentRef};
+use crate::types::{
+    Greeks, OptionInfo, OptionSnapshot, OptionType, Quote, SettlementType, UnderlyingType,
+};
+
+const SQLITE_PATH_ENV: &str = "SQLITE_DB_PATH";
+
+/// `MIGRATIONS[i]` upgrades a database from `PRAGMA user_version` `i` to
+/// `i + 1`. The first one is written so it also applies cleanly to databases
+/// created before the schema was versioned.
+const MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY,
+    symbol TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    UNIQUE (symbol, timestamp)
+);
+
+CREATE TABLE IF NOT EXISTS quotes (
+    snapshot_id INTEGER PRIMARY KEY REFERENCES snapshots (id) ON DELETE CASCADE,
+    symbol TEXT NOT NULL,
+    last REAL,
+    change REAL,
+    volume INTEGER NOT NULL,
+    open REAL,
+    high REAL,
+    low REAL,
+    close REAL
+);
+
+CREATE TABLE IF NOT EXISTS options (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    timestamp TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    option_type TEXT NOT NULL,
+    strike REAL NOT NULL,
+    expiration_date TEXT NOT NULL,
+    open_interest INTEGER NOT NULL,
+    volume INTEGER NOT NULL,
+    delta REAL,
+    gamma REAL,
+    theta REAL,
+    vega REAL,
+    rho REAL,
+    vanna REAL,
+    charm REAL,
+    last REAL,
+    change REAL,
+    open REAL,
+    high REAL,
+    low REAL,
+    close REAL,
+    bid_iv REAL,
+    mid_iv REAL,
+    ask_iv REAL,
+    smv_vol REAL,
+    multiplier REAL,
+    settlement_type TEXT,
+    days_to_expiration INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS options_snapshot ON options (snapshot_id);
+CREATE INDEX IF NOT EXISTS options_symbol_timestamp ON options (symbol, timestamp);
+CREATE INDEX IF NOT EXISTS options_expiration_strike ON options (symbol, expiration_date, strike);
+",
+    "
+ALTER TABLE options ADD COLUMN bid REAL;
+ALTER TABLE options ADD COLUMN ask REAL;
+",
+    "
+ALTER TABLE snapshots ADD COLUMN provider TEXT;
+ALTER TABLE quotes ADD COLUMN bid REAL;
+ALTER TABLE quotes ADD COLUMN ask REAL;
+",
+    "
+ALTER TABLE options ADD COLUMN underlying_type TEXT;
+",
+    "
+ALTER TABLE options ADD COLUMN speed REAL;
+ALTER TABLE options ADD COLUMN zomma REAL;
+ALTER TABLE options ADD COLUMN color REAL;
+ALTER TABLE options ADD COLUMN vomma REAL;
+ALTER TABLE options ADD COLUMN veta REAL;
+ALTER TABLE options ADD COLUMN ultima REAL;
+",
+];
+
+/// Snapshot storage in a bundled SQLite database so the collected chains can
+/// be queried with plain SQL.
+pub struct SqliteDb {
+    connection: Connection,
+}
+
+impl SqliteDb {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens the database at `SQLITE_DB_PATH` if the variable is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var(SQLITE_PATH_ENV) {
+            Ok(path) => Ok(Some(Self::open(path)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> anyhow::Result<Self> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+        let mut db = Self { connection };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn migrate(&mut self) -> anyhow::Result<()> {
+        let version: i64 = self
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        let version = version as usize;
+        if version > MIGRATIONS.len() {
+            anyhow::bail!(
+                "Unsupported SQLite schema version {} (current is {})",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = self.connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+            transaction.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores a snapshot. Returns false if a snapshot of the symbol already
+    /// exists for that time.
+    pub fn add_snapshot(&mut self, snapshot: &OptionSnapshot) -> anyhow::Result<bool> {
+        let symbol = snapshot.symbol.to_uppercase();
+        let timestamp = format_timestamp(snapshot.timestamp);
+
+        let transaction = self.connection.transaction()?;
+
+        let inserted = transaction.execute(
+            "INSERT OR IGNORE INTO snapshots (symbol, timestamp, provider) VALUES (?1, ?2, ?3)",
+            params![symbol, timestamp, snapshot.provider],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        let snapshot_id = transaction.last_insert_rowid();
+
+        if let Some(quote) = &snapshot.quote {
+            transaction.execute(
+                "INSERT INTO quotes (
+                    snapshot_id, symbol, last, change, volume, open, high, low, close, bid, ask
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    snapshot_id,
+                    quote.symbol,
+                    quote.last,
+                    quote.change,
+                    quote.volume as i64,
+                    quote.open,
+                    quote.high,
+                    quote.low,
+                    quote.close,
+                    quote.bid,
+                    quote.ask,
+                ],
+            )?;
+        }
+
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO options (
+                    snapshot_id, timestamp, symbol, option_type, strike, expiration_date,
+                    open_interest, volume, delta, gamma, theta, vega, rho, vanna, charm,
+                    last, change, open, high, low, close, bid_iv, mid_iv, ask_iv, smv_vol,
+                    multiplier, settlement_type, days_to_expiration, bid, ask, underlying_type,
+                    speed, zomma, color, vomma, veta, ultima
+                ) VALUES (
+                    ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                    ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
+                    ?31, ?32, ?33, ?34, ?35, ?36, ?37
+                )",
+            )?;
+
+            for option in &snapshot.options {
+                let greeks = option.greeks.as_ref();
+                statement.execute(params![
+                    snapshot_id,
+                    option.timestamp,
+                    option.symbol,
+                    format!("{:?}", option.option_type),
+                    option.strike,
+                    option.expiration_date,
+                    option.open_interest as i64,
+                    option.volume as i64,
+                    greeks.map(|g| g.delta),
+                    greeks.map(|g| g.gamma),
+                    greeks.map(|g| g.theta),
+                    greeks.map(|g| g.vega),
+                    greeks.map(|g| g.rho),
+                    greeks.map(|g| g.vanna),
+                    greeks.map(|g| g.charm),
+                    option.last,
+                    option.change,
+                    option.open,
+                    option.high,
+                    option.low,
+                    option.close,
+                    option.bid_iv,
+                    option.mid_iv,
+                    option.ask_iv,
+                    option.smv_vol,
+                    option.multiplier,
+                    option.settlement_type.map(|s| format!("{:?}", s)),
+                    option.days_to_expiration,
+                    option.bid,
+                    option.ask,
+                    option.underlying_type.map(|u| format!("{:?}", u)),
+                    greeks.map(|g| g.speed),
+                    greeks.map(|g| g.zomma),
+                    greeks.map(|g| g.color),
+                    greeks.map(|g| g.vomma),
+                    greeks.map(|g| g.veta),
+                    greeks.map(|g| g.ultima),
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+
+        Ok(true)
+    }
+
+    pub fn symbols(&self) -> anyhow::Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT DISTINCT symbol FROM snapshots ORDER BY symbol")?;
+        let symbols = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(symbols)
+    }
+
+    pub fn snapshot_timestamps(&self, symbol: &str) -> anyhow::Result<Vec<DateTime<Utc>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT timestamp FROM snapshots WHERE symbol = ?1 ORDER BY timestamp")?;
+        let timestamps: Vec<String> = statement
+            .query_map(params![symbol.to_uppercase()], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        timestamps.iter().map(|t| parse_timestamp(t)).collect()
+    }
+
+    pub fn latest_timestamp(&self, symbol: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let timestamp: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT MAX(timestamp) FROM snapshots WHERE symbol = ?1",
+                params![symbol.to_uppercase()],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+
+        timestamp.map(|t| parse_timestamp(&t)).transpose()
+    }
+
+    pub fn option_chain(&self, symbol: &str) -> anyhow::Result<Option<Vec<OptionInfo>>> {
+        self.option_chain_at(symbol, Utc::now())
+    }
+
+    /// The most recent option chain taken at or before `time`.
+    pub fn option_chain_at(
+        &self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Vec<OptionInfo>>> {
+        Ok(self.snapshot_at(symbol, time)?.map(|s| s.options))
+    }
+
+    /// The most recent snapshot taken at or before `time`.
+    pub fn snapshot_at(
+        &self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<Option<OptionSnapshot>> {
+        let snapshot = self
+            .connection
+            .query_row(
+                "SELECT id, symbol, timestamp, provider FROM snapshots
+                 WHERE symbol = ?1 AND timestamp <= ?2
+                 ORDER BY timestamp DESC LIMIT 1",
+                params![symbol.to_uppercase(), format_timestamp(time)],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let (snapshot_id, symbol, timestamp, provider) = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+
+        let quote = self
+            .connection
+            .query_row(
+                "SELECT symbol, last, change, volume, open, high, low, close, bid, ask
+                 FROM quotes WHERE snapshot_id = ?1",
+                params![snapshot_id],
+                quote_from_row,
+            )
+            .optional()?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT timestamp, symbol, option_type, strike, expiration_date, open_interest,
+                volume, delta, gamma, theta, vega, rho, vanna, charm, last, change, open,
+                high, low, close, bid_iv, mid_iv, ask_iv, smv_vol, multiplier,
+                settlement_type, days_to_expiration, bid, ask, underlying_type, speed, zomma,
+                color, vomma, veta, ultima
+             FROM options WHERE snapshot_id = ?1",
+        )?;
+        let options = statement
+            .query_map(params![snapshot_id], option_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(OptionSnapshot {
+            timestamp: parse_timestamp(&timestamp)?,
+            symbol,
+            provider,
+            quote,
+            options,
+        }))
+    }
+
+    /// Copies every snapshot in `db` that isn't stored yet. This also covers
+    /// a legacy `db.gz`, which `FileDb::load` imports into `db`.
+    pub fn sync_from(&mut self, db: &FileDb) -> anyhow::Result<usize> {
+        let mut count = 0;
+        for segment in db.segment_refs() {
+            count += self.sync_segment(&segment)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Copies the snapshots of one segment that aren't stored yet, however
+    /// old. The segment is only read if some are missing.
+    pub fn sync_segment(&mut self, segment: &SegmentRef) -> anyhow::Result<usize> {
+        let timestamps = segment.timestamps();
+        let (first, last) = match (timestamps.iter().min(), timestamps.iter().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Ok(0),
+        };
+        let stored = self.timestamps_between(segment.symbol(), first, last)?;
+        if timestamps.iter().all(|t| stored.contains(t)) {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        for snapshot in segment.read()? {
+            if !stored.contains(&snapshot.timestamp) && self.add_snapshot(&snapshot)? {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The times of the stored snapshots of `symbol` from `first` to `last`.
+    fn timestamps_between(
+        &self,
+        symbol: &str,
+        first: DateTime<Utc>,
+        last: DateTime<Utc>,
+    ) -> anyhow::Result<HashSet<DateTime<Utc>>> {
+        let mut statement = self.connection.prepare(
+            "SELECT timestamp FROM snapshots WHERE symbol = ?1 AND timestamp BETWEEN ?2 AND ?3",
+        )?;
+        let timestamps: Vec<String> = statement
+            .query_map(
+                params![
+                    symbol.to_uppercase(),
+                    format_timestamp(first),
+                    format_timestamp(last)
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<Result<_, _>>()?;
+
+        timestamps.iter().map(|t| parse_timestamp(t)).collect()
+    }
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn parse_timestamp(timestamp: &str) -> anyhow::Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(timestamp)?.with_timezone(&Utc))
+}
+
+fn quote_from_row(row: &Row) -> rusqlite::Result<Quote> {
+    Ok(Quote {
+        symbol: row.get("symbol")?,
+        last: row.get("last")?,
+        change: row.get("change")?,
+        volume: row.get::<_, i64>("volume")? as u64,
+        open: row.get("open")?,
+        high: row.get("high")?,
+        low: row.get("low")?,
+        close: row.get("close")?,
+        bid: row.get("bid")?,
+        ask: row.get("ask")?,
+    })
+}
+
+fn option_from_row(row: &Row) -> rusqlite::Result<OptionInfo> {
+    let option_type: String = row.get("option_type")?;
+    let option_type = OptionType::from_str(&option_type).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(
+            row.as_ref().column_index("option_type").unwrap_or_default(),
+            rusqlite::types::Type::Text,
+            e.into(),
+        )
+    })?;
+    let settlement_type: Option<String> = row.get("settlement_type")?;
+    let underlying_type: Option<String> = row.get("underlying_type")?;
+    let delta: Option<f64> = row.get("delta")?;
+
+    let greeks = match delta {
+        Some(delta) => Some(Greeks {
+            delta,
+            gamma: row.get::<_, Option<f64>>("gamma")?.unwrap_or(0.0),
+            theta: row.get::<_, Option<f64>>("theta")?.unwrap_or(0.0),
+            vega: row.get::<_, Option<f64>>("vega")?.unwrap_or(0.0),
+            rho: row.get::<_, Option<f64>>("rho")?.unwrap_or(0.0),
+            vanna: row.get::<_, Option<f64>>("vanna")?.unwrap_or(0.0),
+            charm: row.get::<_, Option<f64>>("charm")?.unwrap_or(0.0),
+            speed: row.get::<_, Option<f64>>("speed")?.unwrap_or(0.0),
+            zomma: row.get::<_, Option<f64>>("zomma")?.unwrap_or(0.0),
+            color: row.get::<_, Option<f64>>("color")?.unwrap_or(0.0),
+            vomma: row.get::<_, Option<f64>>("vomma")?.unwrap_or(0.0),
+            veta: row.get::<_, Option<f64>>("veta")?.unwrap_or(0.0),
+            ultima: row.get::<_, Option<f64>>("ultima")?.unwrap_or(0.0),
+        }),
+        None => None,
+    };
+
+    Ok(OptionInfo {
+        timestamp: row.get("timestamp")?,
+        symbol: row.get("symbol")?,
+        option_type,
+        strike: row.get("strike")?,
+        expiration_date: row.get("expiration_date")?,
+        open_interest: row.get::<_, i64>("open_interest")? as u64,
+        volume: row.get::<_, i64>("volume")? as u64,
+        greeks,
+        last: row.get("last")?,
+        change: row.get("change")?,
+        open: row.get("open")?,
+        high: row.get("high")?,
+        low: row.get("low")?,
+        close: row.get("close")?,
+        bid_iv: row.get("bid_iv")?,
+        mid_iv: row.get("mid_iv")?,
+        ask_iv: row.get("ask_iv")?,
+        smv_vol: row.get("smv_vol")?,
+        bid: row.get("bid")?,
+        ask: row.get("ask")?,
+        multiplier: row.get("multiplier")?,
+        settlement_type: settlement_type.and_then(|s| SettlementType::from_str(&s).ok()),
+        days_to_expiration: row.get("days_to_expiration")?,
+        underlying_type: underlying_type.and_then(|u| UnderlyingType::from_str(&u).ok()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::at;
+
+    #[test]
+    fn sqlite() {
+        let mut db = SqliteDb::open_in_memory().unwrap();
+
+        let mut put = OptionInfo::test();
+        put.option_type = OptionType::Put;
+        put.greeks = None;
+        let time = at("2021-06-10T14:00:00Z");
+        let mut snapshot = OptionSnapshot::test();
+        snapshot.timestamp = time;
+        snapshot.options.push(put);
+        assert!(db.add_snapshot(&snapshot).unwrap());
+        assert!(!db.add_snapshot(&snapshot).unwrap());
+
+        assert_eq!(db.symbols().unwrap(), vec!["TST"]);
+        assert_eq!(db.snapshot_timestamps("TST").unwrap(), vec![time]);
+        assert!(db
+            .option_chain_at("TST", at("2021-06-10T13:00:00Z"))
+            .unwrap()
+            .is_none());
+
+        let stored = db.snapshot_at("TST", time).unwrap().unwrap();
+        assert_eq!(stored.provider.as_deref(), Some("test"));
+        assert_eq!(stored.quote.unwrap().bid, Some(9.95));
+
+        let chain = db.option_chain("TST").unwrap().unwrap();
+        assert_eq!(chain.len(), 2);
+        let call = chain
+            .iter()
+            .find(|o| o.option_type == OptionType::Call)
+            .unwrap();
+        assert_eq!(call.gamma(), 11.0);
+        assert_eq!(call.settlement_type, Some(SettlementType::PM));
+        assert_eq!(call.bid, Some(2.9));
+        let put = chain
+            .iter()
+            .find(|o| o.option_type == OptionType::Put)
+            .unwrap();
+        assert!(put.greeks.is_none());
+    }
+
+    #[test]
+    fn sync_and_import() {
+        let root = Path::new("data/test/sqlite_sync");
+        let _ = std::fs::remove_dir_all(root);
+        let mut file_db = FileDb::new(root);
+        for time in ["2021-06-10T14:00:00+00:00", "2021-06-10T15:00:00+00:00"] {
+            let mut option = OptionInfo::test();
+            option.timestamp = time.to_string();
+            file_db.add_option_info("TST", vec![option]).unwrap();
+        }
+
+        let mut db = SqliteDb::open_in_memory().unwrap();
+        assert_eq!(db.sync_from(&file_db).unwrap(), 2);
+        assert_eq!(db.sync_from(&file_db).unwrap(), 0);
+
+        // History older than what was synced, like an imported CSV
+        for time in ["2021-06-09T14:00:00+00:00", "2021-06-10T13:45:00+00:00"] {
+            let mut option = OptionInfo::test();
+            option.timestamp = time.to_string();
+            file_db.add_option_info("TST", vec![option]).unwrap();
+        }
+        assert_eq!(db.sync_from(&file_db).unwrap(), 2);
+        assert_eq!(db.snapshot_timestamps("TST").unwrap().len(), 4);
+
+        db.connection
+            .execute("UPDATE options SET option_type = 'straddle'", [])
+            .unwrap();
+        assert!(db.option_chain("TST").is_err());
+
+        // A legacy file reaches SQLite through the segments it's imported into
+        let legacy_path = root.join("db.gz");
+        file_db.export_legacy(&legacy_path).unwrap();
+        let legacy_root = Path::new("data/test/sqlite_sync_legacy");
+        let _ = std::fs::remove_dir_all(legacy_root);
+        let mut legacy_db = FileDb::new(legacy_root);
+        legacy_db.import_legacy(&legacy_path).unwrap();
+        let mut imported = SqliteDb::open_in_memory().unwrap();
+        assert_eq!(imported.sync_from(&legacy_db).unwrap(), 4);
+        assert_eq!(
+            imported.latest_timestamp("TST").unwrap(),
+            Some(at("2021-06-10T15:00:00Z"))
+        );
+    }
+}
@@ -2,21 +2,35 @@ use std::sync::Arc;
 
 use crate::{
     analysis::{
+        distribution::implied_distributions,
         gamma_exposure::{gamma_exposure, gamma_exposure_aggregate},
+        implied_volatility::implied_volatilities,
         option_stats::option_stats,
+        vanna_exposure::{vanna_exposure_aggregate, DEFAULT_IV_SHOCKS},
     },
-    data_apis::tradier,
+    data_apis::{DataError, Provider},
     db::{self, FileDb},
-    types::{stats::StrikeStats, GammaExposureStats, Ohlc, OhlcInterval, Quote},
+    math::pricing::PricingConfig,
+    types::{
+        stats::StrikeStats, GammaExposureStats, ImpliedDistribution, ImpliedVolatility, Ohlc,
+        OhlcInterval, OptionInfo, OptionSnapshot, Quote, VannaExposureStats, VolSurface,
+    },
+    utils::blocking,
 };
-use async_graphql::{Context, EmptyMutation, EmptySubscription, Object};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object};
+use chrono::{DateTime, Utc};
 use tokio::sync::Mutex;
 
+const INTERNAL_ERROR_CODE: &str = "INTERNAL_ERROR";
+
 pub type Schema = async_graphql::Schema<Root, EmptyMutation, EmptySubscription>;
 
-pub fn schema(db: Arc<Mutex<FileDb>>) -> Schema {
+/// `pricing` holds the inputs used when greeks are computed on the fly.
+pub fn schema(db: Arc<Mutex<FileDb>>, provider: Provider, pricing: PricingConfig) -> Schema {
     async_graphql::Schema::build(Root, EmptyMutation, EmptySubscription)
         .data(db)
+        .data(provider)
+        .data(pricing)
         .finish()
 }
 
@@ -24,26 +38,31 @@ pub struct Root;
 
 #[Object]
 impl Root {
-    async fn quote(&self, symbol: String) -> anyhow::Result<Quote> {
+    async fn quote(&self, context: &Context<'_>, symbol: String) -> async_graphql::Result<Quote> {
         log::info!("Querying quote");
-        let quote = tradier::get_quote(&symbol).await.map_err(log_error)?;
-        Ok(quote.into())
+        let provider = context
+            .data::<Provider>()
+            .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+        provider.quote(&symbol).await.map_err(log_error)
     }
 
     async fn ohlc(
         &self,
+        context: &Context<'_>,
         symbol: String,
         #[graphql(default_with = "default_interval()")] interval: OhlcInterval,
-    ) -> anyhow::Result<Vec<Ohlc>> {
+    ) -> async_graphql::Result<Vec<Ohlc>> {
         log::info!("Querying ohlc");
-        let ohlc = tradier::get_time_and_sales(&symbol, interval)
+        let provider = context
+            .data::<Provider>()
+            .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+        provider
+            .time_and_sales(&symbol, interval)
             .await
-            .map_err(log_error)?;
-        let result = ohlc.into_iter().map(|ts| (interval, ts).into()).collect();
-        Ok(result)
+            .map_err(log_error)
     }
 
-    async fn symbols(&self, context: &Context<'_>) -> anyhow::Result<Vec<String>> {
+    async fn symbols(&self, context: &Context<'_>) -> async_graphql::Result<Vec<String>> {
         log::info!("Querying symbols");
         let db = context
             .data::<Arc<Mutex<FileDb>>>()
@@ -52,16 +71,58 @@ impl Root {
         Ok(db.symbols())
     }
 
-    async fn option_stats(
+    async fn snapshot_timestamps(
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<Vec<StrikeStats>> {
-        log::info!("Querying option stats");
+    ) -> async_graphql::Result<Vec<String>> {
+        log::info!("Querying snapshot timestamps");
         let db = context
             .data::<Arc<Mutex<FileDb>>>()
             .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+        let db = db.lock().await;
+        Ok(db
+            .snapshot_timestamps(&symbol)
+            .iter()
+            .map(|t| t.to_rfc3339())
+            .collect())
+    }
+
+    /// The latest option chain, or the one stored at or before `at` (RFC 3339).
+    async fn option_chain(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<Vec<OptionInfo>> {
+        log::info!("Querying option chain");
+        stored_option_chain(context, &symbol, at)
+            .await
+            .map_err(log_error)
+    }
+
+    /// The latest snapshot, or the one stored at or before `at` (RFC 3339),
+    /// with the time it was captured, the underlying quote and the provider.
+    async fn snapshot(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<OptionSnapshot> {
+        log::info!("Querying snapshot");
+        stored_snapshot(context, &symbol, at)
+            .await
+            .map_err(log_error)
+    }
+
+    async fn option_stats(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<Vec<StrikeStats>> {
+        log::info!("Querying option stats");
+        let option_chain = stored_option_chain(context, &symbol, at)
             .await
             .map_err(log_error)?;
         let stats = option_stats(&option_chain);
@@ -72,53 +133,199 @@ impl Root {
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<GammaExposureStats> {
+        at: Option<String>,
+    ) -> async_graphql::Result<GammaExposureStats> {
         log::info!("Querying gamma exposure");
-        let db = context
-            .data::<Arc<Mutex<FileDb>>>()
-            .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+        let option_chain = stored_option_chain(context, &symbol, at)
+            .await
+            .map_err(log_error)?;
+        gamma_exposure(&symbol, &option_chain).map_err(log_error)
+    }
+
+    /// Bid, mid and ask implied volatilities solved from the quotes of the
+    /// stored chain, to check or stand in for the ones the provider reports.
+    async fn implied_volatility(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<Vec<ImpliedVolatility>> {
+        log::info!("Querying implied volatility");
+        let snapshot = stored_snapshot(context, &symbol, at)
             .await
             .map_err(log_error)?;
-        let gex = gamma_exposure(&symbol, &option_chain).unwrap();
-        Ok(gex)
+        implied_volatilities(&snapshot, &pricing(context)).map_err(log_error)
     }
 
+    /// Implied volatilities of the stored chain with a smile fitted to every
+    /// expiration, and any arbitrage between them.
+    async fn vol_surface(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<VolSurface> {
+        log::info!("Querying volatility surface");
+        let snapshot = stored_snapshot(context, &symbol, at)
+            .await
+            .map_err(log_error)?;
+        let pricing = pricing(context);
+        blocking(move || VolSurface::new(&snapshot, &pricing))
+            .await
+            .map_err(log_error)
+    }
+
+    /// The risk-neutral distribution of the underlying at every expiration
+    /// of the stored chain with a fitted smile.
+    async fn implied_distribution(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+    ) -> async_graphql::Result<Vec<ImpliedDistribution>> {
+        log::info!("Querying implied distribution");
+        let snapshot = stored_snapshot(context, &symbol, at)
+            .await
+            .map_err(log_error)?;
+        let pricing = pricing(context);
+        blocking(move || implied_distributions(&snapshot, &pricing))
+            .await
+            .map_err(log_error)
+    }
+
+    /// With `surface` set, every option is priced at the volatility of the
+    /// fitted surface rather than its own.
     async fn gamma_exposure_aggregate(
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<GammaExposureStats> {
+        at: Option<String>,
+        #[graphql(default)] surface: bool,
+    ) -> async_graphql::Result<GammaExposureStats> {
         log::info!("Querying gamma exposure aggregate");
-        let db = context
-            .data::<Arc<Mutex<FileDb>>>()
-            .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+        let snapshot = stored_snapshot(context, &symbol, at)
+            .await
+            .map_err(log_error)?;
+        let pricing = pricing(context);
+        blocking(move || {
+            let surface = if surface {
+                Some(VolSurface::new(&snapshot, &pricing)?)
+            } else {
+                None
+            };
+            gamma_exposure_aggregate(
+                &symbol,
+                &snapshot.options,
+                snapshot.timestamp,
+                &pricing,
+                surface.as_ref(),
+            )
+        })
+        .await
+        .map_err(log_error)
+    }
+
+    /// How dealer delta changes as volatility rises, across prices and with
+    /// every volatility shifted by each of `iv_shocks`. `surface` is the same
+    /// as for `gammaExposureAggregate`.
+    async fn vanna_exposure_aggregate(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        at: Option<String>,
+        #[graphql(default)] surface: bool,
+        iv_shocks: Option<Vec<f64>>,
+    ) -> async_graphql::Result<Vec<VannaExposureStats>> {
+        log::info!("Querying vanna exposure aggregate");
+        let snapshot = stored_snapshot(context, &symbol, at)
             .await
             .map_err(log_error)?;
-        let gex_agg = gamma_exposure_aggregate(&symbol, &option_chain).unwrap();
-        Ok(gex_agg)
+        let pricing = pricing(context);
+        let iv_shocks = iv_shocks.unwrap_or_else(|| DEFAULT_IV_SHOCKS.to_vec());
+        blocking(move || {
+            let surface = if surface {
+                Some(VolSurface::new(&snapshot, &pricing)?)
+            } else {
+                None
+            };
+            vanna_exposure_aggregate(
+                &symbol,
+                &snapshot.options,
+                snapshot.timestamp,
+                &pricing,
+                surface.as_ref(),
+                &iv_shocks,
+            )
+        })
+        .await
+        .map_err(log_error)
+    }
+}
+
+async fn stored_option_chain(
+    context: &Context<'_>,
+    symbol: &str,
+    at: Option<String>,
+) -> anyhow::Result<Vec<OptionInfo>> {
+    Ok(stored_snapshot(context, symbol, at).await?.options)
+}
+
+async fn stored_snapshot(
+    context: &Context<'_>,
+    symbol: &str,
+    at: Option<String>,
+) -> anyhow::Result<OptionSnapshot> {
+    let db = context
+        .data::<Arc<Mutex<FileDb>>>()
+        .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
+
+    match at {
+        Some(at) => {
+            let time = DateTime::parse_from_rfc3339(&at)
+                .map_err(|e| DataError::InvalidInput(format!("Invalid time {}: {}", at, e)))?
+                .with_timezone(&Utc);
+            db::historical_snapshot(symbol, time, db.clone()).await
+        }
+        None => {
+            let provider = context
+                .data::<Provider>()
+                .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+            db::snapshot(symbol, db.clone(), provider.as_ref()).await
+        }
     }
 }
 
+fn pricing(context: &Context<'_>) -> PricingConfig {
+    context
+        .data_opt::<PricingConfig>()
+        .cloned()
+        .unwrap_or_default()
+}
+
 fn default_interval() -> OhlcInterval {
     OhlcInterval::FiveMinute
 }
 
-fn log_error(error: anyhow::Error) -> anyhow::Error {
+/// Logs `error` and tags it with a `code` extension, so clients can tell an
+/// unknown symbol from an upstream outage.
+fn log_error(error: anyhow::Error) -> async_graphql::Error {
     log::error!("{}", error);
-    error
+    let code = DataError::find(&error).map_or(INTERNAL_ERROR_CODE, DataError::code);
+    async_graphql::Error::new(error.to_string()).extend_with(|_, e| e.set("code", code))
 }
 
 /*
- * WIP TDA implementation
+ * TDA implementation. Option data is always fetched from TD rather than the
+ * shared db so it can be compared against the configured provider.
  */
 
 pub type TdaSchema = async_graphql::Schema<TdaRoot, EmptyMutation, EmptySubscription>;
 
-pub fn tda_schema(db: Arc<Mutex<FileDb>>) -> TdaSchema {
+pub fn tda_schema(db: Arc<Mutex<FileDb>>, provider: Provider, pricing: PricingConfig) -> TdaSchema {
     async_graphql::Schema::build(TdaRoot, EmptyMutation, EmptySubscription)
         .data(db)
+        .data(provider)
+        .data(pricing)
         .finish()
 }
 
@@ -126,26 +333,31 @@ pub struct TdaRoot;
 
 #[Object]
 impl TdaRoot {
-    async fn quote(&self, symbol: String) -> anyhow::Result<Quote> {
-        log::info!("Querying quote");
-        let quote = tradier::get_quote(&symbol).await.map_err(log_error)?;
-        Ok(quote.into())
+    async fn quote(&self, context: &Context<'_>, symbol: String) -> async_graphql::Result<Quote> {
+        log::info!("Querying TDA quote");
+        let provider = context
+            .data::<Provider>()
+            .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+        provider.quote(&symbol).await.map_err(log_error)
     }
 
     async fn ohlc(
         &self,
+        context: &Context<'_>,
         symbol: String,
         #[graphql(default_with = "default_interval()")] interval: OhlcInterval,
-    ) -> anyhow::Result<Vec<Ohlc>> {
-        log::info!("Querying ohlc");
-        let ohlc = tradier::get_time_and_sales(&symbol, interval)
+    ) -> async_graphql::Result<Vec<Ohlc>> {
+        log::info!("Querying TDA ohlc");
+        let provider = context
+            .data::<Provider>()
+            .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+        provider
+            .time_and_sales(&symbol, interval)
             .await
-            .map_err(log_error)?;
-        let result = ohlc.into_iter().map(|ts| (interval, ts).into()).collect();
-        Ok(result)
+            .map_err(log_error)
     }
 
-    async fn symbols(&self, context: &Context<'_>) -> anyhow::Result<Vec<String>> {
+    async fn symbols(&self, context: &Context<'_>) -> async_graphql::Result<Vec<String>> {
         log::info!("Querying symbols");
         let db = context
             .data::<Arc<Mutex<FileDb>>>()
@@ -154,16 +366,22 @@ impl TdaRoot {
         Ok(db.symbols())
     }
 
+    async fn option_chain(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+    ) -> async_graphql::Result<Vec<OptionInfo>> {
+        log::info!("Querying TDA option chain");
+        tda_option_chain(context, &symbol).await.map_err(log_error)
+    }
+
     async fn option_stats(
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<Vec<StrikeStats>> {
-        log::info!("Querying option stats");
-        let db = context
-            .data::<Arc<Mutex<FileDb>>>()
-            .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+    ) -> async_graphql::Result<Vec<StrikeStats>> {
+        log::info!("Querying TDA option stats");
+        let option_chain = tda_option_chain(context, &symbol)
             .await
             .map_err(log_error)?;
         let stats = option_stats(&option_chain);
@@ -174,31 +392,114 @@ impl TdaRoot {
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<GammaExposureStats> {
-        log::info!("Querying gamma exposure");
-        let db = context
-            .data::<Arc<Mutex<FileDb>>>()
-            .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+    ) -> async_graphql::Result<GammaExposureStats> {
+        log::info!("Querying TDA gamma exposure");
+        let option_chain = tda_option_chain(context, &symbol)
             .await
             .map_err(log_error)?;
-        let gex = gamma_exposure(&symbol, &option_chain).unwrap();
-        Ok(gex)
+        gamma_exposure(&symbol, &option_chain).map_err(log_error)
     }
 
     async fn gamma_exposure_aggregate(
         &self,
         context: &Context<'_>,
         symbol: String,
-    ) -> anyhow::Result<GammaExposureStats> {
-        log::info!("Querying gamma exposure aggregate");
-        let db = context
-            .data::<Arc<Mutex<FileDb>>>()
-            .map_err(|_| anyhow::anyhow!("Failed to load db"))?;
-        let option_chain = db::option_chain(&symbol, db.clone())
+    ) -> async_graphql::Result<GammaExposureStats> {
+        log::info!("Querying TDA gamma exposure aggregate");
+        let option_chain = tda_option_chain(context, &symbol)
             .await
             .map_err(log_error)?;
-        let gex_agg = gamma_exposure_aggregate(&symbol, &option_chain).unwrap();
-        Ok(gex_agg)
+        let pricing = pricing(context);
+        blocking(move || {
+            gamma_exposure_aggregate(&symbol, &option_chain, Utc::now(), &pricing, None)
+        })
+        .await
+        .map_err(log_error)
+    }
+
+    async fn vanna_exposure_aggregate(
+        &self,
+        context: &Context<'_>,
+        symbol: String,
+        iv_shocks: Option<Vec<f64>>,
+    ) -> async_graphql::Result<Vec<VannaExposureStats>> {
+        log::info!("Querying TDA vanna exposure aggregate");
+        let option_chain = tda_option_chain(context, &symbol)
+            .await
+            .map_err(log_error)?;
+        let pricing = pricing(context);
+        let iv_shocks = iv_shocks.unwrap_or_else(|| DEFAULT_IV_SHOCKS.to_vec());
+        blocking(move || {
+            vanna_exposure_aggregate(
+                &symbol,
+                &option_chain,
+                Utc::now(),
+                &pricing,
+                None,
+                &iv_shocks,
+            )
+        })
+        .await
+        .map_err(log_error)
+    }
+}
+
+/// A fresh chain from the provider, which downloads it on every request.
+async fn tda_option_chain(context: &Context<'_>, symbol: &str) -> anyhow::Result<Vec<OptionInfo>> {
+    let provider = context
+        .data::<Provider>()
+        .map_err(|_| anyhow::anyhow!("Failed to load data provider"))?;
+    provider.option_chain(&symbol.to_uppercase()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{data_apis::td::TdProvider, types::Quote};
+
+    #[tokio::test]
+    async fn snapshot() {
+        let root = std::path::Path::new("data/test/graphql_snapshot");
+        let _ = std::fs::remove_dir_all(root);
+        let mut db = FileDb::new(root);
+        let time = Utc.with_ymd_and_hms(2021, 6, 10, 14, 0, 0).unwrap();
+        db.add_snapshot(OptionSnapshot::new(
+            time,
+            "TST",
+            "test",
+            Some(Quote::test()),
+            vec![OptionInfo::test()],
+        ))
+        .unwrap();
+
+        let schema = schema(
+            Arc::new(Mutex::new(db)),
+            Arc::new(TdProvider::default()),
+            PricingConfig::default(),
+        );
+        let response = schema
+            .execute(
+                r#"{ snapshot(symbol: "tst", at: "2021-06-10T15:00:00Z") {
+                    timestamp symbol provider quote { last } options { strike }
+                } }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        let snapshot = &data["snapshot"];
+        assert_eq!(snapshot["timestamp"], time.to_rfc3339());
+        assert_eq!(snapshot["symbol"], "TST");
+        assert_eq!(snapshot["provider"], "test");
+        assert_eq!(snapshot["quote"]["last"], Quote::test().last.unwrap());
+        assert_eq!(snapshot["options"].as_array().unwrap().len(), 1);
+
+        let response = schema
+            .execute(r#"{ volSurface(symbol: "TST", at: "yesterday") { symbol } }"#)
+            .await;
+        let extensions = serde_json::to_value(&response.errors[0].extensions).unwrap();
+        assert_eq!(extensions["code"], "INVALID_INPUT");
     }
 }
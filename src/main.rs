@@ -6,15 +6,26 @@ pub mod math;
 pub mod types;
 pub mod utils;
 
+use anyhow::Context;
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use std::{convert::Infallible, sync::Arc};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use std::{convert::Infallible, path::Path, sync::Arc};
 use tokio::sync::Mutex;
 use warp::{
     http::{Response, StatusCode},
     Filter, Rejection,
 };
 
-use crate::db::FileDb;
+use crate::{
+    data_apis::DataError,
+    db::{csv::ColumnMapping, FileDb, RetentionPolicy, SqliteDb},
+    math::pricing::PricingConfig,
+    types::OptionSnapshot,
+    utils::blocking,
+};
+
+const MAX_IMPORT_BYTES: u64 = 256 * 1024 * 1024;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -26,18 +37,77 @@ async fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
 
     let frontend = warp::fs::dir("frontend/public");
-    let db_download = warp::path("db").and(warp::fs::file("data/db.gz"));
 
-    let db = match FileDb::load() {
-        Ok(db) => db,
-        Err(_) => FileDb::default(),
-    };
+    let provider = data_apis::provider_from_env()?;
+    log::info!("Using {} market data", provider.name());
+
+    // Replayed snapshots go to their own database unless `DB_PATH` says
+    // otherwise, and never into the recording being replayed
+    let replay = provider.name() == data_apis::replay::PROVIDER_NAME;
+    let db_root = db::file::root_from_env(if replay {
+        db::file::REPLAY_DB_PATH
+    } else {
+        db::file::DEFAULT_DB_PATH
+    });
+    if replay {
+        data_apis::replay::check_db_root(&db_root)?;
+    }
+    let legacy_path = Some(Path::new(db::file::DEFAULT_FILE_PATH)).filter(|_| !replay);
+    log::info!("Storing snapshots in {}", db_root.display());
+
+    // Starting with an empty database would overwrite the history on the next
+    // snapshot, so refuse to start if it can't be read or recovered.
+    let db = FileDb::load(&db_root, legacy_path).map_err(|e| {
+        log::error!("Failed to load database: {}", e);
+        e
+    })?;
     let db = Arc::new(Mutex::new(db));
 
-    db::start_db_update_loop(db.clone())?;
+    let db_download = warp::path("db")
+        .and(with_db(db.clone()))
+        .and_then(download_db);
+
+    let export = warp::path("export")
+        .and(warp::get())
+        .and(warp::query::<ExportQuery>())
+        .and(with_db(db.clone()))
+        .and_then(export_parquet);
+
+    let csv_download = warp::path("csv")
+        .and(warp::get())
+        .and(warp::query::<CsvQuery>())
+        .and(with_db(db.clone()))
+        .and_then(download_csv);
+
+    let csv_import = warp::path!("import" / "csv")
+        .and(warp::post())
+        .and(warp::query::<CsvQuery>())
+        .and(warp::body::content_length_limit(MAX_IMPORT_BYTES))
+        .and(warp::body::bytes())
+        .and(with_db(db.clone()))
+        .and_then(import_csv);
+
+    let sqlite = SqliteDb::from_env()?;
+    if sqlite.is_some() {
+        log::info!("Mirroring snapshots to SQLite");
+    }
+
+    db::start_db_update_loop(db.clone(), provider.clone(), sqlite)?;
+
+    if let Some(policy) = RetentionPolicy::from_env()? {
+        log::info!("Compacting snapshots with {:?}", policy);
+        db::retention::start_compaction_loop(db.clone(), policy)?;
+    }
+
+    let pricing = PricingConfig::from_env()?;
 
     let tradier_graphql_filter = warp::path("graphql").and(
-        async_graphql_warp::graphql(graphql::schema(db.clone())).and_then(
+        async_graphql_warp::graphql(graphql::schema(
+            db.clone(),
+            provider.clone(),
+            pricing.clone(),
+        ))
+        .and_then(
             |(schema, request): (graphql::Schema, async_graphql::Request)| async move {
                 let resp = schema.execute(request).await;
                 Ok::<_, Infallible>(async_graphql_warp::Response::from(resp))
@@ -52,7 +122,12 @@ async fn main() -> anyhow::Result<()> {
     });
 
     let tda_graphql_filter = warp::path("tdagraphql").and(
-        async_graphql_warp::graphql(graphql::tda_schema(db.clone())).and_then(
+        async_graphql_warp::graphql(graphql::tda_schema(
+            db.clone(),
+            Arc::new(data_apis::td::TdProvider::new(pricing.clone())),
+            pricing,
+        ))
+        .and_then(
             |(schema, request): (graphql::TdaSchema, async_graphql::Request)| async move {
                 let resp = schema.execute(request).await;
                 Ok::<_, Infallible>(async_graphql_warp::Response::from(resp))
@@ -74,6 +149,9 @@ async fn main() -> anyhow::Result<()> {
         .allow_header("content-type");
 
     let routes = db_download
+        .or(export)
+        .or(csv_download)
+        .or(csv_import)
         .or(tradier_graphql_filter)
         .or(tradier_graphql_playground)
         .or(tda_graphql_filter)
@@ -89,9 +167,226 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn with_db(
+    db: Arc<Mutex<FileDb>>,
+) -> impl Filter<Extract = (Arc<Mutex<FileDb>>,), Error = Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
+
+/// Serves the database in the legacy single-file `db.gz` format.
+async fn download_db(db: Arc<Mutex<FileDb>>) -> Result<impl warp::Reply, Infallible> {
+    let segments = db.lock().await.segment_refs();
+    let bytes = blocking(move || db::file::encode_legacy(&segments)).await;
+
+    let response = match bytes {
+        Ok(bytes) => Response::builder()
+            .header("content-type", "application/gzip")
+            .header("content-disposition", "attachment; filename=\"db.gz\"")
+            .body(bytes),
+        Err(e) => {
+            log::error!("{}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Vec::new())
+        }
+    };
+
+    Ok(response)
+}
+
+/// `/export?symbol=SPY&from=2021-06-01&to=2021-06-30`, both dates inclusive
+/// and optional.
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    symbol: String,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Serves every snapshot of a symbol within a date range as a Parquet file.
+async fn export_parquet(
+    query: ExportQuery,
+    db: Arc<Mutex<FileDb>>,
+) -> Result<impl warp::Reply, Infallible> {
+    let from = query.from.unwrap_or(NaiveDate::MIN);
+    let to = query.to.unwrap_or(NaiveDate::MAX);
+    let mut file_name = query.symbol.to_uppercase().replace('/', "");
+    for date in [query.from, query.to].iter().flatten() {
+        file_name = format!("{}_{}", file_name, date);
+    }
+
+    let segments = db
+        .lock()
+        .await
+        .symbol_segment_refs(&query.symbol, from..=to);
+    let bytes = blocking(move || db::file::encode_parquet(&segments)).await;
+
+    let response = match bytes {
+        Ok(bytes) => Response::builder()
+            .header("content-type", db::parquet::CONTENT_TYPE)
+            .header(
+                "content-disposition",
+                format!(
+                    "attachment; filename=\"{}.{}\"",
+                    file_name,
+                    db::parquet::EXTENSION
+                ),
+            )
+            .body(bytes),
+        Err(e) => {
+            log::error!("{}", e);
+            Response::builder()
+                .status(error_status(&e))
+                .body(Vec::new())
+        }
+    };
+
+    Ok(response)
+}
+
+/// `/csv?symbol=SPY&at=2021-06-10T20:00:00Z&mapping=cboe` downloads a stored
+/// snapshot, the latest one without `at`. `/import/csv?symbol=SPX&mapping=cboe`
+/// stores the CSV in the request body. The mapping defaults to `default`.
+#[derive(Debug, Deserialize)]
+struct CsvQuery {
+    symbol: String,
+    at: Option<DateTime<Utc>>,
+    mapping: Option<String>,
+}
+
+impl CsvQuery {
+    fn mapping(&self) -> anyhow::Result<ColumnMapping> {
+        ColumnMapping::named(self.mapping.as_deref().unwrap_or("default"))
+            .map_err(|e| DataError::InvalidInput(e.to_string()).into())
+    }
+}
+
+async fn download_csv(
+    query: CsvQuery,
+    db: Arc<Mutex<FileDb>>,
+) -> Result<impl warp::Reply, Infallible> {
+    let csv = match stored_snapshot(&db, &query).await {
+        Ok(snapshot) => {
+            let mapping = query.mapping();
+            blocking(move || {
+                let mut bytes = Vec::new();
+                db::csv::write_snapshot(&mut bytes, &snapshot, &mapping?)?;
+                Ok((snapshot.timestamp, bytes))
+            })
+            .await
+        }
+        Err(e) => Err(e),
+    };
+
+    let response = match csv {
+        Ok((timestamp, bytes)) => Response::builder()
+            .header("content-type", db::csv::CONTENT_TYPE)
+            .header(
+                "content-disposition",
+                format!(
+                    "attachment; filename=\"{}_{}.csv\"",
+                    query.symbol.to_uppercase().replace('/', ""),
+                    timestamp.format("%Y%m%dT%H%M%SZ")
+                ),
+            )
+            .body(bytes),
+        Err(e) => {
+            log::error!("{}", e);
+            Response::builder()
+                .status(error_status(&e))
+                .body(Vec::new())
+        }
+    };
+
+    Ok(response)
+}
+
+/// A copy of the snapshot `query` asks for, so it can be written out without
+/// holding the database.
+async fn stored_snapshot(
+    db: &Arc<Mutex<FileDb>>,
+    query: &CsvQuery,
+) -> anyhow::Result<OptionSnapshot> {
+    let mut db = db.lock().await;
+    let snapshot = match query.at {
+        Some(at) => db.snapshot_at(&query.symbol, at)?,
+        None => db.snapshot(&query.symbol)?,
+    }
+    .ok_or_else(|| DataError::UnknownSymbol(query.symbol.to_uppercase()))?;
+
+    Ok(snapshot.clone())
+}
+
+async fn import_csv(
+    query: CsvQuery,
+    body: warp::hyper::body::Bytes,
+    db: Arc<Mutex<FileDb>>,
+) -> Result<impl warp::Reply, Infallible> {
+    let mapping = query.mapping();
+    let symbol = query.symbol.clone();
+    let snapshots = blocking(move || {
+        db::csv::read_snapshots(&*body, &symbol, &mapping?)
+            .map_err(|e| DataError::InvalidInput(format!("{:#}", e)).into())
+    })
+    .await;
+
+    // Only storing the parsed snapshots needs the database. A failure there
+    // leaves the snapshots stored before it, which the response says.
+    let imported = match snapshots {
+        Ok(snapshots) => {
+            let mut db = db.lock().await;
+            let total = snapshots.len();
+            let mut stored = 0;
+            snapshots
+                .into_iter()
+                .try_for_each(|snapshot| {
+                    db.add_snapshot(snapshot)?;
+                    stored += 1;
+                    anyhow::Ok(())
+                })
+                .with_context(|| format!("Stored {} of {} snapshots before failing", stored, total))
+                .map(|_| total)
+        }
+        Err(e) => Err(e),
+    };
+
+    let response = match imported {
+        Ok(count) => warp::reply::with_status(
+            format!(
+                "Imported {} snapshots of {}",
+                count,
+                query.symbol.to_uppercase()
+            ),
+            StatusCode::OK,
+        ),
+        Err(e) => {
+            log::error!("{:#}", e);
+            warp::reply::with_status(format!("{:#}", e), error_status(&e))
+        }
+    };
+
+    Ok(response)
+}
+
+/// The status to respond with when a handler fails with `error`.
+fn error_status(error: &anyhow::Error) -> StatusCode {
+    DataError::find(error).map_or(StatusCode::INTERNAL_SERVER_ERROR, DataError::status)
+}
+
 async fn handle_rejection(err: Rejection) -> Result<impl warp::Reply, Infallible> {
-    Ok(warp::reply::with_status(
-        format!("{:?}", err),
-        StatusCode::INTERNAL_SERVER_ERROR,
-    ))
+    let (status, message) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    } else if let Some(e) = err.find::<async_graphql_warp::BadRequest>() {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::PayloadTooLarge>() {
+        (StatusCode::PAYLOAD_TOO_LARGE, e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::MethodNotAllowed>() {
+        (StatusCode::METHOD_NOT_ALLOWED, e.to_string())
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", err))
+    };
+
+    Ok(warp::reply::with_status(message, status))
 }
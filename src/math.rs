@@ -1,4 +1,12 @@
+pub mod american;
+pub mod batch;
+pub mod black76;
 pub mod bs;
+pub mod expiry;
+pub mod iv;
+pub mod pricing;
+pub mod rates;
+pub mod svi;
 
 use statrs::distribution::{ContinuousCDF, Normal};
 
@@ -0,0 +1,746 @@
+//! American options, which can be exercised any time before expiry, with a
+//! Cox-Ross-Rubinstein binomial tree or the Bjerksund-Stensland (2002)
+//! approximation. Neither has closed-form greeks, so they come from finite
+//! differences of the price, except that the tree reads delta, gamma and
+//! theta off its first steps. Times are in years, and the rate and dividend
+//! yield are continuous as in [`super::bs`].
+
+use super::{bs, standard_normal_cdf};
+use crate::types::{Greeks, OptionType};
+
+/// Enough for prices within about a cent of the limit for listed options
+/// while keeping exposures over a whole chain quick to compute.
+const BINOMIAL_STEPS: usize = 100;
+
+/// Relative changes of the underlying price for finite differences. The
+/// tree's gamma jumps as nodes cross the strike, so speed is measured across
+/// a wider move.
+const PRICE_BUMP: f64 = 0.001;
+const TREE_PRICE_BUMP: f64 = 0.01;
+const VOLATILITY_BUMP: f64 = 0.005;
+const RATE_BUMP: f64 = 0.0001;
+const TIME_BUMP: f64 = 1.0 / 365.0;
+
+pub fn binomial_price(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    tree(
+        option_type,
+        sigma,
+        time_to_expiry,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    )
+    .price
+}
+
+pub fn binomial_gamma(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    tree(
+        option_type,
+        sigma,
+        time_to_expiry,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    )
+    .gamma
+}
+
+/// Every greek of an American option priced on a binomial tree. Moving the
+/// volatility or the time moves the nodes of the tree, so those bumps are
+/// kept wide enough to step over the resulting noise.
+pub fn binomial_greeks(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> Greeks {
+    let t = time_to_expiry;
+    let tree = |sigma: f64, t: f64, current_price: f64, rate: f64| {
+        tree(
+            option_type,
+            sigma,
+            t,
+            current_price,
+            strike,
+            rate,
+            dividend_yield,
+        )
+    };
+    let s = current_price;
+
+    let values = tree(sigma, t, s, rate);
+    let dv = volatility_bump(sigma);
+    let (vol_up, vol_down) = (tree(sigma + dv, t, s, rate), tree(sigma - dv, t, s, rate));
+    let (vol_up2, vol_down2) = (
+        tree(sigma + 2.0 * dv, t, s, rate),
+        tree(sigma - 2.0 * dv, t, s, rate),
+    );
+    let dr = RATE_BUMP;
+    let (rate_up, rate_down) = (tree(sigma, t, s, rate + dr), tree(sigma, t, s, rate - dr));
+    let dt = TIME_BUMP.min(t / 2.0);
+    let later = tree(sigma, t - dt, s, rate);
+    let (later_up, later_down) = (
+        tree(sigma + dv, t - dt, s, rate),
+        tree(sigma - dv, t - dt, s, rate),
+    );
+    let ds = s * TREE_PRICE_BUMP;
+    let (price_up, price_down) = (tree(sigma, t, s + ds, rate), tree(sigma, t, s - ds, rate));
+
+    let vega = (vol_up.price - vol_down.price) / (2.0 * dv) / 100.0;
+    let later_vega = (later_up.price - later_down.price) / (2.0 * dv) / 100.0;
+
+    Greeks {
+        delta: values.delta,
+        gamma: values.gamma,
+        theta: values.theta,
+        vega,
+        rho: (rate_up.price - rate_down.price) / (2.0 * dr) / 100.0,
+        vanna: (vol_up.delta - vol_down.delta) / (2.0 * dv) / 100.0,
+        charm: (later.delta - values.delta) / dt,
+        speed: (price_up.gamma - price_down.gamma) / (2.0 * ds),
+        zomma: (vol_up.gamma - vol_down.gamma) / (2.0 * dv) / 100.0,
+        color: (later.gamma - values.gamma) / dt / 365.0,
+        vomma: (vol_up.price - 2.0 * values.price + vol_down.price) / dv.powi(2) / 10_000.0,
+        veta: (later_vega - vega) / dt / 365.0,
+        ultima: (vol_up2.price - 2.0 * vol_up.price + 2.0 * vol_down.price - vol_down2.price)
+            / (2.0 * dv.powi(3))
+            / 1_000_000.0,
+    }
+}
+
+pub fn bjerksund_stensland_price(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = time_to_expiry;
+    if !(t > 0.0 && sigma > 0.0) {
+        return intrinsic_value(option_type, current_price, strike);
+    }
+
+    let carry = rate - dividend_yield;
+    let (american, european) = match option_type {
+        OptionType::Call => (
+            bjerksund_stensland_call(sigma, t, current_price, strike, rate, carry),
+            bs::call_price(sigma, t, 0.0, current_price, strike, rate, dividend_yield),
+        ),
+        // A put is a call with the underlying and strike swapped, the rate
+        // and dividend yield too
+        OptionType::Put => (
+            bjerksund_stensland_call(sigma, t, strike, current_price, dividend_yield, -carry),
+            bs::put_price(sigma, t, 0.0, current_price, strike, rate, dividend_yield),
+        ),
+    };
+
+    // The approximation falls short of the European price when early
+    // exercise is barely worth anything, as with rates near zero
+    american.max(european)
+}
+
+pub fn bjerksund_stensland_gamma(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let price = |current_price: f64| {
+        bjerksund_stensland_price(
+            option_type,
+            sigma,
+            time_to_expiry,
+            current_price,
+            strike,
+            rate,
+            dividend_yield,
+        )
+    };
+    let ds = current_price * PRICE_BUMP;
+
+    (price(current_price + ds) - 2.0 * price(current_price) + price(current_price - ds))
+        / ds.powi(2)
+}
+
+/// Every greek of an American option under the Bjerksund-Stensland
+/// approximation.
+pub fn bjerksund_stensland_greeks(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> Greeks {
+    let t = time_to_expiry;
+    let s = current_price;
+    let price = |sigma: f64, t: f64, current_price: f64, rate: f64| {
+        bjerksund_stensland_price(
+            option_type,
+            sigma,
+            t,
+            current_price,
+            strike,
+            rate,
+            dividend_yield,
+        )
+    };
+    let ds = s * PRICE_BUMP;
+    let dv = volatility_bump(sigma);
+    let dr = RATE_BUMP;
+    let dt = TIME_BUMP.min(t / 2.0);
+
+    let delta = |sigma: f64, t: f64| {
+        (price(sigma, t, s + ds, rate) - price(sigma, t, s - ds, rate)) / (2.0 * ds)
+    };
+    let gamma = |sigma: f64, t: f64, s: f64| {
+        (price(sigma, t, s + ds, rate) - 2.0 * price(sigma, t, s, rate)
+            + price(sigma, t, s - ds, rate))
+            / ds.powi(2)
+    };
+    let vega = |sigma: f64, t: f64| {
+        (price(sigma + dv, t, s, rate) - price(sigma - dv, t, s, rate)) / (2.0 * dv) / 100.0
+    };
+
+    let value = price(sigma, t, s, rate);
+    let spot_delta = delta(sigma, t);
+    let spot_gamma = gamma(sigma, t, s);
+    let spot_vega = vega(sigma, t);
+    let (vol_up, vol_down) = (price(sigma + dv, t, s, rate), price(sigma - dv, t, s, rate));
+
+    Greeks {
+        delta: spot_delta,
+        gamma: spot_gamma,
+        theta: (price(sigma, t - dt, s, rate) - value) / dt / 365.0,
+        vega: spot_vega,
+        rho: (price(sigma, t, s, rate + dr) - price(sigma, t, s, rate - dr)) / (2.0 * dr) / 100.0,
+        vanna: (delta(sigma + dv, t) - delta(sigma - dv, t)) / (2.0 * dv) / 100.0,
+        charm: (delta(sigma, t - dt) - spot_delta) / dt,
+        speed: (gamma(sigma, t, s + ds) - gamma(sigma, t, s - ds)) / (2.0 * ds),
+        zomma: (gamma(sigma + dv, t, s) - gamma(sigma - dv, t, s)) / (2.0 * dv) / 100.0,
+        color: (gamma(sigma, t - dt, s) - spot_gamma) / dt / 365.0,
+        vomma: (vol_up - 2.0 * value + vol_down) / dv.powi(2) / 10_000.0,
+        veta: (vega(sigma, t - dt) - spot_vega) / dt / 365.0,
+        ultima: (vega(sigma + dv, t) - 2.0 * spot_vega + vega(sigma - dv, t))
+            / dv.powi(2)
+            / 10_000.0,
+    }
+}
+
+/// Small enough for accurate differences, but never so wide that the
+/// volatility is bumped to zero.
+fn volatility_bump(sigma: f64) -> f64 {
+    VOLATILITY_BUMP.min(sigma / 4.0)
+}
+
+/// The price and the greeks a binomial tree gives without being rebuilt.
+/// Theta is per calendar day like [`bs::call_theta`].
+#[derive(Clone, Copy, Debug, Default)]
+struct TreeValues {
+    price: f64,
+    delta: f64,
+    gamma: f64,
+    theta: f64,
+}
+
+/// Values an American option on a tree, correcting it by how far a European
+/// option on the same tree is from Black-Scholes-Merton. Most of the error
+/// of a tree comes from where its nodes fall around the strike, which is
+/// the same for both, so the correction also keeps the greeks from jumping
+/// about as the inputs move the nodes.
+fn tree(
+    option_type: OptionType,
+    sigma: f64,
+    t: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> TreeValues {
+    let intrinsic = intrinsic_value(option_type, current_price, strike);
+    if !(t > 0.0 && sigma > 0.0) {
+        return TreeValues {
+            price: intrinsic,
+            ..TreeValues::default()
+        };
+    }
+
+    let steps = BINOMIAL_STEPS;
+    let dt = t / steps as f64;
+    let up = (sigma * dt.sqrt()).exp();
+    let down = 1.0 / up;
+    let p = ((((rate - dividend_yield) * dt).exp() - down) / (up - down)).clamp(0.0, 1.0);
+    let discount = (-rate * dt).exp();
+    // The underlying price after `step` steps with `ups` of them up
+    let node = |step: usize, ups: usize| current_price * up.powi(2 * ups as i32 - step as i32);
+
+    let mut american: Vec<f64> = (0..=steps)
+        .map(|ups| intrinsic_value(option_type, node(steps, ups), strike))
+        .collect();
+    let mut european = american.clone();
+    let mut first = ([0.0; 2], [0.0; 2]);
+    let mut second = ([0.0; 3], [0.0; 3]);
+
+    for step in (0..steps).rev() {
+        for ups in 0..=step {
+            let held = discount * (p * american[ups + 1] + (1.0 - p) * american[ups]);
+            american[ups] = held.max(intrinsic_value(option_type, node(step, ups), strike));
+            european[ups] = discount * (p * european[ups + 1] + (1.0 - p) * european[ups]);
+        }
+        match step {
+            2 => {
+                second.0.copy_from_slice(&american[..3]);
+                second.1.copy_from_slice(&european[..3]);
+            }
+            1 => {
+                first.0.copy_from_slice(&american[..2]);
+                first.1.copy_from_slice(&european[..2]);
+            }
+            _ => {}
+        }
+    }
+
+    let (s_up, s_down) = (node(2, 2), node(2, 0));
+    let read = |price: f64, first: [f64; 2], second: [f64; 3]| {
+        let delta_up = (second[2] - second[1]) / (s_up - current_price);
+        let delta_down = (second[1] - second[0]) / (current_price - s_down);
+        TreeValues {
+            price,
+            delta: (first[1] - first[0]) / (node(1, 1) - node(1, 0)),
+            gamma: (delta_up - delta_down) / ((s_up - s_down) / 2.0),
+            // The middle node two steps in is back at the current price
+            theta: (second[1] - price) / (2.0 * dt) / 365.0,
+        }
+    };
+    let american = read(american[0], first.0, second.0);
+    let european = read(european[0], first.1, second.1);
+
+    let exact_price = match option_type {
+        OptionType::Call => bs::call_price,
+        OptionType::Put => bs::put_price,
+    };
+    let exact_price = exact_price(sigma, t, 0.0, current_price, strike, rate, dividend_yield);
+    let exact = bs::greeks(
+        option_type,
+        sigma,
+        t,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+
+    TreeValues {
+        price: (american.price + exact_price - european.price).max(intrinsic),
+        delta: american.delta + exact.delta - european.delta,
+        gamma: american.gamma + exact.gamma - european.gamma,
+        theta: american.theta + exact.theta - european.theta,
+    }
+}
+
+/// An American call with cost of carry `carry`, the rate less the dividend
+/// yield. Exercise follows a flat boundary before `t1` and another flat one
+/// after it.
+fn bjerksund_stensland_call(
+    sigma: f64,
+    t: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    carry: f64,
+) -> f64 {
+    // Without dividends a call is never worth exercising early
+    if carry >= rate {
+        return bs::call_price(sigma, t, 0.0, current_price, strike, rate, rate - carry);
+    }
+
+    let variance = sigma.powi(2);
+    let t1 = 0.5 * (5f64.sqrt() - 1.0) * t;
+    let beta = (0.5 - carry / variance)
+        + ((carry / variance - 0.5).powi(2) + 2.0 * rate / variance).sqrt();
+    let b_infinity = beta / (beta - 1.0) * strike;
+    let b_zero = strike.max(rate / (rate - carry) * strike);
+    let boundary = |t: f64| {
+        let h = -(carry * t + 2.0 * sigma * t.sqrt()) * strike.powi(2)
+            / ((b_infinity - b_zero) * b_zero);
+        b_zero + (b_infinity - b_zero) * (1.0 - h.exp())
+    };
+    let (i1, i2) = (boundary(t1), boundary(t));
+    if current_price >= i2 {
+        return current_price - strike;
+    }
+
+    let alpha1 = (i1 - strike) * i1.powf(-beta);
+    let alpha2 = (i2 - strike) * i2.powf(-beta);
+    let terms = Terms {
+        current_price,
+        rate,
+        carry,
+        sigma,
+    };
+    let phi = |gamma: f64, h: f64, i: f64| terms.phi(t1, gamma, h, i);
+    let ksi = |gamma: f64, h: f64| terms.ksi(t, gamma, h, i2, i1, t1);
+
+    alpha2 * current_price.powf(beta) - alpha2 * phi(beta, i2, i2) + phi(1.0, i2, i2)
+        - phi(1.0, i1, i2)
+        - strike * phi(0.0, i2, i2)
+        + strike * phi(0.0, i1, i2)
+        + alpha1 * phi(beta, i1, i2)
+        - alpha1 * ksi(beta, i1)
+        + ksi(1.0, i1)
+        - ksi(1.0, strike)
+        - strike * ksi(0.0, i1)
+        + strike * ksi(0.0, strike)
+}
+
+/// The inputs shared by the terms of the Bjerksund-Stensland formula.
+struct Terms {
+    current_price: f64,
+    rate: f64,
+    carry: f64,
+    sigma: f64,
+}
+
+impl Terms {
+    fn lambda(&self, gamma: f64) -> f64 {
+        -self.rate + gamma * self.carry + 0.5 * gamma * (gamma - 1.0) * self.sigma.powi(2)
+    }
+
+    fn kappa(&self, gamma: f64) -> f64 {
+        2.0 * self.carry / self.sigma.powi(2) + 2.0 * gamma - 1.0
+    }
+
+    /// The drift term of the d's for a payoff of the underlying to `gamma`.
+    fn drift(&self, gamma: f64) -> f64 {
+        self.carry + (gamma - 0.5) * self.sigma.powi(2)
+    }
+
+    /// Values the underlying to `gamma` paid at `t` unless it reached `i`
+    /// first, and only when above `h`.
+    fn phi(&self, t: f64, gamma: f64, h: f64, i: f64) -> f64 {
+        let s = self.current_price;
+        let vol = self.sigma * t.sqrt();
+        let d = -((s / h).ln() + self.drift(gamma) * t) / vol;
+
+        (self.lambda(gamma) * t).exp()
+            * s.powf(gamma)
+            * (standard_normal_cdf(d)
+                - (i / s).powf(self.kappa(gamma))
+                    * standard_normal_cdf(d - 2.0 * (i / s).ln() / vol))
+    }
+
+    /// Like [`Terms::phi`] with the barrier at `i1` until `t1` and at `i2`
+    /// from then until `t2`.
+    fn ksi(&self, t2: f64, gamma: f64, h: f64, i2: f64, i1: f64, t1: f64) -> f64 {
+        let s = self.current_price;
+        let drift = self.drift(gamma);
+        let (vol1, vol2) = (self.sigma * t1.sqrt(), self.sigma * t2.sqrt());
+
+        let e1 = ((s / i1).ln() + drift * t1) / vol1;
+        let e2 = ((i2.powi(2) / (s * i1)).ln() + drift * t1) / vol1;
+        let e3 = ((s / i1).ln() - drift * t1) / vol1;
+        let e4 = ((i2.powi(2) / (s * i1)).ln() - drift * t1) / vol1;
+        let f1 = ((s / h).ln() + drift * t2) / vol2;
+        let f2 = ((i2.powi(2) / (s * h)).ln() + drift * t2) / vol2;
+        let f3 = ((i1.powi(2) / (s * h)).ln() + drift * t2) / vol2;
+        let f4 = ((s * i1.powi(2) / (h * i2.powi(2))).ln() + drift * t2) / vol2;
+        let rho = (t1 / t2).sqrt();
+        let kappa = self.kappa(gamma);
+
+        (self.lambda(gamma) * t2).exp()
+            * s.powf(gamma)
+            * (bivariate_normal_cdf(-e1, -f1, rho)
+                - (i2 / s).powf(kappa) * bivariate_normal_cdf(-e2, -f2, rho)
+                - (i1 / s).powf(kappa) * bivariate_normal_cdf(-e3, -f3, -rho)
+                + (i1 / i2).powf(kappa) * bivariate_normal_cdf(-e4, -f4, -rho))
+    }
+}
+
+fn intrinsic_value(option_type: OptionType, current_price: f64, strike: f64) -> f64 {
+    match option_type {
+        OptionType::Call => (current_price - strike).max(0.0),
+        OptionType::Put => (strike - current_price).max(0.0),
+    }
+}
+
+/// Gauss-Legendre abscissas and weights on half of [-1, 1], with 6, 12 and
+/// 20 points.
+const GAUSS_LEGENDRE: [&[(f64, f64)]; 3] = [
+    &[
+        (-0.932_469_514_203_152_2, 0.171_324_492_379_170_5),
+        (-0.661_209_386_466_264_7, 0.360_761_573_048_138_4),
+        (-0.238_619_186_083_197, 0.467_913_934_572_690_4),
+    ],
+    &[
+        (-0.981_560_634_246_719_1, 0.047_175_336_386_511_77),
+        (-0.904_117_256_370_475, 0.106_939_325_995_318_3),
+        (-0.769_902_674_194_305, 0.160_078_328_543_346_4),
+        (-0.587_317_954_286_617_1, 0.203_167_426_723_065_9),
+        (-0.367_831_498_998_180_2, 0.233_492_536_538_354_7),
+        (-0.125_233_408_511_469_2, 0.249_147_045_813_402_9),
+    ],
+    &[
+        (-0.993_128_599_185_094_9, 0.017_614_007_139_152_12),
+        (-0.963_971_927_277_913_8, 0.040_601_429_800_386_94),
+        (-0.912_234_428_251_326, 0.062_672_048_334_109_06),
+        (-0.839_116_971_822_218_8, 0.083_276_741_576_704_75),
+        (-0.746_331_906_460_150_8, 0.101_930_119_817_240_4),
+        (-0.636_053_680_726_515, 0.118_194_531_961_518_4),
+        (-0.510_867_001_950_827_1, 0.131_688_638_449_176_6),
+        (-0.373_706_088_715_419_6, 0.142_096_109_318_382_1),
+        (-0.227_785_851_141_645_1, 0.149_172_986_472_603_7),
+        (-0.076_526_521_133_497_33, 0.152_753_387_130_725_9),
+    ],
+];
+
+/// The probability that two standard normals with correlation `rho` are
+/// below `x` and `y`, after Genz (2004).
+fn bivariate_normal_cdf(x: f64, y: f64, rho: f64) -> f64 {
+    use std::f64::consts::PI;
+
+    let points = GAUSS_LEGENDRE[if rho.abs() < 0.3 {
+        0
+    } else if rho.abs() < 0.75 {
+        1
+    } else {
+        2
+    }];
+    // Genz integrates the upper tail
+    let (h, mut k) = (-x, -y);
+    let mut hk = h * k;
+
+    if rho.abs() < 0.925 {
+        let hs = (h * h + k * k) / 2.0;
+        let asr = rho.asin();
+        let mut sum = 0.0;
+        for &(x, w) in points {
+            for sn in [(asr * (1.0 - x) / 2.0).sin(), (asr * (1.0 + x) / 2.0).sin()] {
+                sum += w * ((sn * hk - hs) / (1.0 - sn * sn)).exp();
+            }
+        }
+        return sum * asr / (4.0 * PI) + standard_normal_cdf(-h) * standard_normal_cdf(-k);
+    }
+
+    if rho < 0.0 {
+        k = -k;
+        hk = -hk;
+    }
+    let mut bvn = 0.0;
+    if rho.abs() < 1.0 {
+        let as_ = (1.0 - rho) * (1.0 + rho);
+        let mut a = as_.sqrt();
+        let bs = (h - k).powi(2);
+        let c = (4.0 - hk) / 8.0;
+        let d = (12.0 - hk) / 16.0;
+        bvn = a
+            * (-(bs / as_ + hk) / 2.0).exp()
+            * (1.0 - c * (bs - as_) * (1.0 - d * bs / 5.0) / 3.0 + c * d * as_ * as_ / 5.0);
+        if hk > -160.0 {
+            let b = bs.sqrt();
+            bvn -= (-hk / 2.0).exp()
+                * (2.0 * PI).sqrt()
+                * standard_normal_cdf(-b / a)
+                * b
+                * (1.0 - c * bs * (1.0 - d * bs / 5.0) / 3.0);
+        }
+        a /= 2.0;
+        for &(x, w) in points {
+            for sign in [-1.0, 1.0] {
+                let xs = (a * (sign * x + 1.0)).powi(2);
+                let rs = (1.0 - xs).sqrt();
+                let asr = -(bs / xs + hk) / 2.0;
+                if asr > -100.0 {
+                    bvn += a
+                        * w
+                        * asr.exp()
+                        * ((-hk * (1.0 - rs) / (2.0 * (1.0 + rs))).exp() / rs
+                            - (1.0 + c * xs * (1.0 + d * xs)));
+                }
+            }
+        }
+        bvn = -bvn / (2.0 * PI);
+    }
+
+    if rho > 0.0 {
+        bvn + standard_normal_cdf(-h.max(k))
+    } else if k > h {
+        -bvn + standard_normal_cdf(k) - standard_normal_cdf(h)
+    } else {
+        -bvn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bivariate_normal() {
+        // Along the diagonal at zero the probability has a closed form
+        for rho in [-0.99f64, -0.95, -0.5, 0.0, 0.2, 0.5, 0.8, 0.95, 0.99] {
+            let expected = 0.25 + rho.asin() / (2.0 * std::f64::consts::PI);
+            assert_close(bivariate_normal_cdf(0.0, 0.0, rho), expected, 1e-12);
+        }
+        assert_close(
+            bivariate_normal_cdf(0.3, -0.4, 0.0),
+            standard_normal_cdf(0.3) * standard_normal_cdf(-0.4),
+            1e-12,
+        );
+        // Against numerical integration
+        assert_close(bivariate_normal_cdf(0.5, -0.3, 0.95), 0.381_856_9, 1e-6);
+        assert_close(bivariate_normal_cdf(1.2, 0.7, -0.93), 0.642_966_7, 1e-6);
+        assert_close(bivariate_normal_cdf(-0.4, 0.9, 0.6), 0.332_468_1, 1e-6);
+    }
+
+    #[test]
+    fn binomial_tree() {
+        // Hull, Options, Futures, and Other Derivatives, Example 21.1: the
+        // American put is worth 4.284 in the limit
+        let put = binomial_price(OptionType::Put, 0.4, 5.0 / 12.0, 50.0, 50.0, 0.1, 0.0);
+        assert_close(put, 4.284, 0.01);
+        assert!(put > bs::put_price(0.4, 5.0 / 12.0, 0.0, 50.0, 50.0, 0.1, 0.0));
+
+        // Without dividends an American call is worth the European one
+        let call = binomial_price(OptionType::Call, 0.25, 0.5, 100.0, 95.0, 0.05, 0.0);
+        assert_close(
+            call,
+            bs::call_price(0.25, 0.5, 0.0, 100.0, 95.0, 0.05, 0.0),
+            0.02,
+        );
+
+        // Deep in the money the put is exercised right away
+        let deep = binomial_greeks(OptionType::Put, 0.2, 1.0, 50.0, 100.0, 0.05, 0.0);
+        assert_close(
+            binomial_price(OptionType::Put, 0.2, 1.0, 50.0, 100.0, 0.05, 0.0),
+            50.0,
+            1e-3,
+        );
+        assert_close(deep.delta, -1.0, 1e-3);
+        assert_close(deep.gamma, 0.0, 1e-3);
+    }
+
+    #[test]
+    fn bjerksund_stensland() {
+        // Dividends make early exercise of a call worthwhile
+        let (sigma, t, s, k, r, q) = (0.35, 0.75, 42.0, 40.0, 0.04, 0.08);
+        let call = bjerksund_stensland_price(OptionType::Call, sigma, t, s, k, r, q);
+        assert_close(call, 5.2869, 1e-4);
+        assert!(call > bs::call_price(sigma, t, 0.0, s, k, r, q));
+
+        // The approximation is a lower bound within a percent or two
+        for (option_type, sigma, t, s, k, r, q) in [
+            (OptionType::Call, 0.35, 0.75, 42.0, 40.0, 0.04, 0.08),
+            (OptionType::Put, 0.4, 5.0 / 12.0, 50.0, 50.0, 0.1, 0.0),
+            (OptionType::Put, 0.3, 1.0, 100.0, 110.0, 0.05, 0.01),
+            (OptionType::Put, 0.2, 0.25, 100.0, 90.0, 0.03, 0.02),
+        ] {
+            let approximation = bjerksund_stensland_price(option_type, sigma, t, s, k, r, q);
+            let tree = binomial_price(option_type, sigma, t, s, k, r, q);
+            assert!(
+                approximation < tree * 1.002 && approximation > tree * 0.98,
+                "{:?} {} {}: {} {}",
+                option_type,
+                s,
+                k,
+                approximation,
+                tree
+            );
+        }
+
+        assert_eq!(
+            bjerksund_stensland_price(OptionType::Call, 0.25, 0.5, 100.0, 90.0, 0.05, 0.0),
+            bs::call_price(0.25, 0.5, 0.0, 100.0, 90.0, 0.05, 0.0)
+        );
+        assert_eq!(
+            bjerksund_stensland_price(OptionType::Put, 0.2, 1.0, 50.0, 100.0, 0.05, 0.0),
+            50.0
+        );
+    }
+
+    #[test]
+    fn greeks_match_european_without_early_exercise() {
+        // With no dividends and no rate neither option is exercised early
+        let (sigma, t, s, k) = (0.3, 0.5, 100.0, 105.0);
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let european = bs::greeks(option_type, sigma, t, s, k, 0.0, 0.0);
+            for model in [
+                bjerksund_stensland_greeks(option_type, sigma, t, s, k, 0.0, 0.0),
+                binomial_greeks(option_type, sigma, t, s, k, 0.0, 0.0),
+            ] {
+                // Rho is left out since a move either way from a zero rate
+                // makes one of the options worth exercising early
+                for (greek, (actual, expected)) in [
+                    ("delta", (model.delta, european.delta)),
+                    ("gamma", (model.gamma, european.gamma)),
+                    ("theta", (model.theta, european.theta)),
+                    ("vega", (model.vega, european.vega)),
+                    ("vanna", (model.vanna, european.vanna)),
+                    ("charm", (model.charm, european.charm)),
+                    ("speed", (model.speed, european.speed)),
+                    ("zomma", (model.zomma, european.zomma)),
+                    ("color", (model.color, european.color)),
+                    ("vomma", (model.vomma, european.vomma)),
+                    ("veta", (model.veta, european.veta)),
+                    ("ultima", (model.ultima, european.ultima)),
+                ] {
+                    assert!(
+                        ((actual - expected) / expected).abs() < 0.01,
+                        "{:?} {}: {} != {}",
+                        option_type,
+                        greek,
+                        actual,
+                        expected
+                    );
+                }
+            }
+            assert_close(
+                bjerksund_stensland_gamma(option_type, sigma, t, s, k, 0.0, 0.0),
+                european.gamma,
+                1e-4,
+            );
+        }
+
+        // Early exercise makes an American put less sensitive to the rate
+        let american =
+            bjerksund_stensland_greeks(OptionType::Put, 0.3, 1.0, 100.0, 110.0, 0.05, 0.0);
+        let european = bs::greeks(OptionType::Put, 0.3, 1.0, 100.0, 110.0, 0.05, 0.0);
+        assert!(american.rho < 0.0 && american.rho > european.rho);
+        assert!(american.delta < european.delta);
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Black-76 for options on futures. The underlying is the futures price
+//! `forward`, which carries no cost, so the model is Black-Scholes-Merton
+//! with the dividend yield equal to the risk-free rate. Rho is the exception
+//! since the forward doesn't move with the rate.
+
+use super::bs;
+use crate::types::{Greeks, OptionType};
+
+pub fn call_price(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::call_price(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn put_price(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::put_price(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn call_delta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::call_delta(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn put_delta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::put_delta(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn gamma(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::gamma(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+/// Per calendar day.
+pub fn call_theta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::call_theta(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+/// Per calendar day.
+pub fn put_theta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::put_theta(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+/// Per percentage point of volatility.
+pub fn vega(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::vega(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+/// Per percentage point of rate. Only the discounting depends on it.
+pub fn call_rho(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    -t * call_price(sigma, expiration_time, current_time, forward, strike, rate) / 100.0
+}
+
+/// Per percentage point of rate. Only the discounting depends on it.
+pub fn put_rho(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    -t * put_price(sigma, expiration_time, current_time, forward, strike, rate) / 100.0
+}
+
+pub fn vanna(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::vanna(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn call_charm(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::call_charm(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+pub fn put_charm(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> f64 {
+    bs::put_charm(
+        sigma,
+        expiration_time,
+        current_time,
+        forward,
+        strike,
+        rate,
+        rate,
+    )
+}
+
+/// Every greek of a futures option with `time_to_expiry` years left.
+pub fn greeks(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    forward: f64,
+    strike: f64,
+    rate: f64,
+) -> Greeks {
+    let rho = match option_type {
+        OptionType::Call => call_rho,
+        OptionType::Put => put_rho,
+    };
+
+    Greeks {
+        rho: rho(sigma, time_to_expiry, 0.0, forward, strike, rate),
+        ..bs::greeks(
+            option_type,
+            sigma,
+            time_to_expiry,
+            forward,
+            strike,
+            rate,
+            rate,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLOAT_ERROR: f64 = 0.0001;
+
+    #[test]
+    fn test_price() {
+        // Haug, The Complete Guide to Option Pricing Formulas, 1.1.2
+        assert_float_eq(1.7011, call_price(0.28, 0.75, 0.0, 19.0, 19.0, 0.1));
+        assert_float_eq(1.7011, put_price(0.28, 0.75, 0.0, 19.0, 19.0, 0.1));
+        // Hull, Example 18.6
+        assert_float_eq(1.1166, put_price(0.25, 4.0 / 12.0, 0.0, 20.0, 20.0, 0.09));
+    }
+
+    #[test]
+    fn test_greeks() {
+        assert_float_eq(0.5086, call_delta(0.28, 0.75, 0.0, 19.0, 19.0, 0.1));
+        assert_float_eq(
+            0.5086 - (-0.1f64 * 0.75).exp(),
+            put_delta(0.28, 0.75, 0.0, 19.0, 19.0, 0.1),
+        );
+        assert_float_eq(0.0797, gamma(0.28, 0.75, 0.0, 19.0, 19.0, 0.1));
+
+        let (sigma, t, f, k, r) = (0.3, 0.5, 4200.0, 4100.0, 0.04);
+        let h = 1e-5;
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let price = match option_type {
+                OptionType::Call => call_price,
+                OptionType::Put => put_price,
+            };
+            let greeks = greeks(option_type, sigma, t, f, k, r);
+            assert_float_eq(
+                greeks.rho,
+                (price(sigma, t, 0.0, f, k, r + h) - price(sigma, t, 0.0, f, k, r - h))
+                    / (2.0 * h)
+                    / 100.0,
+            );
+            assert_float_eq(
+                greeks.vega,
+                (price(sigma + h, t, 0.0, f, k, r) - price(sigma - h, t, 0.0, f, k, r))
+                    / (2.0 * h)
+                    / 100.0,
+            );
+        }
+    }
+
+    fn assert_float_eq(actual: f64, expected: f64) {
+        let diff = actual - expected;
+        assert!(diff.abs() < FLOAT_ERROR, "{} != {}", actual, expected);
+    }
+}
@@ -1,6 +1,11 @@
-use crate::math::{standard_normal_cdf, standard_normal_probability_density};
+//! Generalized Black-Scholes-Merton with a continuously compounded risk-free
+//! rate `rate` and a continuous dividend yield `dividend_yield`. Times are in
+//! years.
 
-const R: f64 = 0.0;
+use crate::{
+    math::{standard_normal_cdf, standard_normal_probability_density},
+    types::{Greeks, OptionType},
+};
 
 pub fn call_price(
     sigma: f64,
@@ -8,14 +13,15 @@ pub fn call_price(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    use std::f64::consts::E;
-
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
-    let d2 = d2(d1, sigma, expiration_time, current_time);
     let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
 
-    (standard_normal_cdf(d1) * current_price) - (standard_normal_cdf(d2) * strike * E.powf(-R * t))
+    (standard_normal_cdf(d1) * current_price * (-dividend_yield * t).exp())
+        - (standard_normal_cdf(d2) * strike * (-rate * t).exp())
 }
 
 pub fn put_price(
@@ -24,15 +30,15 @@ pub fn put_price(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    use std::f64::consts::E;
-
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
-    let d2 = d2(d1, sigma, expiration_time, current_time);
     let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
 
-    (standard_normal_cdf(-d2) * strike * E.powf(-R * t))
-        - (standard_normal_cdf(-d1) * current_price)
+    (standard_normal_cdf(-d2) * strike * (-rate * t).exp())
+        - (standard_normal_cdf(-d1) * current_price * (-dividend_yield * t).exp())
 }
 
 pub fn call_delta(
@@ -41,14 +47,12 @@ pub fn call_delta(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    standard_normal_cdf(d1(
-        sigma,
-        expiration_time,
-        current_time,
-        current_price,
-        strike,
-    ))
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    (-dividend_yield * t).exp() * standard_normal_cdf(d1)
 }
 
 pub fn put_delta(
@@ -57,14 +61,12 @@ pub fn put_delta(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    standard_normal_cdf(d1(
-        sigma,
-        expiration_time,
-        current_time,
-        current_price,
-        strike,
-    )) - 1.0
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    (-dividend_yield * t).exp() * (standard_normal_cdf(d1) - 1.0)
 }
 
 pub fn gamma(
@@ -73,42 +75,113 @@ pub fn gamma(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
     let t = expiration_time - current_time;
-    standard_normal_probability_density(d1) / (current_price * sigma * t.sqrt())
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    (-dividend_yield * t).exp() * standard_normal_probability_density(d1)
+        / (current_price * sigma * t.sqrt())
 }
 
-pub fn theta(
+/// Per calendar day.
+pub fn call_theta(
     sigma: f64,
     expiration_time: f64,
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    use std::f64::consts::E;
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let carry = (-dividend_yield * t).exp();
 
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
-    let d2 = d2(d1, sigma, expiration_time, current_time);
+    let a = (current_price * carry * standard_normal_probability_density(d1) * sigma)
+        / (2.0 * t.sqrt());
+    let b = rate * strike * (-rate * t).exp() * standard_normal_cdf(d2);
+    let c = dividend_yield * current_price * carry * standard_normal_cdf(d1);
+
+    (-a - b + c) / 365.0
+}
+
+/// Per calendar day.
+pub fn put_theta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
     let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let carry = (-dividend_yield * t).exp();
 
-    let a = (current_price * standard_normal_probability_density(d1) * sigma) / (2.0 * t.sqrt());
-    let b = R * strike * E.powf(-R * t) * standard_normal_cdf(d2);
+    let a = (current_price * carry * standard_normal_probability_density(d1) * sigma)
+        / (2.0 * t.sqrt());
+    let b = rate * strike * (-rate * t).exp() * standard_normal_cdf(-d2);
+    let c = dividend_yield * current_price * carry * standard_normal_cdf(-d1);
 
-    (-a - b) / 365.0
+    (-a + b - c) / 365.0
 }
 
+/// Per percentage point of volatility.
 pub fn vega(
     sigma: f64,
     expiration_time: f64,
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+
+    (current_price
+        * (-dividend_yield * t).exp()
+        * standard_normal_probability_density(d1)
+        * t.sqrt())
+        / 100.0
+}
+
+/// Per percentage point of rate.
+pub fn call_rho(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+
+    strike * t * (-rate * t).exp() * standard_normal_cdf(d2) / 100.0
+}
+
+/// Per percentage point of rate.
+pub fn put_rho(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
     let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
 
-    (current_price * standard_normal_probability_density(d1) * t.sqrt()) / 100.0
+    -strike * t * (-rate * t).exp() * standard_normal_cdf(-d2) / 100.0
 }
 
 pub fn vanna(
@@ -117,35 +190,266 @@ pub fn vanna(
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
-    let d2 = d2(d1, sigma, expiration_time, current_time);
-    (-1.0 * standard_normal_probability_density(d1) * (d2 / sigma)) / 100.0
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    (-(-dividend_yield * t).exp() * standard_normal_probability_density(d1) * (d2 / sigma)) / 100.0
 }
 
-pub fn charm(
+pub fn call_charm(
     sigma: f64,
     expiration_time: f64,
     current_time: f64,
     current_price: f64,
     strike: f64,
+    rate: f64,
+    dividend_yield: f64,
 ) -> f64 {
     let t = expiration_time - current_time;
-    let d1 = d1(sigma, expiration_time, current_time, current_price, strike);
-    let d2 = d2(d1, sigma, expiration_time, current_time);
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let carry = (-dividend_yield * t).exp();
 
-    let numerator = (2.0 * R * t) - (d2 * sigma * t.sqrt());
-    let denominator = 2.0 * t * sigma * t.sqrt();
-    -standard_normal_probability_density(d1) * (numerator / denominator)
+    dividend_yield * carry * standard_normal_cdf(d1)
+        + charm_decay(sigma, t, d1, rate, dividend_yield)
+}
+
+pub fn put_charm(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let carry = (-dividend_yield * t).exp();
+
+    -dividend_yield * carry * standard_normal_cdf(-d1)
+        + charm_decay(sigma, t, d1, rate, dividend_yield)
 }
 
-fn d1(sigma: f64, expiration_time: f64, current_time: f64, current_price: f64, strike: f64) -> f64 {
+/// How gamma changes with the underlying price. The same for calls and puts.
+pub fn speed(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
     let t = expiration_time - current_time;
-    ((current_price / strike).ln() + (R + (sigma.powi(2) / 2.0)) * t) / (sigma * t.sqrt())
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let gamma = gamma(
+        sigma,
+        expiration_time,
+        current_time,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+
+    -gamma / current_price * (d1 / (sigma * t.sqrt()) + 1.0)
 }
 
-fn d2(d1: f64, sigma: f64, expiration_time: f64, current_time: f64) -> f64 {
+/// How gamma changes per percentage point of volatility.
+pub fn zomma(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
     let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let gamma = gamma(
+        sigma,
+        expiration_time,
+        current_time,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+
+    gamma * (d1 * d2 - 1.0) / sigma / 100.0
+}
+
+/// How gamma changes per calendar day.
+pub fn color(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let vol = sigma * t.sqrt();
+    let decay =
+        2.0 * dividend_yield * t + 1.0 + (2.0 * (rate - dividend_yield) * t - d2 * vol) / vol * d1;
+
+    (-dividend_yield * t).exp() * standard_normal_probability_density(d1)
+        / (2.0 * current_price * t * vol)
+        * decay
+        / 365.0
+}
+
+/// How vega changes per percentage point of volatility, with vega itself per
+/// percentage point.
+pub fn vomma(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let vega = vega(
+        sigma,
+        expiration_time,
+        current_time,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+
+    vega * d1 * d2 / sigma / 100.0
+}
+
+/// How vega changes per calendar day, with vega per percentage point.
+pub fn veta(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let vega = vega(
+        sigma,
+        expiration_time,
+        current_time,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+
+    vega * (dividend_yield + (rate - dividend_yield) * d1 / (sigma * t.sqrt())
+        - (1.0 + d1 * d2) / (2.0 * t))
+        / 365.0
+}
+
+/// How vomma changes per percentage point of volatility.
+pub fn ultima(
+    sigma: f64,
+    expiration_time: f64,
+    current_time: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let t = expiration_time - current_time;
+    let d1 = d1(sigma, t, current_price, strike, rate, dividend_yield);
+    let d2 = d2(d1, sigma, t);
+    let vega = vega(
+        sigma,
+        expiration_time,
+        current_time,
+        current_price,
+        strike,
+        rate,
+        dividend_yield,
+    );
+    let d1d2 = d1 * d2;
+
+    -vega / sigma.powi(2) * (d1d2 * (1.0 - d1d2) + d1.powi(2) + d2.powi(2)) / 10_000.0
+}
+
+/// The part of charm shared by calls and puts.
+fn charm_decay(sigma: f64, t: f64, d1: f64, rate: f64, dividend_yield: f64) -> f64 {
+    let d2 = d2(d1, sigma, t);
+    let numerator = (2.0 * (rate - dividend_yield) * t) - (d2 * sigma * t.sqrt());
+    let denominator = 2.0 * t * sigma * t.sqrt();
+    -(-dividend_yield * t).exp()
+        * standard_normal_probability_density(d1)
+        * (numerator / denominator)
+}
+
+/// Every greek of an option with `time_to_expiry` years left.
+pub fn greeks(
+    option_type: OptionType,
+    sigma: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> Greeks {
+    let (delta, theta, rho, charm): (Greek, Greek, Greek, Greek) = match option_type {
+        OptionType::Call => (call_delta, call_theta, call_rho, call_charm),
+        OptionType::Put => (put_delta, put_theta, put_rho, put_charm),
+    };
+    let greek = |f: Greek| {
+        f(
+            sigma,
+            time_to_expiry,
+            0.0,
+            current_price,
+            strike,
+            rate,
+            dividend_yield,
+        )
+    };
+
+    Greeks {
+        delta: greek(delta),
+        gamma: greek(gamma),
+        theta: greek(theta),
+        vega: greek(vega),
+        rho: greek(rho),
+        vanna: greek(vanna),
+        charm: greek(charm),
+        speed: greek(speed),
+        zomma: greek(zomma),
+        color: greek(color),
+        vomma: greek(vomma),
+        veta: greek(veta),
+        ultima: greek(ultima),
+    }
+}
+
+type Greek = fn(f64, f64, f64, f64, f64, f64, f64) -> f64;
+
+fn d1(sigma: f64, t: f64, current_price: f64, strike: f64, rate: f64, dividend_yield: f64) -> f64 {
+    ((current_price / strike).ln() + (rate - dividend_yield + (sigma.powi(2) / 2.0)) * t)
+        / (sigma * t.sqrt())
+}
+
+fn d2(d1: f64, sigma: f64, t: f64) -> f64 {
     d1 - (sigma * t.sqrt())
 }
 
@@ -158,55 +462,191 @@ mod tests {
 
     #[test]
     fn test_call_price() {
-        assert_float_eq(1.8824, call_price(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(
+            1.8824,
+            call_price(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
     }
 
     #[test]
     fn test_put_price() {
-        assert_float_eq(0.8824, put_price(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(0.8824, put_price(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
     }
 
     #[test]
     fn test_call_delta() {
-        assert_float_eq(0.6828, call_delta(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(
+            0.6828,
+            call_delta(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
     }
 
     #[test]
     fn test_put_delta() {
-        assert_float_eq(-0.3172, put_delta(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(
+            -0.3172,
+            put_delta(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
     }
 
     #[test]
     fn test_gamma() {
-        assert_float_eq(0.1015, gamma(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(0.1015, gamma(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
     }
 
     #[test]
-    fn test_theta() {
-        assert_float_eq(-0.0035, theta(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+    fn test_call_theta() {
+        assert_float_eq(
+            -0.0035,
+            call_theta(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
     }
 
     #[test]
     fn test_vega() {
-        assert_float_eq(0.0250, vega(0.5, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(0.0250, vega(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
     }
 
     #[test]
     fn test_vanna() {
-        assert_float_eq(-0.01798, vanna(0.1, EXPIRATION, 0.0, 10.0, 9.0));
-        assert_float_eq(-0.0008871, vanna(0.5, EXPIRATION, 0.0, 10.0, 9.0));
-        assert_float_eq(0.0007075, vanna(1.0, EXPIRATION, 0.0, 10.0, 9.0));
+        assert_float_eq(-0.01798, vanna(0.1, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
+        assert_float_eq(-0.0008871, vanna(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
+        assert_float_eq(0.0007075, vanna(1.0, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
     }
 
     #[test]
-    fn test_charm() {
-        assert_float_eq(0.1823, charm(0.1, EXPIRATION, 0.0, 10.0, 9.0));
-        assert_float_eq(0.0449, charm(0.5, EXPIRATION, 0.0, 10.0, 9.0));
-        assert_float_eq(-0.0717, charm(1.0, EXPIRATION, 0.0, 10.0, 9.0));
+    fn test_call_charm() {
+        assert_float_eq(
+            0.1823,
+            call_charm(0.1, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
+        assert_float_eq(
+            0.0449,
+            call_charm(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
+        assert_float_eq(
+            -0.0717,
+            call_charm(1.0, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn test_higher_order_greeks() {
+        // Scaled back to unit changes of volatility and years
+        assert_float_eq(-0.023891, speed(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0));
+        assert_float_eq(
+            -0.190917,
+            zomma(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0) * 100.0,
+        );
+        assert_float_eq(
+            0.096785,
+            color(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0) * 365.0,
+        );
+        assert_float_eq(
+            0.296320,
+            vomma(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0) * 10_000.0,
+        );
+        assert_float_eq(
+            -2.686904,
+            veta(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0) * 36_500.0,
+        );
+        assert_float_eq(
+            -2.976659,
+            ultima(0.5, EXPIRATION, 0.0, 10.0, 9.0, 0.0, 0.0) * 1_000_000.0,
+        );
+    }
+
+    #[test]
+    fn test_rate_and_dividend_yield() {
+        // Hull, Example 17.1: S = 42, K = 40, r = 10%, sigma = 20%, six months
+        assert_float_eq(4.7594, call_price(0.2, 0.5, 0.0, 42.0, 40.0, 0.1, 0.0));
+        assert_float_eq(0.8086, put_price(0.2, 0.5, 0.0, 42.0, 40.0, 0.1, 0.0));
+
+        // Put-call parity with a dividend yield
+        let (sigma, t, s, k, r, q) = (0.3, 0.75, 100.0, 95.0, 0.05, 0.02);
+        let call = call_price(sigma, t, 0.0, s, k, r, q);
+        let put = put_price(sigma, t, 0.0, s, k, r, q);
+        assert_float_eq(call - put, s * (-q * t).exp() - k * (-r * t).exp());
+
+        let call_delta = call_delta(sigma, t, 0.0, s, k, r, q);
+        let put_delta = put_delta(sigma, t, 0.0, s, k, r, q);
+        assert_float_eq(call_delta - put_delta, (-q * t).exp());
+    }
+
+    #[test]
+    fn test_greeks_match_finite_differences() {
+        let (sigma, t, s, k, r, q) = (0.25, 0.4, 100.0, 105.0, 0.04, 0.015);
+        let h = 1e-4;
+
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let price = match option_type {
+                OptionType::Call => call_price,
+                OptionType::Put => put_price,
+            };
+            let delta = |s: f64, t: f64| {
+                (price(sigma, t, 0.0, s + h, k, r, q) - price(sigma, t, 0.0, s - h, k, r, q))
+                    / (2.0 * h)
+            };
+            let greeks = greeks(option_type, sigma, t, s, k, r, q);
+
+            assert_float_eq(greeks.delta, delta(s, t));
+            assert_float_eq(
+                greeks.theta,
+                -(price(sigma, t + h, 0.0, s, k, r, q) - price(sigma, t - h, 0.0, s, k, r, q))
+                    / (2.0 * h)
+                    / 365.0,
+            );
+            assert_float_eq(
+                greeks.rho,
+                (price(sigma, t, 0.0, s, k, r + h, q) - price(sigma, t, 0.0, s, k, r - h, q))
+                    / (2.0 * h)
+                    / 100.0,
+            );
+            assert_float_eq(
+                greeks.charm,
+                -(delta(s, t + h) - delta(s, t - h)) / (2.0 * h),
+            );
+        }
+
+        // The higher-order greeks are the same for calls and puts
+        let gamma = |sigma: f64, t: f64, s: f64| super::gamma(sigma, t, 0.0, s, k, r, q);
+        let vega = |sigma: f64, t: f64| super::vega(sigma, t, 0.0, s, k, r, q);
+        let vomma = |sigma: f64| super::vomma(sigma, t, 0.0, s, k, r, q);
+        let greeks = greeks(OptionType::Put, sigma, t, s, k, r, q);
+        assert_relative_eq(
+            greeks.speed,
+            (gamma(sigma, t, s + h) - gamma(sigma, t, s - h)) / (2.0 * h),
+        );
+        assert_relative_eq(
+            greeks.zomma,
+            (gamma(sigma + h, t, s) - gamma(sigma - h, t, s)) / (2.0 * h) / 100.0,
+        );
+        assert_relative_eq(
+            greeks.color,
+            -(gamma(sigma, t + h, s) - gamma(sigma, t - h, s)) / (2.0 * h) / 365.0,
+        );
+        assert_relative_eq(
+            greeks.vomma,
+            (vega(sigma + h, t) - vega(sigma - h, t)) / (2.0 * h) / 100.0,
+        );
+        assert_relative_eq(
+            greeks.veta,
+            -(vega(sigma, t + h) - vega(sigma, t - h)) / (2.0 * h) / 365.0,
+        );
+        assert_relative_eq(
+            greeks.ultima,
+            (vomma(sigma + h) - vomma(sigma - h)) / (2.0 * h) / 100.0,
+        );
     }
 
     fn assert_float_eq(actual: f64, expected: f64) {
         let diff = actual - expected;
         assert!(diff.abs() < FLOAT_ERROR, "{} != {}", actual, expected);
     }
+
+    /// For greeks too small to compare to a fixed number of places.
+    fn assert_relative_eq(actual: f64, expected: f64) {
+        let diff = (actual - expected) / expected;
+        assert!(diff.abs() < 1e-5, "{} != {}", actual, expected);
+    }
 }
@@ -0,0 +1,285 @@
+//! Time to expiry as a fraction of a year, which is what the pricing models
+//! expect. Options settle at 16:00 New York time on their expiration date,
+//! or at the open for AM-settled index options, and the remaining time is
+//! measured to the second so same-day expirations still get sensible greeks.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::America::New_York;
+
+use crate::types::SettlementType;
+
+const DAY_COUNT_ENV: &str = "DAY_COUNT";
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const TRADING_SECONDS_PER_DAY: f64 = 6.5 * 60.0 * 60.0;
+/// Roots whose standard contracts settle on the opening print. Their weekly
+/// counterparts (`SPXW`, `NDXP`, `RUTW`) settle at the close.
+const AM_SETTLED_ROOTS: &[&str] = &["SPX", "NDX", "RUT", "VIX", "DJX"];
+
+/// How the time until expiry is counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DayCount {
+    /// Every minute counts, over a 365 day year
+    #[default]
+    Calendar,
+    /// Only regular trading hours count, over a 252 day year, plus the
+    /// calendar time from the last close to an AM settlement. Holidays
+    /// aren't known, so they're counted as trading days.
+    Trading,
+}
+
+impl FromStr for DayCount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "calendar" => DayCount::Calendar,
+            "trading" => DayCount::Trading,
+            _ => anyhow::bail!("Invalid day count: {}", s),
+        })
+    }
+}
+
+impl DayCount {
+    /// Reads `DAY_COUNT`, defaulting to calendar time.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var(DAY_COUNT_ENV) {
+            Ok(day_count) => day_count.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+fn market_open() -> NaiveTime {
+    NaiveTime::from_hms_opt(9, 30, 0).unwrap_or_default()
+}
+
+fn market_close() -> NaiveTime {
+    NaiveTime::from_hms_opt(16, 0, 0).unwrap_or_default()
+}
+
+fn new_york(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    New_York
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&date.and_time(time)))
+}
+
+/// The settlement of an option on `root`, using `settlement` when the
+/// provider reports it.
+pub fn settlement_type(root: &str, settlement: Option<SettlementType>) -> SettlementType {
+    settlement.unwrap_or_else(|| {
+        if AM_SETTLED_ROOTS.contains(&root.to_uppercase().as_ref()) {
+            SettlementType::AM
+        } else {
+            SettlementType::PM
+        }
+    })
+}
+
+/// When an option expiring on `date` stops trading.
+pub fn settlement_time(date: NaiveDate, settlement: SettlementType) -> DateTime<Utc> {
+    match settlement {
+        SettlementType::AM => new_york(date, market_open()),
+        SettlementType::PM => new_york(date, market_close()),
+    }
+}
+
+pub fn parse_expiration_date(date: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("Invalid expiration date: {}", date))
+}
+
+/// Years from `now` until `expiration`, zero once it has passed.
+pub fn year_fraction(now: DateTime<Utc>, expiration: DateTime<Utc>, day_count: DayCount) -> f64 {
+    if expiration <= now {
+        return 0.0;
+    }
+
+    match day_count {
+        DayCount::Calendar => seconds(now, expiration) / SECONDS_PER_YEAR,
+        DayCount::Trading => {
+            // An expiry outside trading hours, like an AM settlement, still
+            // prices in the time since the last close, counted as calendar
+            // time so contracts don't run out of time overnight
+            let session_end = last_session_end(expiration).max(now);
+            trading_seconds(now, session_end) / (TRADING_DAYS_PER_YEAR * TRADING_SECONDS_PER_DAY)
+                + seconds(session_end, expiration) / SECONDS_PER_YEAR
+        }
+    }
+}
+
+/// Years from `now` until an option on `root` expiring on `expiration_date`
+/// settles.
+pub fn time_to_expiry(
+    root: &str,
+    expiration_date: &str,
+    settlement: Option<SettlementType>,
+    now: DateTime<Utc>,
+    day_count: DayCount,
+) -> anyhow::Result<f64> {
+    let date = parse_expiration_date(expiration_date)?;
+    let expiration = settlement_time(date, settlement_type(root, settlement));
+    Ok(year_fraction(now, expiration, day_count))
+}
+
+fn seconds(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+/// `time` if it falls within regular trading hours, otherwise the last close
+/// before it.
+fn last_session_end(time: DateTime<Utc>) -> DateTime<Utc> {
+    let mut date = time.with_timezone(&New_York).date_naive();
+
+    // A week back always reaches a weekday
+    for _ in 0..7 {
+        if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            let close = new_york(date, market_close());
+            if new_york(date, market_open()) < time && time <= close {
+                return time;
+            }
+            if close < time {
+                return close;
+            }
+        }
+        date = match date.pred_opt() {
+            Some(previous) => previous,
+            None => break,
+        };
+    }
+
+    time
+}
+
+/// Seconds of regular trading hours between `from` and `to`.
+fn trading_seconds(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    let last = to.with_timezone(&New_York).date_naive();
+    let mut date = from.with_timezone(&New_York).date_naive();
+    let mut total = 0.0;
+
+    while date <= last {
+        if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            let open = new_york(date, market_open()).max(from);
+            let close = new_york(date, market_close()).min(to);
+            if close > open {
+                total += seconds(open, close);
+            }
+        }
+        date = match date.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::at;
+
+    #[test]
+    fn settlement() {
+        assert_eq!(settlement_type("SPX", None), SettlementType::AM);
+        assert_eq!(settlement_type("spxw", None), SettlementType::PM);
+        assert_eq!(
+            settlement_type("SPX", Some(SettlementType::PM)),
+            SettlementType::PM
+        );
+
+        let date = NaiveDate::from_ymd_opt(2021, 6, 18).unwrap();
+        assert_eq!(
+            settlement_time(date, SettlementType::PM),
+            at("2021-06-18T20:00:00Z")
+        );
+        // Standard time in December
+        let date = NaiveDate::from_ymd_opt(2021, 12, 17).unwrap();
+        assert_eq!(
+            settlement_time(date, SettlementType::AM),
+            at("2021-12-17T14:30:00Z")
+        );
+    }
+
+    #[test]
+    fn calendar_days() {
+        let t = time_to_expiry(
+            "SPY",
+            "2021-06-18",
+            None,
+            at("2021-06-17T20:00:00Z"),
+            DayCount::Calendar,
+        )
+        .unwrap();
+        assert!((t - 1.0 / 365.0).abs() < 1e-12);
+
+        // Same day expiration, half an hour before the close
+        let t = time_to_expiry(
+            "SPY",
+            "2021-06-18",
+            None,
+            at("2021-06-18T19:30:00Z"),
+            DayCount::Calendar,
+        )
+        .unwrap();
+        assert!((t - 30.0 / (365.0 * 24.0 * 60.0)).abs() < 1e-12);
+
+        let expired = time_to_expiry(
+            "SPX",
+            "2021-06-18",
+            None,
+            at("2021-06-18T15:00:00Z"),
+            DayCount::Calendar,
+        )
+        .unwrap();
+        assert_eq!(expired, 0.0);
+        assert!(time_to_expiry("SPY", "tomorrow", None, Utc::now(), DayCount::Calendar).is_err());
+    }
+
+    #[test]
+    fn trading_days() {
+        // Friday close to the next Friday close is five sessions
+        let t = year_fraction(
+            at("2021-06-11T20:00:00Z"),
+            at("2021-06-18T20:00:00Z"),
+            DayCount::Trading,
+        );
+        assert!((t - 5.0 / 252.0).abs() < 1e-12);
+
+        // Overnight and weekends don't count
+        let t = year_fraction(
+            at("2021-06-11T19:00:00Z"),
+            at("2021-06-14T14:30:00Z"),
+            DayCount::Trading,
+        );
+        assert!((t - 2.0 / (252.0 * 6.5)).abs() < 1e-12);
+
+        // Thursday evening to an AM settlement on Friday
+        let t = time_to_expiry(
+            "SPX",
+            "2021-06-18",
+            None,
+            at("2021-06-17T21:00:00Z"),
+            DayCount::Trading,
+        )
+        .unwrap();
+        assert!((t - 16.5 / (365.0 * 24.0)).abs() < 1e-12);
+
+        let t = time_to_expiry(
+            "SPX",
+            "2021-06-18",
+            None,
+            at("2021-06-17T19:00:00Z"),
+            DayCount::Trading,
+        )
+        .unwrap();
+        assert!((t - 1.0 / (252.0 * 6.5) - 17.5 / (365.0 * 24.0)).abs() < 1e-12);
+
+        assert_eq!("Trading".parse::<DayCount>().unwrap(), DayCount::Trading);
+        assert!("weekly".parse::<DayCount>().is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Implied volatility from option prices under Black-Scholes-Merton. Newton's
+//! method converges in a few steps near the money, but vega vanishes deep in
+//! or out of the money, so every step is kept inside a bracket around the
+//! root and falls back to bisection whenever Newton would leave it.
+
+use super::bs;
+use crate::types::OptionType;
+
+const MIN_VOLATILITY: f64 = 1e-4;
+const MAX_VOLATILITY: f64 = 10.0;
+const PRICE_TOLERANCE: f64 = 1e-10;
+const VOLATILITY_TOLERANCE: f64 = 1e-10;
+const MAX_ITERATIONS: usize = 100;
+
+/// Implied volatilities of an option's bid, mid and ask prices.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuoteVolatility {
+    pub bid: Option<f64>,
+    pub mid: Option<f64>,
+    pub ask: Option<f64>,
+}
+
+/// The volatility at which the model price of the option is `price`, or
+/// `None` when no volatility gives that price, such as below intrinsic value.
+pub fn implied_volatility(
+    option_type: OptionType,
+    price: f64,
+    time_to_expiry: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> Option<f64> {
+    let t = time_to_expiry;
+    if !(price.is_finite() && price > 0.0 && t > 0.0 && current_price > 0.0 && strike > 0.0) {
+        return None;
+    }
+
+    let model_price = |sigma: f64| match option_type {
+        OptionType::Call => {
+            bs::call_price(sigma, t, 0.0, current_price, strike, rate, dividend_yield)
+        }
+        OptionType::Put => {
+            bs::put_price(sigma, t, 0.0, current_price, strike, rate, dividend_yield)
+        }
+    };
+
+    let (mut low, mut high) = (MIN_VOLATILITY, MAX_VOLATILITY);
+    if price < model_price(low) || price > model_price(high) {
+        return None;
+    }
+
+    let mut sigma =
+        initial_guess(price, t, current_price, strike, rate, dividend_yield).clamp(low, high);
+
+    for _ in 0..MAX_ITERATIONS {
+        let error = model_price(sigma) - price;
+        if error.abs() < PRICE_TOLERANCE {
+            return Some(sigma);
+        }
+
+        // Prices increase with volatility, so the root is on the other side
+        if error > 0.0 {
+            high = sigma;
+        } else {
+            low = sigma;
+        }
+        if high - low < VOLATILITY_TOLERANCE {
+            return Some(sigma);
+        }
+
+        let vega = bs::vega(sigma, t, 0.0, current_price, strike, rate, dividend_yield) * 100.0;
+        let newton = sigma - error / vega;
+        sigma = if vega > f64::EPSILON && newton > low && newton < high {
+            newton
+        } else {
+            (low + high) / 2.0
+        };
+    }
+
+    Some(sigma)
+}
+
+/// Combines the Manaster-Koehler guess, which is close away from the money,
+/// with the Brenner-Subrahmanyam one, which is close at the money.
+fn initial_guess(
+    price: f64,
+    t: f64,
+    current_price: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> f64 {
+    let moneyness = (current_price / strike).ln() + (rate - dividend_yield) * t;
+    let away = (2.0 * moneyness.abs() / t).sqrt();
+    let at = (2.0 * std::f64::consts::PI / t).sqrt() * price / current_price;
+    away.max(at)
+}
+
+/// Implied volatilities of the bid, ask and their midpoint, with `solve`
+/// giving the volatility of a price. A zero or missing bid has no
+/// volatility, and the midpoint is then taken from zero to the ask. Without
+/// an ask the last trade stands in for the midpoint.
+pub fn quote_volatility(
+    bid: Option<f64>,
+    ask: Option<f64>,
+    last: Option<f64>,
+    solve: impl Fn(f64) -> Option<f64>,
+) -> QuoteVolatility {
+    let bid = bid.filter(|b| b.is_finite() && *b > 0.0);
+    let ask = ask.filter(|a| a.is_finite() && *a > 0.0);
+    let mid = match ask {
+        Some(ask) => Some((bid.unwrap_or(0.0) + ask) / 2.0),
+        None => last,
+    };
+
+    QuoteVolatility {
+        bid: bid.and_then(&solve),
+        mid: mid.and_then(&solve),
+        ask: ask.and_then(&solve),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let (t, s, r, q) = (0.25, 100.0, 0.03, 0.01);
+        for option_type in [OptionType::Call, OptionType::Put] {
+            for strike in [40.0, 80.0, 95.0, 100.0, 105.0, 130.0, 200.0] {
+                for sigma in [0.05, 0.2, 0.6, 1.5, 4.0] {
+                    let price = match option_type {
+                        OptionType::Call => bs::call_price(sigma, t, 0.0, s, strike, r, q),
+                        OptionType::Put => bs::put_price(sigma, t, 0.0, s, strike, r, q),
+                    };
+                    // Prices this small can't be told apart from intrinsic value
+                    let intrinsic = match option_type {
+                        OptionType::Call => s * (-q * t).exp() - strike * (-r * t).exp(),
+                        OptionType::Put => strike * (-r * t).exp() - s * (-q * t).exp(),
+                    };
+                    if price - intrinsic.max(0.0) < 1e-6 {
+                        continue;
+                    }
+
+                    let solved = implied_volatility(option_type, price, t, s, strike, r, q)
+                        .unwrap_or_else(|| panic!("{:?} {} {}", option_type, strike, sigma));
+                    assert!(
+                        (solved - sigma).abs() < 1e-6,
+                        "{:?} {} {}: {}",
+                        option_type,
+                        strike,
+                        sigma,
+                        solved
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_solution() {
+        // Below intrinsic value
+        assert_eq!(
+            implied_volatility(OptionType::Call, 9.0, 0.5, 110.0, 100.0, 0.0, 0.0),
+            None
+        );
+        // Above the price of the underlying
+        assert_eq!(
+            implied_volatility(OptionType::Call, 120.0, 0.5, 110.0, 100.0, 0.0, 0.0),
+            None
+        );
+        assert_eq!(
+            implied_volatility(OptionType::Put, 0.0, 0.5, 110.0, 100.0, 0.0, 0.0),
+            None
+        );
+        assert_eq!(
+            implied_volatility(OptionType::Put, 1.0, 0.0, 110.0, 100.0, 0.0, 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        let t = 180.0 / 365.0;
+        let call = |price| implied_volatility(OptionType::Call, price, t, 10.0, 9.0, 0.0, 0.0);
+        let ivs = quote_volatility(Some(1.85), Some(1.9148), None, call);
+        assert!(ivs.bid.unwrap() < ivs.mid.unwrap());
+        assert!(ivs.mid.unwrap() < ivs.ask.unwrap());
+        assert!((ivs.mid.unwrap() - 0.5).abs() < 1e-3);
+
+        // A zero bid has no volatility, the midpoint is half the ask
+        let put = |price| implied_volatility(OptionType::Put, price, t, 10.0, 6.0, 0.0, 0.0);
+        let ivs = quote_volatility(Some(0.0), Some(0.1), None, put);
+        assert_eq!(ivs.bid, None);
+        let mid = bs::put_price(ivs.mid.unwrap(), t, 0.0, 10.0, 6.0, 0.0, 0.0);
+        assert!((mid - 0.05).abs() < 1e-8);
+    }
+}
@@ -0,0 +1,479 @@
+//! The market inputs the pricing models need besides the option itself, and
+//! which model prices each underlying.
+
+use std::{collections::HashMap, str::FromStr};
+
+use super::{
+    american,
+    batch::Contract,
+    black76, bs,
+    expiry::DayCount,
+    iv::{self, QuoteVolatility},
+    rates::RateCurve,
+};
+use crate::types::{Greeks, OptionInfo, UnderlyingType};
+
+const RISK_FREE_RATE_ENV: &str = "RISK_FREE_RATE";
+const RATE_CURVE_PATH_ENV: &str = "RATE_CURVE_PATH";
+const DIVIDEND_YIELDS_ENV: &str = "DIVIDEND_YIELDS";
+const PRICING_MODEL_ENV: &str = "PRICING_MODEL";
+const PRICING_MODELS_ENV: &str = "PRICING_MODELS";
+
+/// How options on equities and indices are priced. Futures options always
+/// use Black-76.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PricingModel {
+    /// European exercise
+    #[default]
+    BlackScholes,
+    /// American exercise on a Cox-Ross-Rubinstein tree
+    Binomial,
+    /// American exercise with the Bjerksund-Stensland (2002) approximation,
+    /// quicker than the tree and slightly below it
+    BjerksundStensland,
+}
+
+impl FromStr for PricingModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_ref() {
+            "black-scholes" | "blackscholes" | "bs" | "european" => PricingModel::BlackScholes,
+            "binomial" | "crr" => PricingModel::Binomial,
+            "bjerksund-stensland" | "bjerksundstensland" | "bs2002" => {
+                PricingModel::BjerksundStensland
+            }
+            _ => anyhow::bail!("Invalid pricing model: {}", s),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PricingConfig {
+    /// How time to expiry is counted
+    pub day_count: DayCount,
+    pub rates: RateCurve,
+    /// Continuous dividend yields by underlying symbol, zero when missing
+    pub dividend_yields: HashMap<String, f64>,
+    /// The model for underlyings without one of their own
+    pub model: PricingModel,
+    /// Models by underlying symbol
+    pub models: HashMap<String, PricingModel>,
+}
+
+impl PricingConfig {
+    /// Reads `DAY_COUNT`, the rate curve from the file at `RATE_CURVE_PATH`
+    /// or else a flat `RISK_FREE_RATE`, and `DIVIDEND_YIELDS` as a list such
+    /// as `SPY=0.013,QQQ=0.006`. Rates and yields are decimals. The default
+    /// model is read from `PRICING_MODEL` and models by symbol from
+    /// `PRICING_MODELS`, like `AAPL=binomial,IWM=bs2002`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let rates = match std::env::var(RATE_CURVE_PATH_ENV) {
+            Ok(path) => RateCurve::from_file(path)?,
+            Err(_) => match std::env::var(RISK_FREE_RATE_ENV) {
+                Ok(rate) => RateCurve::Flat(
+                    rate.trim()
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid risk-free rate: {}", rate))?,
+                ),
+                Err(_) => RateCurve::default(),
+            },
+        };
+        let dividend_yields = match std::env::var(DIVIDEND_YIELDS_ENV) {
+            Ok(yields) => parse_by_symbol(&yields, "dividend yield")?,
+            Err(_) => HashMap::new(),
+        };
+        let model = match std::env::var(PRICING_MODEL_ENV) {
+            Ok(model) => model.parse()?,
+            Err(_) => PricingModel::default(),
+        };
+        let models = match std::env::var(PRICING_MODELS_ENV) {
+            Ok(models) => parse_by_symbol(&models, "pricing model")?,
+            Err(_) => HashMap::new(),
+        };
+
+        Ok(Self {
+            day_count: DayCount::from_env()?,
+            rates,
+            dividend_yields,
+            model,
+            models,
+        })
+    }
+
+    /// The risk-free rate for an option with `time_to_expiry` years left.
+    pub fn rate(&self, time_to_expiry: f64) -> f64 {
+        self.rates.rate(time_to_expiry)
+    }
+
+    pub fn dividend_yield(&self, symbol: &str) -> f64 {
+        self.dividend_yields
+            .get(&symbol.to_uppercase())
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The model for equity options on `symbol`. Index options settle
+    /// European and futures options are priced with Black-76 whatever it is.
+    pub fn model(&self, symbol: &str) -> PricingModel {
+        self.models
+            .get(&symbol.to_uppercase())
+            .copied()
+            .unwrap_or(self.model)
+    }
+
+    /// The model `option` on `underlying` is priced with when it isn't a
+    /// futures option.
+    fn spot_model(&self, underlying: &str, option: &OptionInfo) -> PricingModel {
+        match option.underlying() {
+            UnderlyingType::Equity => self.model(underlying),
+            UnderlyingType::Index | UnderlyingType::Future => PricingModel::BlackScholes,
+        }
+    }
+
+    /// The forward price of the underlying of `option` at its expiry. A
+    /// future is its own forward.
+    pub fn forward(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        time_to_expiry: f64,
+        underlying_price: f64,
+    ) -> f64 {
+        match option.underlying() {
+            UnderlyingType::Future => underlying_price,
+            UnderlyingType::Equity | UnderlyingType::Index => {
+                let carry = self.rate(time_to_expiry) - self.dividend_yield(underlying);
+                underlying_price * (carry * time_to_expiry).exp()
+            }
+        }
+    }
+
+    /// Greeks of `option` with `time_to_expiry` years left when `underlying`
+    /// trades at `underlying_price`. Futures options are priced with
+    /// Black-76, index options with Black-Scholes-Merton and equity options
+    /// with the [`PricingModel`] of `underlying`.
+    pub fn greeks(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        sigma: f64,
+        time_to_expiry: f64,
+        underlying_price: f64,
+    ) -> Greeks {
+        let rate = self.rate(time_to_expiry);
+        match option.underlying() {
+            UnderlyingType::Future => black76::greeks(
+                option.option_type,
+                sigma,
+                time_to_expiry,
+                underlying_price,
+                option.strike,
+                rate,
+            ),
+            UnderlyingType::Equity | UnderlyingType::Index => {
+                let greeks = match self.spot_model(underlying, option) {
+                    PricingModel::BlackScholes => bs::greeks,
+                    PricingModel::Binomial => american::binomial_greeks,
+                    PricingModel::BjerksundStensland => american::bjerksund_stensland_greeks,
+                };
+                greeks(
+                    option.option_type,
+                    sigma,
+                    time_to_expiry,
+                    underlying_price,
+                    option.strike,
+                    rate,
+                    self.dividend_yield(underlying),
+                )
+            }
+        }
+    }
+
+    /// The volatility at which `option` is worth `price`, see
+    /// [`PricingConfig::greeks`]. Black-76 is Black-Scholes-Merton with the
+    /// yield at the rate, so one solver covers both models. Volatilities are
+    /// European under every model, the same as providers report them.
+    pub fn implied_volatility(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        price: f64,
+        time_to_expiry: f64,
+        underlying_price: f64,
+    ) -> Option<f64> {
+        let rate = self.rate(time_to_expiry);
+        let dividend_yield = match option.underlying() {
+            UnderlyingType::Future => rate,
+            UnderlyingType::Equity | UnderlyingType::Index => self.dividend_yield(underlying),
+        };
+
+        iv::implied_volatility(
+            option.option_type,
+            price,
+            time_to_expiry,
+            underlying_price,
+            option.strike,
+            rate,
+            dividend_yield,
+        )
+    }
+
+    /// Implied volatilities of the quotes of `option`.
+    pub fn quote_volatility(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        time_to_expiry: f64,
+        underlying_price: f64,
+    ) -> QuoteVolatility {
+        iv::quote_volatility(option.bid, option.ask, option.last, |price| {
+            self.implied_volatility(underlying, option, price, time_to_expiry, underlying_price)
+        })
+    }
+
+    /// `option` set up for evaluation across many underlying prices, see
+    /// [`PricingConfig::greeks`]. `None` under the American models, which
+    /// have no closed form to set up.
+    pub fn contract(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        sigma: f64,
+        time_to_expiry: f64,
+    ) -> Option<Contract> {
+        match (option.underlying(), self.spot_model(underlying, option)) {
+            (UnderlyingType::Future, _) | (_, PricingModel::BlackScholes) => {
+                Some(self.european_contract(underlying, option, sigma, time_to_expiry))
+            }
+            (_, PricingModel::Binomial | PricingModel::BjerksundStensland) => None,
+        }
+    }
+
+    /// The same as [`PricingConfig::contract`], but priced as European under
+    /// every model.
+    pub fn european_contract(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        sigma: f64,
+        time_to_expiry: f64,
+    ) -> Contract {
+        let rate = self.rate(time_to_expiry);
+        match option.underlying() {
+            UnderlyingType::Future => Contract::black76(
+                option.option_type,
+                sigma,
+                time_to_expiry,
+                option.strike,
+                rate,
+            ),
+            UnderlyingType::Equity | UnderlyingType::Index => Contract::new(
+                option.option_type,
+                sigma,
+                time_to_expiry,
+                option.strike,
+                rate,
+                self.dividend_yield(underlying),
+            ),
+        }
+    }
+
+    /// Gamma alone, see [`PricingConfig::greeks`].
+    pub fn gamma(
+        &self,
+        underlying: &str,
+        option: &OptionInfo,
+        sigma: f64,
+        time_to_expiry: f64,
+        underlying_price: f64,
+    ) -> f64 {
+        let rate = self.rate(time_to_expiry);
+        match option.underlying() {
+            UnderlyingType::Future => black76::gamma(
+                sigma,
+                time_to_expiry,
+                0.0,
+                underlying_price,
+                option.strike,
+                rate,
+            ),
+            UnderlyingType::Equity | UnderlyingType::Index => {
+                let dividend_yield = self.dividend_yield(underlying);
+                match self.spot_model(underlying, option) {
+                    PricingModel::BlackScholes => bs::gamma(
+                        sigma,
+                        time_to_expiry,
+                        0.0,
+                        underlying_price,
+                        option.strike,
+                        rate,
+                        dividend_yield,
+                    ),
+                    PricingModel::Binomial => american::binomial_gamma(
+                        option.option_type,
+                        sigma,
+                        time_to_expiry,
+                        underlying_price,
+                        option.strike,
+                        rate,
+                        dividend_yield,
+                    ),
+                    PricingModel::BjerksundStensland => american::bjerksund_stensland_gamma(
+                        option.option_type,
+                        sigma,
+                        time_to_expiry,
+                        underlying_price,
+                        option.strike,
+                        rate,
+                        dividend_yield,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Parses a list of `SYMBOL=value` entries separated by commas, with `what`
+/// naming the values in errors.
+fn parse_by_symbol<T: FromStr>(list: &str, what: &str) -> anyhow::Result<HashMap<String, T>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (symbol, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid {}: {}", what, entry))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid {}: {}", what, entry))?;
+            Ok((symbol.trim().to_uppercase(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OptionType;
+
+    #[test]
+    fn dividend_yields() {
+        let config = PricingConfig {
+            dividend_yields: parse_by_symbol("spy=0.013, QQQ=0.006,", "dividend yield").unwrap(),
+            ..PricingConfig::default()
+        };
+        assert_eq!(config.dividend_yield("SPY"), 0.013);
+        assert_eq!(config.dividend_yield("qqq"), 0.006);
+        assert_eq!(config.dividend_yield("TSLA"), 0.0);
+        assert_eq!(config.rate(1.0), 0.0);
+
+        assert!(parse_by_symbol::<f64>("SPY", "dividend yield").is_err());
+        assert!(parse_by_symbol::<f64>("SPY=high", "dividend yield").is_err());
+    }
+
+    #[test]
+    fn model_by_underlying() {
+        let config = PricingConfig {
+            rates: RateCurve::Flat(0.05),
+            dividend_yields: parse_by_symbol("/ES=0.5", "dividend yield").unwrap(),
+            ..PricingConfig::default()
+        };
+        let mut option = OptionInfo::test();
+        option.symbol = "/ES".to_string();
+        option.strike = 4200.0;
+        option.underlying_type = None;
+
+        // The dividend yield of a future is ignored since it carries none
+        assert_eq!(config.forward("/ES", &option, 0.25, 4250.0), 4250.0);
+        let gamma = config.gamma("/ES", &option, 0.2, 0.25, 4250.0);
+        assert_eq!(gamma, black76::gamma(0.2, 0.25, 0.0, 4250.0, 4200.0, 0.05));
+        assert_eq!(
+            config.greeks("/ES", &option, 0.2, 0.25, 4250.0).gamma,
+            gamma
+        );
+        assert_eq!(option.contract_multiplier().unwrap(), 100.0);
+
+        option.underlying_type = Some(UnderlyingType::Equity);
+        let forward = config.forward("/ES", &option, 0.25, 4250.0);
+        assert!((forward - 4250.0 * (-0.45f64 * 0.25).exp()).abs() < 1e-9);
+        assert_eq!(
+            config.gamma("/ES", &option, 0.2, 0.25, 4250.0),
+            bs::gamma(0.2, 0.25, 0.0, 4250.0, 4200.0, 0.05, 0.5)
+        );
+    }
+
+    #[test]
+    fn model_by_symbol() {
+        let config = PricingConfig {
+            rates: RateCurve::Flat(0.05),
+            models: parse_by_symbol("aapl=binomial, IWM=bs2002", "pricing model").unwrap(),
+            ..PricingConfig::default()
+        };
+        assert_eq!(config.model("AAPL"), PricingModel::Binomial);
+        assert_eq!(config.model("iwm"), PricingModel::BjerksundStensland);
+        assert_eq!(config.model("SPX"), PricingModel::BlackScholes);
+        assert!(parse_by_symbol::<PricingModel>("AAPL=trinomial", "pricing model").is_err());
+
+        let mut option = OptionInfo::test();
+        option.option_type = OptionType::Put;
+        option.strike = 150.0;
+        let (sigma, t, price) = (0.3, 0.5, 120.0);
+
+        let gamma = config.gamma("AAPL", &option, sigma, t, price);
+        assert_eq!(
+            gamma,
+            american::binomial_gamma(OptionType::Put, sigma, t, price, 150.0, 0.05, 0.0)
+        );
+        assert_eq!(config.greeks("AAPL", &option, sigma, t, price).gamma, gamma);
+        assert_eq!(
+            config.greeks("IWM", &option, sigma, t, price).delta,
+            american::bjerksund_stensland_greeks(
+                OptionType::Put,
+                sigma,
+                t,
+                price,
+                150.0,
+                0.05,
+                0.0
+            )
+            .delta
+        );
+        // Deep in the money the American put is exercised right away
+        assert!((config.greeks("AAPL", &option, sigma, t, 60.0).delta + 1.0).abs() < 1e-3);
+
+        assert!(config.contract("AAPL", &option, sigma, t).is_none());
+        let european = config.european_contract("AAPL", &option, sigma, t);
+        let vanna = bs::vanna(sigma, t, 0.0, price, 150.0, 0.05, 0.0);
+        assert!((european.vanna(price) - vanna).abs() < 1e-15);
+        let contract = config.contract("SPX", &option, sigma, t).unwrap();
+        assert!(
+            (contract.gamma(price) - config.gamma("SPX", &option, sigma, t, price)).abs() < 1e-12
+        );
+
+        // Index options stay European and futures options on Black-76
+        // whatever the model
+        let config = PricingConfig {
+            model: PricingModel::Binomial,
+            ..config
+        };
+        option.symbol = "SPX".to_string();
+        option.underlying_type = Some(UnderlyingType::Index);
+        assert_eq!(
+            config.gamma("SPX", &option, sigma, t, price),
+            bs::gamma(sigma, t, 0.0, price, 150.0, 0.05, 0.0)
+        );
+        assert_eq!(
+            config.greeks("SPX", &option, sigma, t, 60.0).delta,
+            bs::greeks(OptionType::Put, sigma, t, 60.0, 150.0, 0.05, 0.0).delta
+        );
+        assert!(config.contract("SPX", &option, sigma, t).is_some());
+
+        option.underlying_type = Some(UnderlyingType::Future);
+        assert_eq!(
+            config.gamma("/ES", &option, sigma, t, price),
+            black76::gamma(sigma, t, 0.0, price, 150.0, 0.05)
+        );
+    }
+}
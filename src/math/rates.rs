@@ -0,0 +1,134 @@
+//! Risk-free rates by time to maturity, either one configured flat rate or a
+//! term structure read from a local file.
+
+use std::{path::Path, str::FromStr};
+
+const DAYS_PER_YEAR: f64 = 365.0;
+
+/// Continuously compounded annual rates, as decimals.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RateCurve {
+    Flat(f64),
+    /// `(years, rate)` points sorted by maturity
+    TermStructure(Vec<(f64, f64)>),
+}
+
+impl Default for RateCurve {
+    fn default() -> Self {
+        RateCurve::Flat(0.0)
+    }
+}
+
+impl RateCurve {
+    pub fn term_structure(mut points: Vec<(f64, f64)>) -> anyhow::Result<Self> {
+        if points.is_empty() {
+            anyhow::bail!("A term structure needs at least one point");
+        }
+        if let Some((years, rate)) = points
+            .iter()
+            .find(|(years, rate)| !years.is_finite() || *years < 0.0 || !rate.is_finite())
+        {
+            anyhow::bail!("Invalid rate {} at {} years", rate, years);
+        }
+
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(RateCurve::TermStructure(points))
+    }
+
+    /// Reads a term structure from lines of `tenor,rate`. Tenors are either
+    /// years or a count of days, weeks, months or years such as `1W` or
+    /// `3M`. Blank lines, `#` comments and a header row are ignored.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        contents.parse()
+    }
+
+    /// The rate for a maturity of `years`, interpolated linearly between the
+    /// points of a term structure and flat beyond them.
+    pub fn rate(&self, years: f64) -> f64 {
+        let points = match self {
+            RateCurve::Flat(rate) => return *rate,
+            RateCurve::TermStructure(points) => points,
+        };
+
+        let after = points.partition_point(|(t, _)| *t < years);
+        match (after.checked_sub(1).map(|i| points[i]), points.get(after)) {
+            (Some((t0, r0)), Some(&(t1, r1))) => r0 + (r1 - r0) * (years - t0) / (t1 - t0),
+            (Some((_, rate)), None) | (None, Some(&(_, rate))) => rate,
+            (None, None) => 0.0,
+        }
+    }
+}
+
+impl FromStr for RateCurve {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (tenor, rate) = line
+                .split_once(',')
+                .ok_or_else(|| anyhow::anyhow!("Line {}: expected tenor,rate", i + 1))?;
+            let rate = match rate.trim().parse::<f64>() {
+                Ok(rate) => rate,
+                // A header row
+                Err(_) if points.is_empty() && parse_tenor(tenor.trim()).is_none() => continue,
+                Err(_) => anyhow::bail!("Line {}: invalid rate {}", i + 1, rate.trim()),
+            };
+            let years = parse_tenor(tenor.trim())
+                .ok_or_else(|| anyhow::anyhow!("Line {}: invalid tenor {}", i + 1, tenor.trim()))?;
+
+            points.push((years, rate));
+        }
+
+        RateCurve::term_structure(points)
+    }
+}
+
+fn parse_tenor(tenor: &str) -> Option<f64> {
+    if let Ok(years) = tenor.parse() {
+        return Some(years);
+    }
+
+    let unit = tenor.chars().last()?;
+    let count: f64 = tenor[..tenor.len() - unit.len_utf8()].trim().parse().ok()?;
+    let years = match unit.to_ascii_uppercase() {
+        'D' => 1.0 / DAYS_PER_YEAR,
+        'W' => 7.0 / DAYS_PER_YEAR,
+        'M' => 1.0 / 12.0,
+        'Y' => 1.0,
+        _ => return None,
+    };
+
+    Some(count * years)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(RateCurve::Flat(0.05).rate(10.0), 0.05);
+
+        let curve: RateCurve = "tenor,rate\n# Treasury bills\n1M,0.01\n\n6M,0.02\n2,0.03\n"
+            .parse()
+            .unwrap();
+        assert_eq!(curve.rate(0.0), 0.01);
+        assert!((curve.rate(0.5) - 0.02).abs() < 1e-12);
+        assert!((curve.rate(1.25) - 0.025).abs() < 1e-12);
+        assert_eq!(curve.rate(30.0), 0.03);
+
+        assert!("1M,0.01\n1X,0.02".parse::<RateCurve>().is_err());
+        assert!("1M,0.01\n3M,high".parse::<RateCurve>().is_err());
+        assert!("tenor,rate".parse::<RateCurve>().is_err());
+    }
+}
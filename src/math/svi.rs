@@ -0,0 +1,296 @@
+//! Smiles in the raw SVI form of Gatheral (2004), which gives total implied
+//! variance `w = sigma^2 * t` against log moneyness `k = ln(K / F)` as
+//! `w(k) = a + b * (rho * (k - m) + sqrt((k - m)^2 + sigma^2))`. With `m`
+//! and `sigma` held fixed the smile is linear in the other three, so the fit
+//! only searches over those two and solves for the rest, the quasi-explicit
+//! method of Zeliade (2009).
+
+use crate::types::SviParameters;
+
+/// A smile has five parameters to fit
+pub const MIN_POINTS: usize = 5;
+/// Lee's moment formula bounds how steep total variance gets in the wings
+const MAX_WING_SLOPE: f64 = 2.0;
+const MAX_RHO: f64 = 0.999;
+const MIN_SIGMA: f64 = 1e-4;
+const MAX_SIGMA: f64 = 10.0;
+const SEARCH_ITERATIONS: usize = 200;
+
+impl SviParameters {
+    pub fn total_variance(&self, log_moneyness: f64) -> f64 {
+        let y = log_moneyness - self.m;
+        self.a + self.b * (self.rho * y + (y * y + self.sigma * self.sigma).sqrt())
+    }
+
+    /// The first derivative of total variance by log moneyness.
+    pub fn slope(&self, log_moneyness: f64) -> f64 {
+        let y = log_moneyness - self.m;
+        self.b * (self.rho + y / (y * y + self.sigma * self.sigma).sqrt())
+    }
+
+    /// The second derivative of total variance by log moneyness.
+    pub fn curvature(&self, log_moneyness: f64) -> f64 {
+        let y = log_moneyness - self.m;
+        self.b * self.sigma.powi(2) / (y * y + self.sigma * self.sigma).powf(1.5)
+    }
+
+    /// The factor `g(k)` of Gatheral and Jacquier (2014), proportional to the
+    /// probability density the smile implies at `log_moneyness`. The smile
+    /// is free of butterfly arbitrage where it isn't negative.
+    pub fn density_factor(&self, log_moneyness: f64) -> f64 {
+        let k = log_moneyness;
+        let w = self.total_variance(k);
+        let slope = self.slope(k);
+        (1.0 - k * slope / (2.0 * w)).powi(2) - slope.powi(2) / 4.0 * (1.0 / w + 0.25)
+            + self.curvature(k) / 2.0
+    }
+}
+
+/// The smile closest to `points` of log moneyness and total variance in the
+/// least squares sense, or `None` with fewer than [`MIN_POINTS`]. The
+/// parameters are kept where total variance stays positive and the wings
+/// respect Lee's bound.
+pub fn calibrate(points: &[(f64, f64)]) -> Option<SviParameters> {
+    if points.len() < MIN_POINTS {
+        return None;
+    }
+    let (min_k, max_k) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (k, _)| {
+            (min.min(*k), max.max(*k))
+        });
+
+    // `sigma` is searched for by its logarithm to keep it positive
+    let fit = |x: [f64; 2]| linear_fit(points, x[0], x[1].exp().clamp(MIN_SIGMA, MAX_SIGMA));
+    let error = |x: [f64; 2]| fit(x).map_or(f64::INFINITY, |(_, error)| error);
+
+    // Start from the best of a coarse grid, since the error has local minima
+    let mut start = [0.0, 0.1f64.ln()];
+    let mut best = f64::INFINITY;
+    for i in 0..=8 {
+        let m = min_k + (max_k - min_k) * i as f64 / 8.0;
+        for sigma in [0.01f64, 0.03, 0.1, 0.3, 1.0] {
+            let x = [m, sigma.ln()];
+            let error = error(x);
+            if error < best {
+                best = error;
+                start = x;
+            }
+        }
+    }
+
+    let step = [((max_k - min_k) / 16.0).max(1e-3), 0.5];
+    fit(nelder_mead(error, start, step)).map(|(parameters, _)| parameters)
+}
+
+/// The best `a`, `b` and `rho` for `m` and `sigma`, with the sum of squared
+/// errors. Solves the normal equations of `w = a + c * y + d * z`, where
+/// `y = k - m` and `z = sqrt(y^2 + sigma^2)`, then brings `b = d` and
+/// `rho = c / d` into range and refits `a` to match.
+fn linear_fit(points: &[(f64, f64)], m: f64, sigma: f64) -> Option<(SviParameters, f64)> {
+    let mut normal = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for &(k, w) in points {
+        let y = k - m;
+        let row = [1.0, y, (y * y + sigma * sigma).sqrt()];
+        for (normal, x) in normal.iter_mut().zip(row) {
+            for (element, y) in normal.iter_mut().zip(row) {
+                *element += x * y;
+            }
+        }
+        for (rhs, x) in rhs.iter_mut().zip(row) {
+            *rhs += x * w;
+        }
+    }
+    let [_, c, d] = solve(normal, rhs)?;
+
+    let b = d.max(0.0);
+    let rho = if b > 0.0 {
+        (c / b).clamp(-MAX_RHO, MAX_RHO)
+    } else {
+        0.0
+    };
+    let b = b.min(MAX_WING_SLOPE / (1.0 + rho.abs()));
+    let mut parameters = SviParameters {
+        a: 0.0,
+        b,
+        rho,
+        m,
+        sigma,
+    };
+    let a = points
+        .iter()
+        .map(|&(k, w)| w - parameters.total_variance(k))
+        .sum::<f64>()
+        / points.len() as f64;
+    // The minimum of total variance is `a + b * sigma * sqrt(1 - rho^2)`
+    parameters.a = a.max(-b * sigma * (1.0 - rho * rho).sqrt());
+
+    let error = points
+        .iter()
+        .map(|&(k, w)| (parameters.total_variance(k) - w).powi(2))
+        .sum();
+    Some((parameters, error))
+}
+
+/// Solves `matrix * x = rhs` by Cramer's rule, or `None` when `matrix` is
+/// singular.
+fn solve(matrix: [[f64; 3]; 3], rhs: [f64; 3]) -> Option<[f64; 3]> {
+    let det = determinant(&matrix);
+    if !det.is_normal() {
+        return None;
+    }
+
+    let mut solution = [0.0; 3];
+    for (column, x) in solution.iter_mut().enumerate() {
+        let mut replaced = matrix;
+        for (row, value) in replaced.iter_mut().zip(rhs) {
+            row[column] = value;
+        }
+        *x = determinant(&replaced) / det;
+    }
+    Some(solution)
+}
+
+fn determinant(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Minimizes `f` with the Nelder-Mead simplex, starting from `start` and a
+/// simplex `step` wide.
+fn nelder_mead(f: impl Fn([f64; 2]) -> f64, start: [f64; 2], step: [f64; 2]) -> [f64; 2] {
+    let mut simplex = [
+        start,
+        [start[0] + step[0], start[1]],
+        [start[0], start[1] + step[1]],
+    ]
+    .map(|x| (x, f(x)));
+
+    for _ in 0..SEARCH_ITERATIONS {
+        simplex.sort_by(|(_, f1), (_, f2)| f1.total_cmp(f2));
+        let [best, middle, worst] = simplex;
+        let centroid = midpoint(best.0, middle.0);
+        // Points on the line from the centroid through the worst vertex
+        let along = |t: f64| {
+            [
+                centroid[0] + t * (worst.0[0] - centroid[0]),
+                centroid[1] + t * (worst.0[1] - centroid[1]),
+            ]
+        };
+
+        let reflected = along(-1.0);
+        let f_reflected = f(reflected);
+        if f_reflected < best.1 {
+            let expanded = along(-2.0);
+            let f_expanded = f(expanded);
+            simplex[2] = if f_expanded < f_reflected {
+                (expanded, f_expanded)
+            } else {
+                (reflected, f_reflected)
+            };
+        } else if f_reflected < middle.1 {
+            simplex[2] = (reflected, f_reflected);
+        } else {
+            let contracted = if f_reflected < worst.1 {
+                along(-0.5)
+            } else {
+                along(0.5)
+            };
+            let f_contracted = f(contracted);
+            if f_contracted < worst.1.min(f_reflected) {
+                simplex[2] = (contracted, f_contracted);
+            } else {
+                for vertex in &mut simplex[1..] {
+                    vertex.0 = midpoint(best.0, vertex.0);
+                    vertex.1 = f(vertex.0);
+                }
+            }
+        }
+    }
+
+    simplex
+        .iter()
+        .min_by(|(_, f1), (_, f2)| f1.total_cmp(f2))
+        .map(|(x, _)| *x)
+        .unwrap_or(start)
+}
+
+fn midpoint(x: [f64; 2], y: [f64; 2]) -> [f64; 2] {
+    [(x[0] + y[0]) / 2.0, (x[1] + y[1]) / 2.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_smile() {
+        let smile = SviParameters {
+            a: 0.01,
+            b: 0.1,
+            rho: -0.5,
+            m: 0.05,
+            sigma: 0.15,
+        };
+        let points: Vec<(f64, f64)> = (0..21)
+            .map(|i| -0.5 + i as f64 * 0.05)
+            .map(|k| (k, smile.total_variance(k)))
+            .collect();
+
+        let fitted = calibrate(&points).unwrap();
+        for (fitted, expected) in [
+            (fitted.a, smile.a),
+            (fitted.b, smile.b),
+            (fitted.rho, smile.rho),
+            (fitted.m, smile.m),
+            (fitted.sigma, smile.sigma),
+        ] {
+            assert!(
+                (fitted - expected).abs() < 1e-6,
+                "{} != {}",
+                fitted,
+                expected
+            );
+        }
+        assert!(calibrate(&points[..4]).is_none());
+
+        // Central differences of total variance
+        let h = 1e-4;
+        for k in [-0.3, 0.05, 0.4] {
+            let slope = (smile.total_variance(k + h) - smile.total_variance(k - h)) / (2.0 * h);
+            let curvature = (smile.total_variance(k + h) - 2.0 * smile.total_variance(k)
+                + smile.total_variance(k - h))
+                / h.powi(2);
+            assert!((smile.slope(k) - slope).abs() < 1e-8);
+            assert!((smile.curvature(k) - curvature).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn butterfly_arbitrage() {
+        let grid = (0..=300).map(|i| -1.5 + i as f64 * 0.01);
+        let smile = SviParameters {
+            a: 0.02,
+            b: 0.1,
+            rho: -0.4,
+            m: 0.05,
+            sigma: 0.2,
+        };
+        assert!(grid.clone().all(|k| smile.density_factor(k) > 0.0));
+
+        // Axel Vogt's smile from Gatheral and Jacquier (2014)
+        let vogt = SviParameters {
+            a: -0.0410,
+            b: 0.1331,
+            rho: 0.3060,
+            m: 0.3586,
+            sigma: 0.4153,
+        };
+        let worst = grid
+            .map(|k| vogt.density_factor(k))
+            .fold(f64::MAX, f64::min);
+        assert!((worst + 0.0329).abs() < 1e-3);
+    }
+}
@@ -1,12 +1,26 @@
 pub mod clock;
+pub mod distribution;
 pub mod gex;
 pub mod ohlc;
 pub mod options;
 pub mod quote;
+pub mod snapshot;
 pub mod stats;
+pub mod surface;
+pub mod underlying;
+pub mod vex;
+pub mod volatility;
 
 pub use clock::Clock;
+pub use distribution::{DensityPoint, ImpliedDistribution, Percentile};
 pub use gex::{GammaExposure, GammaExposureStats};
 pub use ohlc::{Ohlc, OhlcInterval};
-pub use options::{Greeks, OptionInfo, OptionType};
+#[cfg(test)]
+pub use options::at;
+pub use options::{Greeks, OptionInfo, OptionType, SettlementType};
 pub use quote::Quote;
+pub use snapshot::OptionSnapshot;
+pub use surface::{Arbitrage, ArbitrageKind, SurfacePoint, SviParameters, VolSlice, VolSurface};
+pub use underlying::UnderlyingType;
+pub use vex::{VannaExposure, VannaExposureStats};
+pub use volatility::ImpliedVolatility;
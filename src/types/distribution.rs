@@ -0,0 +1,48 @@
+use async_graphql::{ComplexObject, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+/// The risk-neutral distribution of the underlying price at one expiration,
+/// as implied by the prices of its options.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct ImpliedDistribution {
+    pub expiration_date: String,
+    /// In years
+    pub time_to_expiry: f64,
+    pub forward: f64,
+    /// The mean distance between the price at expiration and the forward,
+    /// which is the undiscounted price of the straddle struck at the forward
+    pub expected_move: f64,
+    pub percentiles: Vec<Percentile>,
+    /// Ordered by price
+    pub density: Vec<DensityPoint>,
+}
+
+#[ComplexObject]
+impl ImpliedDistribution {
+    /// The probability of the underlying finishing below `level`.
+    async fn probability_below(&self, level: f64) -> f64 {
+        self.cumulative_probability(level)
+    }
+
+    /// The probability of the underlying finishing above `level`.
+    async fn probability_above(&self, level: f64) -> f64 {
+        1.0 - self.cumulative_probability(level)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct DensityPoint {
+    pub price: f64,
+    /// Probability per unit of price
+    pub density: f64,
+    /// The probability of finishing below `price`
+    pub probability_below: f64,
+}
+
+/// The price the underlying finishes below with `probability`.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct Percentile {
+    pub probability: f64,
+    pub price: f64,
+}
@@ -1,8 +1,15 @@
 use std::str::FromStr;
 
 use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::{underlying::STANDARD_MULTIPLIER, UnderlyingType};
+use crate::{
+    data_apis::DataError,
+    math::expiry::{self, DayCount},
+};
+
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 pub struct OptionInfo {
     pub timestamp: String,
@@ -23,9 +30,48 @@ pub struct OptionInfo {
     pub mid_iv: Option<f64>,
     pub ask_iv: Option<f64>,
     pub smv_vol: Option<f64>,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    pub multiplier: Option<f64>,
+    pub settlement_type: Option<SettlementType>,
+    pub days_to_expiration: Option<i64>,
+    pub underlying_type: Option<UnderlyingType>,
 }
 
 impl OptionInfo {
+    /// Years from `now` until the option settles.
+    pub fn time_to_expiry(&self, now: DateTime<Utc>, day_count: DayCount) -> anyhow::Result<f64> {
+        expiry::time_to_expiry(
+            &self.symbol,
+            &self.expiration_date,
+            self.settlement_type,
+            now,
+            day_count,
+        )
+    }
+
+    /// The recorded underlying type, or else one inferred from the symbol.
+    pub fn underlying(&self) -> UnderlyingType {
+        self.underlying_type
+            .unwrap_or_else(|| UnderlyingType::infer(&self.symbol))
+    }
+
+    /// Units of the underlying one contract controls. Fails for futures
+    /// with an unknown root that didn't come with a multiplier.
+    pub fn contract_multiplier(&self) -> anyhow::Result<f64> {
+        self.multiplier
+            .or_else(|| self.underlying().default_multiplier(&self.symbol))
+            .ok_or_else(|| {
+                DataError::MissingData(format!("No contract multiplier for {}", self.symbol)).into()
+            })
+    }
+
+    /// Open interest in standard 100 share contracts, which is what exposures
+    /// are measured in. Only futures contracts differ from their count.
+    pub fn standard_contracts(&self) -> anyhow::Result<f64> {
+        Ok(self.open_interest as f64 * self.contract_multiplier()? / STANDARD_MULTIPLIER)
+    }
+
     pub fn delta(&self) -> f64 {
         match &self.greeks {
             Some(g) => g.delta,
@@ -74,9 +120,51 @@ impl OptionInfo {
             None => 0.0,
         }
     }
+
+    pub fn speed(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.speed,
+            None => 0.0,
+        }
+    }
+
+    pub fn zomma(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.zomma,
+            None => 0.0,
+        }
+    }
+
+    pub fn color(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.color,
+            None => 0.0,
+        }
+    }
+
+    pub fn vomma(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.vomma,
+            None => 0.0,
+        }
+    }
+
+    pub fn veta(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.veta,
+            None => 0.0,
+        }
+    }
+
+    pub fn ultima(&self) -> f64 {
+        match &self.greeks {
+            Some(g) => g.ultima,
+            None => 0.0,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, SimpleObject)]
 pub struct Greeks {
     pub delta: f64,
     pub gamma: f64,
@@ -85,6 +173,12 @@ pub struct Greeks {
     pub rho: f64,
     pub vanna: f64,
     pub charm: f64,
+    pub speed: f64,
+    pub zomma: f64,
+    pub color: f64,
+    pub vomma: f64,
+    pub veta: f64,
+    pub ultima: f64,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
@@ -93,6 +187,26 @@ pub enum OptionType {
     Put,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum SettlementType {
+    /// Settled on the opening price of the expiration day
+    AM,
+    /// Settled on the closing price of the expiration day
+    PM,
+}
+
+impl FromStr for SettlementType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "am" | "a" => SettlementType::AM,
+            "pm" | "p" => SettlementType::PM,
+            _ => anyhow::bail!("Invalid settlement type: {}", s),
+        })
+    }
+}
+
 impl FromStr for OptionType {
     type Err = anyhow::Error;
 
@@ -124,6 +238,12 @@ impl OptionInfo {
                 rho: 14.0,
                 vanna: 15.0,
                 charm: 16.0,
+                speed: 19.0,
+                zomma: 20.0,
+                color: 21.0,
+                vomma: 22.0,
+                veta: 23.0,
+                ultima: 24.0,
             }),
             last: Some(3.0),
             change: Some(4.0),
@@ -135,6 +255,20 @@ impl OptionInfo {
             mid_iv: Some(18.0),
             ask_iv: Some(16.0),
             smv_vol: Some(17.0),
+            bid: Some(2.9),
+            ask: Some(3.1),
+            multiplier: Some(100.0),
+            settlement_type: Some(SettlementType::PM),
+            days_to_expiration: Some(1),
+            underlying_type: Some(UnderlyingType::Equity),
         }
     }
 }
+
+/// Parses an RFC 3339 time, for tests.
+#[cfg(test)]
+pub fn at(time: &str) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .unwrap()
+        .with_timezone(&chrono::Utc)
+}
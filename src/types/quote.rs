@@ -11,4 +11,24 @@ pub struct Quote {
     pub high: Option<f64>,
     pub low: Option<f64>,
     pub close: Option<f64>,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+}
+
+#[cfg(test)]
+impl Quote {
+    pub fn test() -> Self {
+        Self {
+            symbol: "TST".to_string(),
+            last: Some(10.0),
+            change: Some(0.5),
+            volume: 1000,
+            open: Some(9.5),
+            high: Some(10.5),
+            low: Some(9.0),
+            close: None,
+            bid: Some(9.95),
+            ask: Some(10.05),
+        }
+    }
 }
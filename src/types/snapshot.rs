@@ -0,0 +1,62 @@
+use async_graphql::{ComplexObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{OptionInfo, Quote};
+
+/// An option chain as captured at one point in time, along with the quote of
+/// the underlying the greeks were computed against.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct OptionSnapshot {
+    #[graphql(skip)]
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    /// Unknown for snapshots collected before the provider was recorded
+    pub provider: Option<String>,
+    pub quote: Option<Quote>,
+    pub options: Vec<OptionInfo>,
+}
+
+#[ComplexObject]
+impl OptionSnapshot {
+    async fn timestamp(&self) -> String {
+        self.timestamp.to_rfc3339()
+    }
+}
+
+impl OptionSnapshot {
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        symbol: &str,
+        provider: &str,
+        quote: Option<Quote>,
+        options: Vec<OptionInfo>,
+    ) -> Self {
+        Self {
+            timestamp,
+            symbol: symbol.to_uppercase(),
+            provider: Some(provider.to_string()),
+            quote,
+            options,
+        }
+    }
+
+    /// The price of the underlying when the snapshot was taken.
+    pub fn spot(&self) -> Option<f64> {
+        self.quote.as_ref().and_then(|q| q.last)
+    }
+}
+
+#[cfg(test)]
+impl OptionSnapshot {
+    pub fn test() -> Self {
+        Self::new(
+            Utc::now(),
+            "TST",
+            "test",
+            Some(Quote::test()),
+            vec![OptionInfo::test()],
+        )
+    }
+}
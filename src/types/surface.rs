@@ -0,0 +1,86 @@
+use async_graphql::{Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+use super::OptionType;
+
+/// Implied volatilities of a chain across strikes and expirations, with a
+/// smile fitted to every expiration that has enough strikes.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct VolSurface {
+    pub timestamp: String,
+    pub symbol: String,
+    /// The underlying price the surface was built against
+    pub spot: f64,
+    /// Ordered by time to expiry
+    pub slices: Vec<VolSlice>,
+    /// Empty when the fitted smiles are free of static arbitrage
+    pub arbitrage: Vec<Arbitrage>,
+}
+
+/// One expiration of a [`VolSurface`].
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct VolSlice {
+    pub expiration_date: String,
+    /// In years
+    pub time_to_expiry: f64,
+    pub forward: f64,
+    pub parameters: SviParameters,
+    /// Root-mean-square difference between the market and fitted volatilities
+    pub fit_error: f64,
+    /// Ordered by strike
+    pub points: Vec<SurfacePoint>,
+}
+
+/// The volatility at one strike, taken from the option out of the money.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct SurfacePoint {
+    pub strike: f64,
+    pub option_type: OptionType,
+    /// `ln(strike / forward)`
+    pub log_moneyness: f64,
+    /// Undiscounted delta of a call at the fitted volatility
+    pub delta: f64,
+    /// The volatility reported for the option
+    pub market_volatility: f64,
+    /// The volatility of the fitted smile
+    pub volatility: f64,
+}
+
+/// Raw SVI parameters of a smile in total implied variance against log
+/// moneyness, see [`crate::math::svi`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct SviParameters {
+    /// Overall level of variance
+    pub a: f64,
+    /// Slope of the wings
+    pub b: f64,
+    /// Skew, from -1 to 1
+    pub rho: f64,
+    /// Log moneyness the smile is centered on
+    pub m: f64,
+    /// Curvature at the center
+    pub sigma: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum ArbitrageKind {
+    /// A smile implying a negative probability density, so some butterfly
+    /// spread would cost less than nothing
+    Butterfly,
+    /// Total variance falling from one expiration to the next at the same
+    /// moneyness, so some calendar spread would cost less than nothing
+    Calendar,
+}
+
+/// The worst point of one arbitrage in a [`VolSurface`].
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct Arbitrage {
+    pub kind: ArbitrageKind,
+    /// The expiration with the arbitrage, or the later of the two for
+    /// calendar arbitrage
+    pub expiration_date: String,
+    pub log_moneyness: f64,
+    /// How far below zero the density factor of Gatheral and Jacquier falls
+    /// for butterflies, or how far total variance falls for calendars
+    pub amount: f64,
+}
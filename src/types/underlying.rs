@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use async_graphql::Enum;
+use serde::{Deserialize, Serialize};
+
+/// Shares per contract of standard equity and index options
+pub const STANDARD_MULTIPLIER: f64 = 100.0;
+
+/// Cash-settled index roots, including the weekly and PM-settled variants.
+const INDEX_ROOTS: &[&str] = &[
+    "SPX", "SPXW", "NDX", "NDXP", "RUT", "RUTW", "VIX", "VIXW", "DJX", "XSP", "OEX", "XEO",
+];
+
+/// Units of the underlying future one option controls, by futures root.
+const FUTURES_MULTIPLIERS: &[(&str, f64)] = &[
+    ("ES", 50.0),
+    ("MES", 5.0),
+    ("NQ", 20.0),
+    ("MNQ", 2.0),
+    ("RTY", 50.0),
+    ("M2K", 5.0),
+    ("YM", 5.0),
+    ("MYM", 0.5),
+    ("CL", 1000.0),
+    ("MCL", 100.0),
+    ("NG", 10000.0),
+    ("GC", 100.0),
+    ("MGC", 10.0),
+    ("SI", 5000.0),
+    ("ZB", 1000.0),
+    ("ZN", 1000.0),
+    ("ZF", 1000.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum UnderlyingType {
+    Equity,
+    Index,
+    /// Options on futures, priced off the futures price with Black-76
+    Future,
+}
+
+impl UnderlyingType {
+    /// Guesses the underlying of options on `symbol`. Futures symbols start
+    /// with a slash, like `/ES` or `/CLZ21`.
+    pub fn infer(symbol: &str) -> Self {
+        let symbol = symbol.trim();
+        if symbol.starts_with('/') {
+            return UnderlyingType::Future;
+        }
+
+        // TD writes indices as `$SPX.X`, Yahoo and CBOE as `^SPX`
+        let root = symbol
+            .trim_start_matches(['$', '^'])
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .to_uppercase();
+        if INDEX_ROOTS.contains(&root.as_ref()) {
+            UnderlyingType::Index
+        } else {
+            UnderlyingType::Equity
+        }
+    }
+
+    /// The contract multiplier of options on `symbol`, for when the provider
+    /// doesn't report one.
+    pub fn default_multiplier(&self, symbol: &str) -> Option<f64> {
+        match self {
+            UnderlyingType::Equity | UnderlyingType::Index => Some(STANDARD_MULTIPLIER),
+            UnderlyingType::Future => futures_multiplier(symbol),
+        }
+    }
+}
+
+impl FromStr for UnderlyingType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "equity" | "stock" | "etf" => UnderlyingType::Equity,
+            "index" => UnderlyingType::Index,
+            "future" | "futures" => UnderlyingType::Future,
+            _ => anyhow::bail!("Invalid underlying type: {}", s),
+        })
+    }
+}
+
+/// The multiplier of a futures option on `symbol`, matching the longest
+/// known root so `/MES` isn't taken for `/ES`.
+pub fn futures_multiplier(symbol: &str) -> Option<f64> {
+    let root = symbol.trim().trim_start_matches('/').to_uppercase();
+    FUTURES_MULTIPLIERS
+        .iter()
+        .filter(|(prefix, _)| root.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, multiplier)| *multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_apis::DataError, types::OptionInfo};
+
+    #[test]
+    fn infer() {
+        assert_eq!(UnderlyingType::infer("SPY"), UnderlyingType::Equity);
+        assert_eq!(UnderlyingType::infer("spxw"), UnderlyingType::Index);
+        assert_eq!(UnderlyingType::infer("$SPX.X"), UnderlyingType::Index);
+        assert_eq!(UnderlyingType::infer("/ES"), UnderlyingType::Future);
+
+        assert_eq!(futures_multiplier("/ES"), Some(50.0));
+        assert_eq!(futures_multiplier("/MESZ21"), Some(5.0));
+        assert_eq!(futures_multiplier("/nq"), Some(20.0));
+        assert_eq!(futures_multiplier("/CL"), Some(1000.0));
+        assert_eq!(futures_multiplier("/XX"), None);
+        assert_eq!(
+            UnderlyingType::Equity.default_multiplier("SPY"),
+            Some(STANDARD_MULTIPLIER)
+        );
+    }
+
+    #[test]
+    fn standard_contracts() {
+        let mut option = OptionInfo::test();
+        assert_eq!(option.standard_contracts().unwrap(), 2.0);
+
+        option.symbol = "/ES".to_string();
+        option.underlying_type = Some(UnderlyingType::Future);
+        option.multiplier = None;
+        assert_eq!(option.standard_contracts().unwrap(), 1.0);
+
+        option.symbol = "/XX".to_string();
+        let error = option.standard_contracts().unwrap_err();
+        assert!(matches!(
+            DataError::find(&error),
+            Some(DataError::MissingData(_))
+        ));
+        option.multiplier = Some(10.0);
+        assert_eq!(option.standard_contracts().unwrap(), 0.2);
+    }
+}
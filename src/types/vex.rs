@@ -0,0 +1,33 @@
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct VannaExposure {
+    pub strike: String,
+    /// Change in dealer delta, in units of the underlying, for a rise of
+    /// one volatility point
+    pub vanna_exposure: f64,
+}
+
+/// Vanna exposure across prices with every volatility shifted by `iv_shock`,
+/// summarized the same way as [`super::GammaExposureStats`].
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct VannaExposureStats {
+    pub timestamp: String,
+    pub symbol: String,
+    /// Added to every option's volatility, so 0.05 is five volatility points
+    pub iv_shock: f64,
+    pub prices: Vec<VannaExposure>,
+    pub average_absolute_exposure: f64,
+    pub average_positive_exposure: f64,
+    pub average_negative_exposure: f64,
+    pub maximum_vanna_exposure: f64,
+    pub minimum_vanna_exposure: f64,
+    pub absolute_maximum: f64,
+    pub absolute_minimum: f64,
+    pub weighted_average_absolute_price: f64,
+    pub weighted_average_positive_price: f64,
+    pub weighted_average_negative_price: f64,
+    pub absolute_maximum_price: f64,
+    pub absolute_minimum_price: f64,
+}
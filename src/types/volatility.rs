@@ -0,0 +1,18 @@
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+
+use super::OptionType;
+
+/// Volatilities implied by the quotes of one option, next to the one the
+/// provider reported.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct ImpliedVolatility {
+    pub symbol: String,
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub expiration_date: String,
+    pub bid_iv: Option<f64>,
+    pub mid_iv: Option<f64>,
+    pub ask_iv: Option<f64>,
+    pub reported_mid_iv: Option<f64>,
+}
@@ -39,3 +39,11 @@ where
         ))),
     }
 }
+
+/// Runs `f` on the blocking thread pool, so heavy encoding, parsing or
+/// number crunching doesn't stall the async runtime.
+pub async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(f).await?
+}